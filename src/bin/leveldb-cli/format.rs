@@ -0,0 +1,85 @@
+//! Parsing and printing keys and values in the formats operators use.
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use clap::ValueEnum;
+
+/// How keys or values are written on the command line and printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// UTF-8 text; backslashes and bytes that aren't printable are written
+    /// as `\\` and `\xNN`
+    Utf8,
+    /// hexadecimal digits
+    Hex,
+    /// standard base64
+    Base64,
+}
+
+/// decode the escapes `display` writes for `Format::Utf8`.
+fn unescape(input: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid escape in {:?}", input);
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut rest = input.bytes();
+    while let Some(b) = rest.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        let unescaped = match rest.next().ok_or_else(invalid)? {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            c @ (b'\\' | b'\'' | b'"') => c,
+            b'x' => {
+                let digits: Vec<u8> = rest.by_ref().take(2).collect();
+                if digits.len() != 2 || !digits.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(invalid());
+                }
+                let digits = std::str::from_utf8(&digits).map_err(|_| invalid())?;
+                u8::from_str_radix(digits, 16).map_err(|_| invalid())?
+            }
+            _ => return Err(invalid()),
+        };
+        bytes.push(unescaped);
+    }
+    Ok(bytes)
+}
+
+impl Format {
+    /// parse bytes given on the command line.
+    pub fn parse(self, input: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Utf8 => unescape(input),
+            Format::Hex => {
+                hex::decode(input).map_err(|e| format!("invalid hex {:?}: {}", input, e))
+            }
+            Format::Base64 => STANDARD
+                .decode(input)
+                .map_err(|e| format!("invalid base64 {:?}: {}", input, e)),
+        }
+    }
+
+    /// print bytes for display.
+    pub fn display(self, bytes: &[u8]) -> String {
+        match self {
+            Format::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(text) => {
+                    let mut shown = String::with_capacity(text.len());
+                    for c in text.chars() {
+                        if c == '\\' || c.is_control() {
+                            let mut buf = [0; 4];
+                            let escaped = c.encode_utf8(&mut buf).as_bytes().escape_ascii();
+                            shown.extend(escaped.map(char::from));
+                        } else {
+                            shown.push(c);
+                        }
+                    }
+                    shown
+                }
+                Err(_) => bytes.escape_ascii().to_string(),
+            },
+            Format::Hex => hex::encode(bytes),
+            Format::Base64 => STANDARD.encode(bytes),
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! `leveldb-cli`, a tool to inspect and maintain leveldb databases.
+//!
+//! Run `leveldb-cli --help` for the list of commands.
+mod format;
+
+use clap::{Args, Parser, Subcommand};
+use format::Format;
+use leveldb::database::Database;
+use leveldb::format::manifest::{current_manifest, FileMetaData, Manifest};
+use leveldb::format::{InternalKey, ValueType};
+use leveldb::management::{self, Corruption};
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+
+use std::error::Error;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::process;
+
+#[derive(Parser)]
+#[command(
+    name = "leveldb-cli",
+    version,
+    about = "Inspect and maintain leveldb databases"
+)]
+struct Cli {
+    /// the database directory
+    path: PathBuf,
+    /// create the database if it does not exist
+    #[arg(long)]
+    create_if_missing: bool,
+    /// how keys are given and printed
+    #[arg(long, short, value_enum, default_value = "utf8")]
+    key_format: Format,
+    /// how values are given and printed
+    #[arg(long, short, value_enum, default_value = "utf8")]
+    value_format: Format,
+    /// sync writes to disk before returning
+    #[arg(long)]
+    sync: bool,
+    /// open a database created with ttl, stripping expiry headers and
+    /// hiding expired values
+    #[arg(long)]
+    ttl: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Range {
+    /// only keys starting with this prefix
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    prefix: Option<String>,
+    /// only keys from this one on
+    #[arg(long)]
+    from: Option<String>,
+    /// only keys before this one
+    #[arg(long)]
+    to: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// print the value of a key
+    Get { key: String },
+    /// set a key to a value
+    Put { key: String, value: String },
+    /// delete a key
+    Delete { key: String },
+    /// print keys and values in order
+    Scan {
+        #[command(flatten)]
+        range: Range,
+        /// stop after this many entries
+        #[arg(long)]
+        limit: Option<usize>,
+        /// only print keys
+        #[arg(long)]
+        keys_only: bool,
+    },
+    /// count the keys
+    Count {
+        #[command(flatten)]
+        range: Range,
+    },
+    /// print leveldb's statistics, or a single property
+    Stats {
+        /// a property like leveldb.sstables
+        #[arg(long)]
+        property: Option<String>,
+    },
+    /// compact the database, or a range of it
+    Compact {
+        /// the first key to compact
+        #[arg(long)]
+        from: Option<String>,
+        /// the last key to compact
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// check a closed database for damage, without modifying it
+    Verify,
+    /// try to recover as much data as possible from a corrupted database
+    Repair,
+    /// copy the data still readable from a damaged database into a new one
+    Salvage {
+        /// the new database to create
+        #[arg(long)]
+        into: PathBuf,
+    },
+    /// delete the database
+    Destroy {
+        /// really delete it
+        #[arg(long)]
+        yes: bool,
+    },
+    /// print the approximate size on disk of a key range
+    ApproxSize {
+        /// the first key of the range
+        #[arg(long, default_value = "")]
+        from: String,
+        /// the key after the range
+        #[arg(long)]
+        to: String,
+    },
+    /// inspect the MANIFEST, without opening the database
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ManifestCommand {
+    /// print the version edits and the resulting tables of every level
+    Dump {
+        /// a MANIFEST to read instead of the one named by CURRENT
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// only print the resulting tables
+        #[arg(long)]
+        summary: bool,
+    },
+}
+
+/// The bounds of a key range, parsed.
+struct Bounds {
+    start: Vec<u8>,
+    prefix: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+}
+
+impl Bounds {
+    fn parse(range: &Range, format: Format) -> Result<Bounds, String> {
+        let parse = |key: &Option<String>| key.as_ref().map(|key| format.parse(key)).transpose();
+        let prefix = parse(&range.prefix)?;
+        Ok(Bounds {
+            start: prefix.clone().or(parse(&range.from)?).unwrap_or_default(),
+            prefix,
+            end: parse(&range.to)?,
+        })
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        if let Some(ref prefix) = self.prefix {
+            return key.starts_with(prefix);
+        }
+        match self.end {
+            Some(ref end) => key < &end[..],
+            None => true,
+        }
+    }
+}
+
+/// call `f` with the entries in `bounds`, until it returns false.
+fn each_entry<F>(database: &Database, bounds: &Bounds, mut f: F)
+where
+    F: FnMut(&[u8], &[u8]) -> bool,
+{
+    let mut iter = database.iter(&ReadOptions::default());
+    iter.seek(&bounds.start);
+    while iter.valid() && bounds.contains(iter.key()) {
+        if !f(iter.key(), iter.value()) {
+            break;
+        }
+        iter.next();
+    }
+}
+
+fn internal_key(key: &InternalKey, format: Format) -> String {
+    let kind = match key.value_type {
+        ValueType::Value => "put",
+        ValueType::Deletion => "del",
+    };
+    format!(
+        "{} @ {} : {}",
+        format.display(&key.user_key),
+        key.sequence,
+        kind
+    )
+}
+
+fn key_range(start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>, format: Format) -> String {
+    let start = match *start {
+        Bound::Included(ref key) => format!("[{}", format.display(key)),
+        Bound::Excluded(ref key) => format!("({}", format.display(key)),
+        Bound::Unbounded => "(*".to_string(),
+    };
+    let end = match *end {
+        Bound::Included(ref key) => format!("{}]", format.display(key)),
+        Bound::Excluded(ref key) => format!("{})", format.display(key)),
+        Bound::Unbounded => "*)".to_string(),
+    };
+    format!("{} .. {}", start, end)
+}
+
+fn print_corruptions(corruptions: &[Corruption], format: Format) {
+    for corruption in corruptions {
+        match corruption.keys {
+            Some((ref start, ref end)) => println!(
+                "{}: {}, affecting keys {}",
+                corruption.file,
+                corruption.reason,
+                key_range(start, end, format)
+            ),
+            None => println!("{}: {}", corruption.file, corruption.reason),
+        }
+    }
+}
+
+fn print_table(file: &FileMetaData, format: Format) {
+    println!(
+        "    #{} {} bytes [{} .. {}]",
+        file.number,
+        file.size,
+        internal_key(&file.smallest, format),
+        internal_key(&file.largest, format)
+    );
+}
+
+fn dump_manifest(path: &Path, summary: bool, format: Format) -> Result<(), Box<dyn Error>> {
+    let manifest = Manifest::open(path)?;
+    println!("{}", path.display());
+    if !summary {
+        for (i, edit) in manifest.edits.iter().enumerate() {
+            println!("edit {}:", i + 1);
+            if let Some(ref comparator) = edit.comparator {
+                println!("  comparator {}", comparator);
+            }
+            if let Some(log_number) = edit.log_number {
+                println!("  log number {}", log_number);
+            }
+            if let Some(prev_log_number) = edit.prev_log_number {
+                println!("  previous log number {}", prev_log_number);
+            }
+            if let Some(next_file_number) = edit.next_file_number {
+                println!("  next file number {}", next_file_number);
+            }
+            if let Some(last_sequence) = edit.last_sequence {
+                println!("  last sequence {}", last_sequence);
+            }
+            for (level, key) in &edit.compact_pointers {
+                println!(
+                    "  compact pointer level {}: {}",
+                    level,
+                    internal_key(key, format)
+                );
+            }
+            for (level, number) in &edit.deleted_files {
+                println!("  delete level {} #{}", level, number);
+            }
+            for (level, file) in &edit.new_files {
+                println!("  add level {}:", level);
+                print_table(file, format);
+            }
+        }
+    }
+    for corruption in &manifest.corruptions {
+        println!("skipped damaged record: {}", corruption);
+    }
+
+    let version = &manifest.version;
+    println!("current version:");
+    if let Some(ref comparator) = version.comparator {
+        println!("  comparator {}", comparator);
+    }
+    println!(
+        "  log number {}, previous log number {}, next file number {}, last sequence {}",
+        version.log_number,
+        version.prev_log_number,
+        version.next_file_number,
+        version.last_sequence
+    );
+    for (level, files) in version.levels.iter().enumerate() {
+        let size: u64 = files.iter().map(|file| file.size).sum();
+        println!("  level {}: {} tables, {} bytes", level, files.len(), size);
+        for file in files {
+            print_table(file, format);
+        }
+    }
+    Ok(())
+}
+
+fn open(cli: &Cli) -> Result<Database, Box<dyn Error>> {
+    let options = Options {
+        create_if_missing: cli.create_if_missing,
+        ttl: cli.ttl,
+        ..Options::default()
+    };
+    Ok(Database::open(&cli.path, options)?)
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let write_options = WriteOptions { sync: cli.sync };
+    match cli.command {
+        Command::Get { ref key } => {
+            let database = open(&cli)?;
+            let key = cli.key_format.parse(key)?;
+            match database.get(&ReadOptions::default(), &key)? {
+                Some(value) => println!("{}", cli.value_format.display(&value)),
+                None => {
+                    return Err(format!("key not found: {}", cli.key_format.display(&key)).into())
+                }
+            }
+        }
+        Command::Put { ref key, ref value } => {
+            let database = open(&cli)?;
+            let key = cli.key_format.parse(key)?;
+            let value = cli.value_format.parse(value)?;
+            database.put(&write_options, &key, &value)?;
+        }
+        Command::Delete { ref key } => {
+            let database = open(&cli)?;
+            let key = cli.key_format.parse(key)?;
+            database.delete(&write_options, &key)?;
+        }
+        Command::Scan {
+            ref range,
+            limit,
+            keys_only,
+        } => {
+            let database = open(&cli)?;
+            let bounds = Bounds::parse(range, cli.key_format)?;
+            let mut left = limit.unwrap_or(usize::MAX);
+            each_entry(&database, &bounds, |key, value| {
+                if left == 0 {
+                    return false;
+                }
+                left -= 1;
+                if keys_only {
+                    println!("{}", cli.key_format.display(key));
+                } else {
+                    println!(
+                        "{}\t{}",
+                        cli.key_format.display(key),
+                        cli.value_format.display(value)
+                    );
+                }
+                true
+            });
+        }
+        Command::Count { ref range } => {
+            let database = open(&cli)?;
+            let bounds = Bounds::parse(range, cli.key_format)?;
+            let mut count = 0u64;
+            each_entry(&database, &bounds, |_, _| {
+                count += 1;
+                true
+            });
+            println!("{}", count);
+        }
+        Command::Stats { ref property } => {
+            let database = open(&cli)?;
+            match *property {
+                Some(ref property) => match database.property(property) {
+                    Some(value) => println!("{}", value.trim_end()),
+                    None => return Err(format!("unknown property: {}", property).into()),
+                },
+                None => {
+                    for level in 0..7 {
+                        let property = format!("leveldb.num-files-at-level{}", level);
+                        if let Some(value) = database.property(&property) {
+                            println!("{}: {}", property, value);
+                        }
+                    }
+                    for property in &["leveldb.stats", "leveldb.sstables"] {
+                        if let Some(value) = database.property(property) {
+                            println!("{}:\n{}", property, value.trim_end());
+                        }
+                    }
+                }
+            }
+        }
+        Command::Compact { ref from, ref to } => {
+            let database = open(&cli)?;
+            let parse = |key: &Option<String>| {
+                key.as_ref()
+                    .map(|key| cli.key_format.parse(key))
+                    .transpose()
+            };
+            let (from, to) = (parse(from)?, parse(to)?);
+            database.compact_range(from.as_deref(), to.as_deref());
+        }
+        Command::Verify => {
+            let report = management::verify(&cli.path)?;
+            println!(
+                "{} tables with {} entries, {} log records",
+                report.tables, report.entries, report.log_records
+            );
+            for file in &report.missing_tables {
+                println!("missing table:");
+                print_table(file, cli.key_format);
+            }
+            for number in &report.orphaned_tables {
+                println!("orphaned table #{}", number);
+            }
+            print_corruptions(&report.corruptions, cli.key_format);
+            for warning in &report.warnings {
+                println!("warning: {}: {}", warning.file, warning.reason);
+            }
+            if !report.is_ok() {
+                return Err("the database is damaged".into());
+            }
+        }
+        Command::Repair => management::repair(&cli.path, &Options::default())?,
+        Command::Salvage { ref into } => {
+            let options = Options {
+                create_if_missing: true,
+                error_if_exists: true,
+                ttl: cli.ttl,
+                ..Options::default()
+            };
+            let database = Database::open(into, options)?;
+            let report = management::salvage(&cli.path, &database)?;
+            println!(
+                "{} keys salvaged from {} tables and {} logs",
+                report.keys, report.tables, report.logs
+            );
+            print_corruptions(&report.corruptions, cli.key_format);
+        }
+        Command::Destroy { yes } => {
+            if !yes {
+                return Err(
+                    format!("refusing to destroy {} without --yes", cli.path.display()).into(),
+                );
+            }
+            management::destroy(&cli.path, &Options::default())?;
+        }
+        Command::ApproxSize { ref from, ref to } => {
+            let database = open(&cli)?;
+            let from = cli.key_format.parse(from)?;
+            let to = cli.key_format.parse(to)?;
+            println!("{}", database.approximate_size(&from, &to));
+        }
+        Command::Manifest {
+            command: ManifestCommand::Dump { ref file, summary },
+        } => {
+            let path = match *file {
+                Some(ref file) => file.clone(),
+                None => current_manifest(&cli.path)?,
+            };
+            dump_manifest(&path, summary, cli.key_format)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("leveldb-cli: {}", e);
+        process::exit(1);
+    }
+}
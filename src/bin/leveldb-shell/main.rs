@@ -0,0 +1,124 @@
+//! `leveldb-shell`, an interactive shell for exploring leveldb databases.
+//!
+//! Type `help` at the prompt for the list of commands.
+#[path = "../leveldb-cli/format.rs"]
+mod format;
+mod session;
+
+use clap::{Parser, ValueEnum};
+use leveldb::database::Database;
+use leveldb::merge::{AppendOperator, MergeOperator, U64AddOperator};
+use leveldb::options::{Options, WriteOptions};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use session::{split_words, Shell};
+
+use std::env;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+/// The merge operators the shell can fold values with.
+#[derive(Clone, Copy, ValueEnum)]
+enum Merge {
+    /// add little-endian u64s
+    U64Add,
+    /// append operands to the value
+    Append,
+}
+
+#[derive(Parser)]
+#[command(
+    name = "leveldb-shell",
+    version,
+    about = "Explore leveldb databases interactively"
+)]
+struct Cli {
+    /// the database directory
+    path: PathBuf,
+    /// create the database if it does not exist
+    #[arg(long)]
+    create_if_missing: bool,
+    /// sync writes to disk before returning
+    #[arg(long)]
+    sync: bool,
+    /// open a database created with ttl, stripping expiry headers and
+    /// hiding expired values
+    #[arg(long)]
+    ttl: bool,
+    /// open a database written with this merge operator, folding pending
+    /// merges into the values shown
+    #[arg(long, value_enum)]
+    merge_operator: Option<Merge>,
+}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".leveldb_shell_history"))
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let options = Options {
+        create_if_missing: cli.create_if_missing,
+        ttl: cli.ttl,
+        merge_operator: cli.merge_operator.map(|merge| -> Arc<dyn MergeOperator> {
+            match merge {
+                Merge::U64Add => Arc::new(U64AddOperator),
+                Merge::Append => Arc::new(AppendOperator),
+            }
+        }),
+        ..Options::default()
+    };
+    let database = match Database::open(&cli.path, options) {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("leveldb-shell: {}", e);
+            process::exit(1);
+        }
+    };
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("leveldb-shell: {}", e);
+            process::exit(1);
+        }
+    };
+    let history = history_file();
+    if let Some(ref history) = history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut shell = Shell::new(
+        &database,
+        WriteOptions { sync: cli.sync },
+        cli.merge_operator.is_some(),
+        io::stdout(),
+    );
+    loop {
+        let line = match editor.readline(&shell.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("leveldb-shell: {}", e);
+                break;
+            }
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        let result = split_words(&line)
+            .map_err(|e| e.into())
+            .and_then(|words| shell.run(&words));
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+    if shell.in_batch() {
+        eprintln!("discarding the uncommitted batch");
+    }
+    if let Some(ref history) = history {
+        let _ = editor.save_history(history);
+    }
+}
@@ -0,0 +1,332 @@
+//! The commands of `leveldb-shell`, apart from the line editor reading
+//! them.
+use super::format::Format;
+use clap::ValueEnum;
+use leveldb::batch::Writebatch;
+use leveldb::database::iterator::DatabaseIterator;
+use leveldb::database::Database;
+use leveldb::options::{ReadOptions, WriteOptions};
+
+use std::error::Error;
+use std::io::{self, Write};
+
+const HELP: &str = "\
+commands:
+  get <key>                 print the value of a key
+  put <key> <value>         set a key, or add it to the open batch
+  delete <key>              delete a key, or add it to the open batch
+  seek <key>                move the cursor to the first key >= <key>
+  first, last               move the cursor to the first or last key
+  next [n], prev [n]        move the cursor n entries (default 1)
+  show                      print the entry at the cursor
+  scan [n]                  print n entries from the cursor on (default 10)
+  begin                     start collecting puts and deletes in a batch
+  commit                    write the batch atomically
+  rollback                  discard the batch
+  format key|value <fmt>    parse and print keys or values as utf8, hex or base64
+  help                      show this help
+  quit, exit                leave the shell
+
+Arguments can be quoted with \"...\", using \\ to escape characters.
+In utf8, \\xNN stands for a byte and \\\\ for a backslash; inside quotes,
+the backslash itself has to be escaped.
+The cursor always reads the latest data: after a write, it moves on from
+its key again rather than from a snapshot. With a merge operator, the
+cursor shows folded values, but only visits keys that have a base value.";
+
+/// split a command line into words, honoring quotes.
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut word = String::new();
+        match chars.peek() {
+            None => return Ok(words),
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.push(chars.next().ok_or("unfinished escape")?),
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+            }
+        }
+        words.push(word);
+    }
+}
+
+/// The state of a shell: the cursor, the open batch and the formats in
+/// use. Output goes to `out`.
+pub struct Shell<'a, W> {
+    database: &'a Database,
+    write_options: WriteOptions,
+    /// whether the database has a merge operator, so that values at the
+    /// cursor have to be read with `get` to fold them
+    merged: bool,
+    out: W,
+    key_format: Format,
+    value_format: Format,
+    /// the key the cursor is at, if it was positioned
+    cursor: Option<Vec<u8>>,
+    /// the iterator moving the cursor, dropped after every write so that
+    /// the next move sees the new data
+    iter: Option<DatabaseIterator<'a>>,
+    /// the open batch and the number of operations in it
+    batch: Option<(Writebatch, usize)>,
+}
+
+/// A key and the value shown for it.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// Where to move the cursor.
+enum Move<'a> {
+    Seek(&'a [u8]),
+    First,
+    Last,
+    Next,
+    Prev,
+}
+
+impl<'a, W: Write> Shell<'a, W> {
+    pub fn new(
+        database: &'a Database,
+        write_options: WriteOptions,
+        merged: bool,
+        out: W,
+    ) -> Shell<'a, W> {
+        Shell {
+            database,
+            write_options,
+            merged,
+            out,
+            key_format: Format::Utf8,
+            value_format: Format::Utf8,
+            cursor: None,
+            iter: None,
+            batch: None,
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        match self.batch {
+            Some((_, count)) => format!("leveldb (batch: {})> ", count),
+            None => "leveldb> ".to_string(),
+        }
+    }
+
+    /// whether a batch was begun and not yet committed or rolled back.
+    pub fn in_batch(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    fn print_entry(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}\t{}",
+            self.key_format.display(key),
+            self.value_format.display(value)
+        )
+    }
+
+    /// the value to show for an entry the iterator is at.
+    fn value(&self, key: &[u8], stored: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if !self.merged {
+            return Ok(stored.to_vec());
+        }
+        Ok(self
+            .database
+            .get(&ReadOptions::default(), key)?
+            .unwrap_or_default())
+    }
+
+    /// forget the cursor's iterator after a write.
+    fn written(&mut self) {
+        self.iter = None;
+    }
+
+    /// move the cursor, returning the entry it lands on.
+    fn move_cursor(&mut self, to: Move) -> Result<Option<Entry>, Box<dyn Error>> {
+        let database = self.database;
+        let iter = self
+            .iter
+            .get_or_insert_with(|| database.iter(&ReadOptions::default()));
+        match to {
+            Move::Seek(key) => iter.seek(key),
+            Move::First => iter.seek_to_first(),
+            Move::Last => iter.seek_to_last(),
+            Move::Next | Move::Prev => {
+                let current = match self.cursor {
+                    Some(ref current) => current,
+                    None => return Ok(None),
+                };
+                // the iterator is still at the cursor unless it ran off the
+                // end or was re-created after a write
+                let at_cursor = iter.valid() && iter.key() == &current[..];
+                if !at_cursor {
+                    iter.seek(current);
+                }
+                match to {
+                    Move::Next if iter.valid() && iter.key() == &current[..] => iter.next(),
+                    Move::Next => {}
+                    _ if iter.valid() => iter.prev(),
+                    _ => iter.seek_to_last(),
+                }
+            }
+        }
+        if !iter.valid() {
+            return Ok(None);
+        }
+        let (key, stored) = (iter.key().to_vec(), iter.value().to_vec());
+        let value = self.value(&key, &stored)?;
+        self.cursor = Some(key.clone());
+        Ok(Some((key, value)))
+    }
+
+    /// move the cursor `count` entries forward or backward.
+    fn step(&mut self, forward: bool, count: Option<&String>) -> Result<(), Box<dyn Error>> {
+        let count: usize = count.map(|n| n.parse()).transpose()?.unwrap_or(1);
+        if self.cursor.is_none() {
+            return Err("the cursor is not positioned; use seek, first or last".into());
+        }
+        let mut entry = None;
+        for _ in 0..count {
+            entry = self.move_cursor(if forward { Move::Next } else { Move::Prev })?;
+            if entry.is_none() {
+                break;
+            }
+        }
+        match entry {
+            Some((key, value)) => self.print_entry(&key, &value)?,
+            None => writeln!(self.out, "(end)")?,
+        }
+        Ok(())
+    }
+
+    /// run one command, returning whether the shell should go on.
+    pub fn run(&mut self, words: &[String]) -> Result<bool, Box<dyn Error>> {
+        let args: Vec<&str> = words.iter().map(|word| &word[..]).collect();
+        match args[..] {
+            [] => {}
+            ["help"] => writeln!(self.out, "{}", HELP)?,
+            ["quit"] | ["exit"] => return Ok(false),
+            ["get", key] => {
+                let key = self.key_format.parse(key)?;
+                match self.database.get(&ReadOptions::default(), &key)? {
+                    Some(value) => writeln!(self.out, "{}", self.value_format.display(&value))?,
+                    None => writeln!(self.out, "(not found)")?,
+                }
+            }
+            ["put", key, value] => {
+                let key = self.key_format.parse(key)?;
+                let value = self.value_format.parse(value)?;
+                match self.batch {
+                    Some((ref mut batch, ref mut count)) => {
+                        batch.put(&key, &value);
+                        *count += 1;
+                    }
+                    None => {
+                        self.database.put(&self.write_options, &key, &value)?;
+                        self.written();
+                    }
+                }
+            }
+            ["delete", key] => {
+                let key = self.key_format.parse(key)?;
+                match self.batch {
+                    Some((ref mut batch, ref mut count)) => {
+                        batch.delete(&key);
+                        *count += 1;
+                    }
+                    None => {
+                        self.database.delete(&self.write_options, &key)?;
+                        self.written();
+                    }
+                }
+            }
+            ["seek", key] => {
+                let key = self.key_format.parse(key)?;
+                match self.move_cursor(Move::Seek(&key))? {
+                    Some((key, value)) => self.print_entry(&key, &value)?,
+                    None => writeln!(self.out, "(end)")?,
+                }
+            }
+            ["first"] | ["last"] => {
+                let to = if args[0] == "first" {
+                    Move::First
+                } else {
+                    Move::Last
+                };
+                match self.move_cursor(to)? {
+                    Some((key, value)) => self.print_entry(&key, &value)?,
+                    None => writeln!(self.out, "(empty)")?,
+                }
+            }
+            ["next"] | ["next", _] => self.step(true, words.get(1))?,
+            ["prev"] | ["prev", _] => self.step(false, words.get(1))?,
+            ["show"] => {
+                let key = self.cursor.clone().ok_or("the cursor is not positioned")?;
+                match self.database.get(&ReadOptions::default(), &key)? {
+                    Some(value) => self.print_entry(&key, &value)?,
+                    None => writeln!(self.out, "{}\t(deleted)", self.key_format.display(&key))?,
+                }
+            }
+            ["scan"] | ["scan", _] => {
+                let count: usize = words.get(1).map(|n| n.parse()).transpose()?.unwrap_or(10);
+                let database = self.database;
+                let mut iter = database.iter(&ReadOptions::default());
+                match self.cursor {
+                    Some(ref key) => iter.seek(key),
+                    None => iter.seek_to_first(),
+                }
+                for _ in 0..count {
+                    if !iter.valid() {
+                        writeln!(self.out, "(end)")?;
+                        break;
+                    }
+                    let value = self.value(iter.key(), iter.value())?;
+                    self.print_entry(iter.key(), &value)?;
+                    iter.next();
+                }
+            }
+            ["begin"] => {
+                if self.batch.is_some() {
+                    return Err("a batch is already open".into());
+                }
+                self.batch = Some((Writebatch::new(), 0));
+            }
+            ["commit"] => {
+                let (batch, count) = self.batch.take().ok_or("no batch is open")?;
+                self.database.write(&self.write_options, &batch)?;
+                self.written();
+                writeln!(self.out, "committed {} operations", count)?;
+            }
+            ["rollback"] => {
+                let (_, count) = self.batch.take().ok_or("no batch is open")?;
+                writeln!(self.out, "discarded {} operations", count)?;
+            }
+            ["format", which, format] => {
+                let format = Format::from_str(format, true)?;
+                match which {
+                    "key" => self.key_format = format,
+                    "value" => self.value_format = format,
+                    _ => return Err("format key|value <utf8|hex|base64>".into()),
+                }
+            }
+            _ => return Err(format!("unknown command {:?}; try help", words.join(" ")).into()),
+        }
+        Ok(true)
+    }
+}
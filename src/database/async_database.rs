@@ -0,0 +1,130 @@
+//! An async wrapper around `Database`
+//!
+//! All leveldb calls block, so `AsyncDatabase` runs them on tokio's
+//! blocking thread pool. Only available with the `async` feature, and
+//! must be used from within a tokio runtime.
+use super::batch::Writebatch;
+use super::error::Error;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use futures_core::Stream;
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// The number of entries a scan reads ahead of its consumer.
+const SCAN_BUFFER: usize = 64;
+
+/// A handle to a database for use from async code.
+///
+/// Cloning the handle is cheap, all clones share the same database.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    database: Arc<Database>,
+}
+
+async fn blocking<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(_) => Err(Error::new("blocking task was cancelled".to_string())),
+    }
+}
+
+impl AsyncDatabase {
+    /// wrap a database.
+    pub fn new(database: Database) -> AsyncDatabase {
+        AsyncDatabase::from_arc(Arc::new(database))
+    }
+
+    /// wrap a database that is shared with blocking code.
+    pub fn from_arc(database: Arc<Database>) -> AsyncDatabase {
+        AsyncDatabase { database }
+    }
+
+    /// The underlying database.
+    pub fn database(&self) -> &Arc<Database> {
+        &self.database
+    }
+
+    /// get a value from the database.
+    pub async fn get(&self, options: ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let database = self.database.clone();
+        let key = key.to_vec();
+        blocking(move || database.get(&options, &key)).await
+    }
+
+    /// put a value into the database.
+    pub async fn put(&self, options: WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let database = self.database.clone();
+        let key = key.to_vec();
+        let value = value.to_vec();
+        blocking(move || database.put(&options, &key, &value)).await
+    }
+
+    /// delete a value from the database.
+    pub async fn delete(&self, options: WriteOptions, key: &[u8]) -> Result<(), Error> {
+        let database = self.database.clone();
+        let key = key.to_vec();
+        blocking(move || database.delete(&options, &key)).await
+    }
+
+    /// write a batch to the database atomically.
+    pub async fn write(&self, options: WriteOptions, batch: Writebatch) -> Result<(), Error> {
+        let database = self.database.clone();
+        blocking(move || database.write(&options, &batch)).await
+    }
+
+    /// stream all entries with keys in `[from, to)`, in key order.
+    ///
+    /// `None` leaves the range open on that side. The entries are read on a
+    /// blocking thread, which stays at most a few entries ahead of the
+    /// consumer. Dropping the stream stops the scan.
+    pub fn scan(&self, options: ReadOptions, from: Option<&[u8]>, to: Option<&[u8]>) -> Scan {
+        let (sender, receiver) = mpsc::channel(SCAN_BUFFER);
+        let database = self.database.clone();
+        let from = from.map(|k| k.to_vec());
+        let to = to.map(|k| k.to_vec());
+        task::spawn_blocking(move || {
+            let mut iter = database.iter(&options);
+            match from {
+                Some(ref from) => iter.seek(from),
+                None => iter.seek_to_first(),
+            }
+            while iter.valid() {
+                if let Some(ref to) = to {
+                    if iter.key() >= &to[..] {
+                        break;
+                    }
+                }
+                let entry = (iter.key().to_vec(), iter.value().to_vec());
+                if sender.blocking_send(entry).is_err() {
+                    break;
+                }
+                iter.next();
+            }
+        });
+        Scan { receiver }
+    }
+}
+
+/// A stream of key-value pairs, returned by `AsyncDatabase::scan`.
+pub struct Scan {
+    receiver: mpsc::Receiver<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Stream for Scan {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -0,0 +1,141 @@
+//! Per-key read-modify-write operations.
+//!
+//! leveldb has no transactions, so these operations are made atomic with
+//! respect to each other by serializing all callers touching the same key
+//! within this process. Plain `put`s and `delete`s do not take these locks.
+use super::batch::Writebatch;
+use super::error::Error;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const STRIPES: usize = 64;
+
+/// hash a key for picking a stripe. Stable for the lifetime of the process.
+pub(crate) fn key_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A fixed set of mutexes, keys are mapped onto them by hash.
+pub(crate) struct KeyLocks {
+    stripes: Vec<Mutex<()>>,
+}
+
+impl KeyLocks {
+    pub(crate) fn new() -> KeyLocks {
+        KeyLocks {
+            stripes: (0..STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    pub(crate) fn stripe(&self, key: &[u8]) -> usize {
+        (key_hash(key) % self.stripes.len() as u64) as usize
+    }
+
+    /// lock the stripe `key` maps to.
+    pub(crate) fn lock(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.lock_stripe(self.stripe(key))
+    }
+
+    /// lock the stripes of all `keys`, in a fixed order to avoid deadlocks.
+    pub(crate) fn lock_many<'k, I>(&self, keys: I) -> Vec<MutexGuard<'_, ()>>
+    where
+        I: IntoIterator<Item = &'k [u8]>,
+    {
+        let stripes: BTreeSet<usize> = keys.into_iter().map(|key| self.stripe(key)).collect();
+        stripes
+            .into_iter()
+            .map(|stripe| self.lock_stripe(stripe))
+            .collect()
+    }
+
+    fn lock_stripe(&self, stripe: usize) -> MutexGuard<'_, ()> {
+        self.stripes[stripe]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Database {
+    /// put a value into the database, unless the key is already present.
+    ///
+    /// Returns whether the value was written.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn put_if_absent(
+        &self,
+        options: &WriteOptions,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<bool, Error> {
+        self.compare_and_swap(options, key, None, Some(value))
+    }
+
+    /// replace the value of `key` with `new` if its current value is
+    /// `expected`.
+    ///
+    /// `None` stands for an absent key on both sides, so passing `None` as
+    /// `new` deletes the key. Returns whether the swap happened.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn compare_and_swap(
+        &self,
+        options: &WriteOptions,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let _guard = self.locks.lock(key);
+        let current = self.get(&ReadOptions::default(), key)?;
+        if current.as_deref() != expected {
+            return Ok(false);
+        }
+        self.store(options, key, new)?;
+        Ok(true)
+    }
+
+    /// atomically replace the value of `key` with the result of `f`.
+    ///
+    /// `f` receives the current value, or `None` if the key is absent.
+    /// Returning `None` deletes the key. Returns the new value.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn update<F>(
+        &self,
+        options: &WriteOptions,
+        key: &[u8],
+        f: F,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        F: FnOnce(Option<&[u8]>) -> Option<Vec<u8>>,
+    {
+        let _guard = self.locks.lock(key);
+        let current = self.get(&ReadOptions::default(), key)?;
+        let new = f(current.as_deref());
+        if new.is_some() || current.is_some() {
+            self.store(options, key, new.as_deref())?;
+        }
+        Ok(new)
+    }
+
+    /// put or delete `key` while holding its stripe lock.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    fn store(&self, options: &WriteOptions, key: &[u8], value: Option<&[u8]>) -> Result<(), Error> {
+        if self.merge_operator.is_none() {
+            return match value {
+                Some(value) => self.put(options, key, value),
+                None => self.delete(options, key),
+            };
+        }
+        // the stripe lock is already held, which is all `write` would take
+        let mut batch = Writebatch::new();
+        match value {
+            Some(value) => batch.put(key, value),
+            None => batch.delete(key),
+        }
+        self.write_locked(options, &batch)
+    }
+}
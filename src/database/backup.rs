@@ -0,0 +1,327 @@
+//! Incremental backups with retention
+//!
+//! A `BackupEngine` keeps any number of backups of a database in one
+//! directory:
+//!
+//! * `shared/` holds table files. Tables never change once written, so
+//!   backups share them; a table is stored once per name and checksum.
+//! * `private/<id>/` holds the files specific to one backup: MANIFEST,
+//!   CURRENT and logs.
+//! * `meta/<id>` describes a backup: when it was taken, its size and every
+//!   file with its size and CRC32C checksum.
+//!
+//! Backups are taken from a checkpoint (see `management::checkpoint`), so
+//! the database can keep serving reads and writes meanwhile. All files are
+//! copied, so backups don't share storage with the database.
+//!
+//! Creating, restoring and purging backups lock the file `LOCK` in the
+//! backup directory, so that engines in other threads or processes can't
+//! pick the same id or delete shared files still in use.
+use super::error::Error;
+use super::management::checkpoint;
+use super::Database;
+use crate::format::crc32c;
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn io_error(error: io::Error) -> Error {
+    Error::new(error.to_string())
+}
+
+/// copy `from` to `to` under a temporary name, renaming it once its data
+/// is synced, so that `to` only ever exists complete.
+fn copy_synced(from: &Path, to: &Path) -> io::Result<()> {
+    let mut tmp = to.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::copy(from, &tmp)?;
+    File::open(&tmp)?.sync_all()?;
+    fs::rename(&tmp, to)
+}
+
+fn is_table(name: &str) -> bool {
+    name.ends_with(".ldb") || name.ends_with(".sst")
+}
+
+/// the CRC32C checksum and size of a file.
+fn checksum(path: &Path) -> io::Result<(u32, u64)> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut crc = 0;
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok((crc, size));
+        }
+        crc = crc32c::extend(crc, &buf[..n]);
+        size += n as u64;
+    }
+}
+
+/// A file that is part of a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    /// the name of the file in the database directory
+    pub name: String,
+    /// where the file is stored, relative to the backup directory
+    pub stored: String,
+    /// the size of the file in bytes
+    pub size: u64,
+    /// the CRC32C checksum of the file contents
+    pub checksum: u32,
+}
+
+/// The metadata of a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// the id of the backup, increasing with every backup
+    pub id: u64,
+    /// when the backup was taken, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// the total size of the backup's files in bytes, shared or not
+    pub size: u64,
+    /// the files of the backup
+    pub files: Vec<BackupFile>,
+}
+
+impl BackupInfo {
+    fn encode(&self) -> String {
+        let mut out = format!("timestamp {}\nsize {}\n", self.timestamp, self.size);
+        for file in &self.files {
+            out.push_str(&format!(
+                "file {} {} {} {:08x}\n",
+                file.name, file.stored, file.size, file.checksum
+            ));
+        }
+        out
+    }
+
+    fn decode(id: u64, text: &str) -> Result<BackupInfo, Error> {
+        let corrupt = || Error::new(format!("corrupt metadata for backup {}", id));
+        let mut info = BackupInfo {
+            id,
+            timestamp: 0,
+            size: 0,
+            files: Vec::new(),
+        };
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split(' ').collect();
+            match fields[..] {
+                ["timestamp", timestamp] => {
+                    info.timestamp = timestamp.parse().map_err(|_| corrupt())?
+                }
+                ["size", size] => info.size = size.parse().map_err(|_| corrupt())?,
+                ["file", name, stored, size, checksum] => info.files.push(BackupFile {
+                    name: name.to_string(),
+                    stored: stored.to_string(),
+                    size: size.parse().map_err(|_| corrupt())?,
+                    checksum: u32::from_str_radix(checksum, 16).map_err(|_| corrupt())?,
+                }),
+                _ => return Err(corrupt()),
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// Stores and manages backups of databases in a directory.
+pub struct BackupEngine {
+    dir: PathBuf,
+}
+
+impl BackupEngine {
+    /// open the backup directory `dir`, creating it if missing.
+    pub fn open(dir: &Path) -> Result<BackupEngine, Error> {
+        for sub in &["shared", "private", "meta"] {
+            fs::create_dir_all(dir.join(sub)).map_err(io_error)?;
+        }
+        Ok(BackupEngine {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// lock the backup directory until the returned file is closed.
+    fn lock(&self) -> Result<File, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join("LOCK"))
+            .map_err(io_error)?;
+        file.lock().map_err(io_error)?;
+        Ok(file)
+    }
+
+    fn ids(&self) -> Result<Vec<u64>, Error> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(self.dir.join("meta")).map_err(io_error)? {
+            let name = entry.map_err(io_error)?.file_name();
+            if let Some(id) = name.to_str().and_then(|name| name.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// The metadata of a backup.
+    pub fn backup_info(&self, id: u64) -> Result<BackupInfo, Error> {
+        let path = self.dir.join("meta").join(id.to_string());
+        let text = fs::read_to_string(path).map_err(io_error)?;
+        BackupInfo::decode(id, &text)
+    }
+
+    /// The metadata of all backups, oldest first.
+    pub fn backups(&self) -> Result<Vec<BackupInfo>, Error> {
+        self.ids()?
+            .into_iter()
+            .map(|id| self.backup_info(id))
+            .collect()
+    }
+
+    /// back up a live database, returning the id of the new backup.
+    ///
+    /// Only table files not stored by an earlier backup are added to
+    /// `shared/`.
+    pub fn create_backup(&self, database: &Database) -> Result<u64, Error> {
+        let _lock = self.lock()?;
+        let id = self.ids()?.last().map_or(1, |last| last + 1);
+        let staging = self.dir.join(format!("tmp-{}", id));
+        if staging.exists() {
+            fs::remove_dir_all(&staging).map_err(io_error)?;
+        }
+        checkpoint(database, &staging)?;
+
+        let private = Path::new("private").join(id.to_string());
+        fs::create_dir_all(self.dir.join(&private)).map_err(io_error)?;
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&staging).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            if name == "LOCK" || name.starts_with("LOG") {
+                continue;
+            }
+            let (checksum, size) = checksum(&path).map_err(io_error)?;
+            let stored = if is_table(&name) {
+                Path::new("shared").join(format!("{}_{:08x}_{}", name, checksum, size))
+            } else {
+                private.join(&name)
+            };
+            // tables in the checkpoint are links to the live files, so they
+            // are copied to keep the backup independent of the database
+            if !self.dir.join(&stored).exists() {
+                copy_synced(&path, &self.dir.join(&stored)).map_err(io_error)?;
+            }
+            files.push(BackupFile {
+                name,
+                stored: stored.to_string_lossy().into_owned(),
+                size,
+                checksum,
+            });
+        }
+        fs::remove_dir_all(&staging).map_err(io_error)?;
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let info = BackupInfo {
+            id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            size: files.iter().map(|file| file.size).sum(),
+            files,
+        };
+        // the backup only exists once its metadata is complete
+        let meta = self.dir.join("meta");
+        let tmp = meta.join(format!("{}.tmp", id));
+        let mut file = File::create(&tmp).map_err(io_error)?;
+        file.write_all(info.encode().as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(io_error)?;
+        fs::rename(&tmp, meta.join(id.to_string())).map_err(io_error)?;
+        Ok(id)
+    }
+
+    /// check that all files of a backup are present and intact.
+    pub fn verify(&self, id: u64) -> Result<(), Error> {
+        let _lock = self.lock()?;
+        self.verify_locked(id)
+    }
+
+    fn verify_locked(&self, id: u64) -> Result<(), Error> {
+        for file in self.backup_info(id)?.files {
+            let path = self.dir.join(&file.stored);
+            let (checksum, size) =
+                checksum(&path).map_err(|e| Error::new(format!("{}: {}", path.display(), e)))?;
+            if size != file.size || checksum != file.checksum {
+                return Err(Error::new(format!(
+                    "{} is corrupted: expected {} bytes with checksum {:08x}, found {} bytes with checksum {:08x}",
+                    path.display(),
+                    file.size,
+                    file.checksum,
+                    size,
+                    checksum
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// restore a backup into `target`, which must not exist yet.
+    ///
+    /// The backup is verified first, so a damaged backup is not restored.
+    /// If copying fails, `target` is removed again.
+    pub fn restore(&self, id: u64, target: &Path) -> Result<(), Error> {
+        if target.exists() {
+            return Err(Error::new(format!("{} already exists", target.display())));
+        }
+        let _lock = self.lock()?;
+        self.verify_locked(id)?;
+        let info = self.backup_info(id)?;
+        fs::create_dir_all(target).map_err(io_error)?;
+        for file in &info.files {
+            if let Err(e) = fs::copy(self.dir.join(&file.stored), target.join(&file.name)) {
+                let _ = fs::remove_dir_all(target);
+                return Err(io_error(e));
+            }
+        }
+        Ok(())
+    }
+
+    /// delete all but the newest `keep` backups, and the shared files
+    /// only they used.
+    ///
+    /// Returns the number of backups deleted.
+    pub fn purge_old(&self, keep: usize) -> Result<usize, Error> {
+        let _lock = self.lock()?;
+        let ids = self.ids()?;
+        let purge = ids.len().saturating_sub(keep);
+        for &id in &ids[..purge] {
+            fs::remove_file(self.dir.join("meta").join(id.to_string())).map_err(io_error)?;
+            let private = self.dir.join("private").join(id.to_string());
+            if private.exists() {
+                fs::remove_dir_all(private).map_err(io_error)?;
+            }
+        }
+
+        let mut used = BTreeSet::new();
+        for info in self.backups()? {
+            used.extend(info.files.into_iter().map(|file| file.stored));
+        }
+        for entry in fs::read_dir(self.dir.join("shared")).map_err(io_error)? {
+            let name = entry.map_err(io_error)?.file_name();
+            let stored = Path::new("shared").join(&name);
+            if !used.contains(&*stored.to_string_lossy()) {
+                fs::remove_file(self.dir.join(stored)).map_err(io_error)?;
+            }
+        }
+        Ok(purge)
+    }
+}
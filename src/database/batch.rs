@@ -0,0 +1,208 @@
+//! Module providing write batches
+//!
+//! A `Writebatch` collects puts and deletes that are then applied
+//! atomically using `Database::write`.
+use super::error::Error;
+use super::Database;
+use crate::options::{c_writeoptions, WriteOptions};
+use leveldb_sys::*;
+use libc::{c_char, c_void, size_t};
+use std::ptr;
+use std::slice;
+
+#[allow(missing_docs)]
+struct RawWritebatch {
+    ptr: *mut leveldb_writebatch_t,
+}
+
+#[allow(missing_docs)]
+impl Drop for RawWritebatch {
+    fn drop(&mut self) {
+        unsafe {
+            leveldb_writebatch_destroy(self.ptr);
+        }
+    }
+}
+
+/// A batch of writes, applied atomically.
+pub struct Writebatch {
+    writebatch: RawWritebatch,
+}
+
+unsafe impl Send for Writebatch {}
+
+/// A trait for visiting the operations recorded in a `Writebatch`.
+pub trait WritebatchIterator {
+    /// called for every put in the batch, in insertion order
+    fn put(&mut self, key: &[u8], value: &[u8]);
+    /// called for every delete in the batch, in insertion order
+    fn deleted(&mut self, key: &[u8]);
+}
+
+impl Database {
+    /// write a batch to the database, ensuring that either all operations
+    /// in it are applied or none.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn write(&self, options: &WriteOptions, batch: &Writebatch) -> Result<(), Error> {
+        let _locks = self.lock_operands(batch);
+        self.write_locked(options, batch)
+    }
+
+    /// write a batch while holding the locks `lock_operands` takes for it.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub(crate) fn write_locked(
+        &self,
+        options: &WriteOptions,
+        batch: &Writebatch,
+    ) -> Result<(), Error> {
+        let prepared = self.prepare_batch(batch);
+        let written = prepared.as_ref().unwrap_or(batch);
+        let _guard = self.versions.mutation();
+        let publisher = self.watchers.publisher(|events| events.batch(batch));
+        unsafe { self.write_c(options, written)? };
+        self.versions.bump_batch(written);
+        if let Some(publisher) = publisher {
+            publisher.publish();
+        }
+        Ok(())
+    }
+
+    /// write a batch, recording it in the change log if enabled.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub(crate) unsafe fn write_c(
+        &self,
+        options: &WriteOptions,
+        batch: &Writebatch,
+    ) -> Result<(), Error> {
+        match self.change_log {
+            Some(ref change_log) => change_log.write(self, options, batch),
+            None => self.write_raw(options, batch),
+        }
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub(crate) unsafe fn write_raw(
+        &self,
+        options: &WriteOptions,
+        batch: &Writebatch,
+    ) -> Result<(), Error> {
+        let mut error = ptr::null_mut();
+        let c_writeoptions = c_writeoptions(options);
+        leveldb_write(
+            self.database.ptr,
+            c_writeoptions,
+            batch.writebatch.ptr,
+            &mut error,
+        );
+        leveldb_writeoptions_destroy(c_writeoptions);
+
+        if error.is_null() {
+            Ok(())
+        } else {
+            Err(Error::new_from_i8(error))
+        }
+    }
+}
+
+impl Writebatch {
+    /// create a new, empty writebatch
+    pub fn new() -> Writebatch {
+        let ptr = unsafe { leveldb_writebatch_create() };
+        Writebatch {
+            writebatch: RawWritebatch { ptr },
+        }
+    }
+
+    /// clear the writebatch
+    pub fn clear(&mut self) {
+        unsafe { leveldb_writebatch_clear(self.writebatch.ptr) };
+    }
+
+    /// record a put of `value` under `key`
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        unsafe {
+            leveldb_writebatch_put(
+                self.writebatch.ptr,
+                key.as_ptr() as *mut c_char,
+                key.len() as size_t,
+                value.as_ptr() as *mut c_char,
+                value.len() as size_t,
+            );
+        }
+    }
+
+    /// record a delete of `key`
+    pub fn delete(&mut self, key: &[u8]) {
+        unsafe {
+            leveldb_writebatch_delete(
+                self.writebatch.ptr,
+                key.as_ptr() as *mut c_char,
+                key.len() as size_t,
+            );
+        }
+    }
+
+    /// append all operations recorded in `other` to this batch
+    pub fn append(&mut self, other: &Writebatch) {
+        struct Append(Writebatch);
+
+        impl WritebatchIterator for Append {
+            fn put(&mut self, key: &[u8], value: &[u8]) {
+                self.0.put(key, value);
+            }
+
+            fn deleted(&mut self, key: &[u8]) {
+                self.0.delete(key);
+            }
+        }
+
+        let this = std::mem::take(self);
+        *self = other.iterate(Box::new(Append(this))).0;
+    }
+
+    /// iterate over the operations in the batch, handing them to `iterator`
+    pub fn iterate<T: WritebatchIterator>(&self, mut iterator: Box<T>) -> Box<T> {
+        unsafe {
+            leveldb_writebatch_iterate(
+                self.writebatch.ptr,
+                &mut *iterator as *mut T as *mut c_void,
+                put_callback::<T>,
+                deleted_callback::<T>,
+            );
+        }
+        iterator
+    }
+}
+
+impl Default for Writebatch {
+    fn default() -> Writebatch {
+        Writebatch::new()
+    }
+}
+
+extern "C" fn put_callback<T: WritebatchIterator>(
+    state: *mut c_void,
+    key: *const c_char,
+    keylen: size_t,
+    val: *const c_char,
+    vallen: size_t,
+) {
+    unsafe {
+        let iter = &mut *(state as *mut T);
+        let key = slice::from_raw_parts(key as *const u8, keylen);
+        let val = slice::from_raw_parts(val as *const u8, vallen);
+        iter.put(key, val);
+    }
+}
+
+extern "C" fn deleted_callback<T: WritebatchIterator>(
+    state: *mut c_void,
+    key: *const c_char,
+    keylen: size_t,
+) {
+    unsafe {
+        let iter = &mut *(state as *mut T);
+        let key = slice::from_raw_parts(key as *const u8, keylen);
+        iter.deleted(key);
+    }
+}
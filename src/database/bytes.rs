@@ -1,3 +1,4 @@
+use std::ptr;
 use std::slice;
 
 /// Bytes allocated by leveldb
@@ -9,30 +10,60 @@ pub struct Bytes {
     // it can't be null. (Because `NonZero` is unstable now.)
     bytes: &'static mut u8,
     size: usize,
+    // Number of leading bytes hidden from the user, e.g. a value header.
+    offset: usize,
 }
 
 impl Bytes {
     /// Creates instance of `Bytes` from leveldb-allocated data.
     ///
     /// Returns `None` if `ptr` is `null`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or point to `size` bytes allocated by leveldb.
     pub unsafe fn from_raw(ptr: *mut u8, size: usize) -> Option<Self> {
         if ptr.is_null() {
             None
         } else {
             Some(Bytes {
                 bytes: &mut *ptr,
-                size: size,
+                size,
+                offset: 0,
             })
         }
     }
 
     /// Creates instance of `Bytes` from leveldb-allocated data without null checking.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `size` bytes allocated by leveldb.
     pub unsafe fn from_raw_unchecked(ptr: *mut u8, size: usize) -> Self {
         Bytes {
             bytes: &mut *ptr,
-            size: size,
+            size,
+            offset: 0,
+        }
+    }
+
+    /// Copies `data` into a buffer that can be released like one leveldb
+    /// allocated.
+    pub(crate) fn copy_from(data: &[u8]) -> Self {
+        unsafe {
+            // leveldb_free releases memory with free()
+            let ptr = libc::malloc(data.len().max(1)) as *mut u8;
+            assert!(!ptr.is_null(), "out of memory");
+            ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+            Bytes::from_raw_unchecked(ptr, data.len())
         }
     }
+
+    /// Hides the first `n` bytes.
+    pub(crate) fn skip(mut self, n: usize) -> Self {
+        self.offset = (self.offset + n).min(self.size);
+        self
+    }
 }
 
 impl Drop for Bytes {
@@ -49,19 +80,25 @@ impl ::std::ops::Deref for Bytes {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        unsafe { slice::from_raw_parts(self.bytes, self.size) }
+        unsafe {
+            let start = (self.bytes as *const u8).add(self.offset);
+            slice::from_raw_parts(start, self.size - self.offset)
+        }
     }
 }
 
 impl ::std::ops::DerefMut for Bytes {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { slice::from_raw_parts_mut(self.bytes as *mut u8, self.size) }
+        unsafe {
+            let start = (self.bytes as *mut u8).add(self.offset);
+            slice::from_raw_parts_mut(start, self.size - self.offset)
+        }
     }
 }
 
 impl ::std::borrow::Borrow<[u8]> for Bytes {
     fn borrow(&self) -> &[u8] {
-        &*self
+        self
     }
 }
 
@@ -73,7 +110,7 @@ impl ::std::borrow::BorrowMut<[u8]> for Bytes {
 
 impl AsRef<[u8]> for Bytes {
     fn as_ref(&self) -> &[u8] {
-        &*self
+        self
     }
 }
 
@@ -0,0 +1,397 @@
+//! A change-data-capture log of every write
+//!
+//! When a database is opened with `Options::change_log`, every write batch
+//! is extended with a log entry recording the batch's operations, under
+//! the reserved key prefix `\xff\xff` and a sequence number that increases
+//! by one per batch. The entry is written in the same batch as the data, so
+//! the log holds exactly the writes that were applied, in the order they
+//! were applied.
+//!
+//! The log records writes as they are stored, including the bookkeeping
+//! records of merge operators, ttl and secondary indexes, so replaying it
+//! reproduces the database. Entries are kept until they are removed with
+//! `truncate_changes_before`. Like other bookkeeping records, entries are
+//! hidden from iterators.
+use super::batch::{Writebatch, WritebatchIterator};
+use super::error::Error;
+use super::iterator::DatabaseIterator;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::io::{self, Read, Write};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const PREFIX: &[u8] = b"\xff\xffcdc\x00";
+// the highest sequence number ever truncated, so numbers aren't reused
+// once all entries are gone. Sorts after all entries.
+const TRUNCATED_KEY: &[u8] = b"\xff\xffcdc\x01";
+const TRUNCATE_BATCH: usize = 256;
+
+/// whether `key` belongs to the change log.
+pub(crate) fn is_log_key(key: &[u8]) -> bool {
+    key.starts_with(PREFIX) || key == TRUNCATED_KEY
+}
+
+/// The largest record body `ChangeRecord::write_to` writes and
+/// `ChangeRecord::read_from` accepts, in bytes.
+pub const MAX_RECORD_SIZE: usize = 1 << 30;
+
+const TAG_DELETE: u8 = 0;
+const TAG_PUT: u8 = 1;
+
+/// A single operation of a logged write batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// `key` was set to `value`
+    Put {
+        /// the written key
+        key: Vec<u8>,
+        /// the new value
+        value: Vec<u8>,
+    },
+    /// `key` was deleted
+    Delete {
+        /// the deleted key
+        key: Vec<u8>,
+    },
+}
+
+/// A write batch recorded in the change log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeRecord {
+    /// the sequence number of the batch
+    pub seq: u64,
+    /// the operations of the batch, in the order they were applied
+    pub mutations: Vec<Mutation>,
+}
+
+impl ChangeRecord {
+    /// build a batch applying the record's operations.
+    pub fn to_writebatch(&self) -> Writebatch {
+        let mut batch = Writebatch::new();
+        for mutation in &self.mutations {
+            match *mutation {
+                Mutation::Put { ref key, ref value } => batch.put(key, value),
+                Mutation::Delete { ref key } => batch.delete(key),
+            }
+        }
+        batch
+    }
+
+    /// write the record to a stream, framed by its sequence number and
+    /// length.
+    ///
+    /// Fails with `InvalidInput` if the record is larger than
+    /// `MAX_RECORD_SIZE`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut body = Vec::new();
+        for mutation in &self.mutations {
+            encode_mutation(&mut body, mutation);
+        }
+        if body.len() > MAX_RECORD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "change record too large",
+            ));
+        }
+        writer.write_all(&self.seq.to_be_bytes())?;
+        writer.write_all(&(body.len() as u32).to_be_bytes())?;
+        writer.write_all(&body)
+    }
+
+    /// read a record written by `write_to`.
+    ///
+    /// Returns `None` if the stream ended before the next record. A length
+    /// above `MAX_RECORD_SIZE` is reported as `InvalidData`, and the body is
+    /// only buffered as it arrives, so a corrupt length can't make this
+    /// allocate more than the stream holds.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<ChangeRecord>> {
+        let mut header = [0; 12];
+        let mut read = 0;
+        while read < header.len() {
+            match reader.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let seq = read_u64(&header[..8]).unwrap_or(0);
+        let mut len = [0; 4];
+        len.copy_from_slice(&header[8..]);
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_RECORD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("change record of {} bytes is too large", len),
+            ));
+        }
+        let mut body = Vec::new();
+        reader.take(len as u64).read_to_end(&mut body)?;
+        if body.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mutations = decode_mutations(&body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(Some(ChangeRecord { seq, mutations }))
+    }
+}
+
+fn entry_key(seq: u64) -> Vec<u8> {
+    let mut key = PREFIX.to_vec();
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+fn read_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() != 8 {
+        return None;
+    }
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(buf))
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    if input.len() < 4 {
+        return None;
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&input[..4]);
+    let len = u32::from_be_bytes(len) as usize;
+    if input.len() - 4 < len {
+        return None;
+    }
+    let bytes = &input[4..4 + len];
+    *input = &input[4 + len..];
+    Some(bytes)
+}
+
+fn encode_put(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    out.push(TAG_PUT);
+    put_bytes(out, key);
+    put_bytes(out, value);
+}
+
+fn encode_delete(out: &mut Vec<u8>, key: &[u8]) {
+    out.push(TAG_DELETE);
+    put_bytes(out, key);
+}
+
+fn encode_mutation(out: &mut Vec<u8>, mutation: &Mutation) {
+    match *mutation {
+        Mutation::Put { ref key, ref value } => encode_put(out, key, value),
+        Mutation::Delete { ref key } => encode_delete(out, key),
+    }
+}
+
+/// encode the operations of a batch, returning them and their count.
+fn encode_batch(batch: &Writebatch) -> (Vec<u8>, usize) {
+    struct Encoder(Vec<u8>, usize);
+
+    impl WritebatchIterator for Encoder {
+        fn put(&mut self, key: &[u8], value: &[u8]) {
+            encode_put(&mut self.0, key, value);
+            self.1 += 1;
+        }
+
+        fn deleted(&mut self, key: &[u8]) {
+            encode_delete(&mut self.0, key);
+            self.1 += 1;
+        }
+    }
+
+    let encoder = batch.iterate(Box::new(Encoder(Vec::new(), 0)));
+    (encoder.0, encoder.1)
+}
+
+/// decode operations written by `encode_batch`.
+fn decode_mutations(mut input: &[u8]) -> Result<Vec<Mutation>, Error> {
+    let corrupt = || Error::new("corrupt change log entry".to_string());
+    let mut mutations = Vec::new();
+    while let Some((&tag, rest)) = input.split_first() {
+        input = rest;
+        let key = take_bytes(&mut input).ok_or_else(corrupt)?.to_vec();
+        mutations.push(match tag {
+            TAG_PUT => Mutation::Put {
+                key,
+                value: take_bytes(&mut input).ok_or_else(corrupt)?.to_vec(),
+            },
+            TAG_DELETE => Mutation::Delete { key },
+            _ => return Err(corrupt()),
+        });
+    }
+    Ok(mutations)
+}
+
+/// The state of a database's change log.
+pub(crate) struct ChangeLog {
+    // the last sequence number handed out
+    last: Mutex<u64>,
+}
+
+impl ChangeLog {
+    pub(crate) fn new() -> ChangeLog {
+        ChangeLog {
+            last: Mutex::new(0),
+        }
+    }
+
+    fn last(&self) -> MutexGuard<'_, u64> {
+        self.last.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// continue numbering after the entries already in `database`.
+    pub(crate) fn recover(&self, database: &Database) -> Result<(), Error> {
+        let read_options = ReadOptions::default();
+        let truncated = match database.get_bytes_raw(&read_options, TRUNCATED_KEY)? {
+            Some(value) => read_u64(&value).unwrap_or(0),
+            None => 0,
+        };
+        let mut iter = database.iter(&read_options).raw();
+        iter.seek(TRUNCATED_KEY);
+        if iter.valid() {
+            iter.prev();
+        } else {
+            iter.seek_to_last();
+        }
+        let newest = if iter.valid() && iter.key().starts_with(PREFIX) {
+            read_u64(&iter.key()[PREFIX.len()..]).unwrap_or(0)
+        } else {
+            0
+        };
+        *self.last() = newest.max(truncated);
+        Ok(())
+    }
+
+    /// write `batch` together with its log entry.
+    ///
+    /// # Safety
+    ///
+    /// Same as `Database::write_raw`.
+    pub(crate) unsafe fn write(
+        &self,
+        database: &Database,
+        options: &WriteOptions,
+        batch: &Writebatch,
+    ) -> Result<(), Error> {
+        let (entry, count) = encode_batch(batch);
+        if count == 0 {
+            return database.write_raw(options, batch);
+        }
+        let mut last = self.last();
+        let seq = *last + 1;
+        let mut logged = Writebatch::new();
+        logged.append(batch);
+        logged.put(&entry_key(seq), &entry);
+        database.write_raw(options, &logged)?;
+        *last = seq;
+        Ok(())
+    }
+}
+
+/// An iterator over change log entries, in sequence order.
+///
+/// Reads from the state of the database when it was created.
+pub struct Changes<'a> {
+    iter: DatabaseIterator<'a>,
+}
+
+impl<'a> Iterator for Changes<'a> {
+    type Item = Result<ChangeRecord, Error>;
+
+    fn next(&mut self) -> Option<Result<ChangeRecord, Error>> {
+        if !self.iter.valid() || !self.iter.key().starts_with(PREFIX) {
+            return None;
+        }
+        let record = match read_u64(&self.iter.key()[PREFIX.len()..]) {
+            Some(seq) => {
+                decode_mutations(self.iter.value()).map(|mutations| ChangeRecord { seq, mutations })
+            }
+            None => Err(Error::new("corrupt change log key".to_string())),
+        };
+        self.iter.next();
+        Some(record)
+    }
+}
+
+impl Database {
+    fn change_log(&self) -> Result<&ChangeLog, Error> {
+        self.change_log
+            .as_deref()
+            .ok_or_else(|| Error::new("database not opened with change_log".to_string()))
+    }
+
+    /// The sequence number of the newest change log entry, or 0 if nothing
+    /// was logged yet.
+    pub fn last_change_seq(&self) -> Result<u64, Error> {
+        Ok(*self.change_log()?.last())
+    }
+
+    /// iterate over the change log entries with a sequence number greater
+    /// than `seq`.
+    ///
+    /// Pass 0 to read the whole log, or the last sequence number processed
+    /// to continue after it.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes<'_>, Error> {
+        self.change_log()?;
+        let mut iter = self.iter(&ReadOptions::default()).raw();
+        iter.seek(&entry_key(seq.saturating_add(1)));
+        Ok(Changes { iter })
+    }
+
+    /// delete the change log entries with a sequence number less than
+    /// `seq`, in batches.
+    ///
+    /// Returns the number of entries deleted.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn truncate_changes_before(
+        &self,
+        options: &WriteOptions,
+        seq: u64,
+    ) -> Result<usize, Error> {
+        self.change_log()?;
+        let read_options = ReadOptions::default();
+        let mut truncated = 0;
+        loop {
+            let mut batch = Writebatch::new();
+            let mut highest = None;
+            let mut entries = 0;
+            {
+                let mut iter = self.iter(&read_options).raw();
+                iter.seek(PREFIX);
+                while entries < TRUNCATE_BATCH && iter.valid() && iter.key().starts_with(PREFIX) {
+                    match read_u64(&iter.key()[PREFIX.len()..]) {
+                        Some(entry) if entry >= seq => break,
+                        Some(entry) => highest = Some(entry),
+                        None => {}
+                    }
+                    batch.delete(iter.key());
+                    entries += 1;
+                    iter.next();
+                }
+            }
+            if let Some(highest) = highest {
+                let previous = match self.get_bytes_raw(&read_options, TRUNCATED_KEY)? {
+                    Some(value) => read_u64(&value).unwrap_or(0),
+                    None => 0,
+                };
+                batch.put(TRUNCATED_KEY, &previous.max(highest).to_be_bytes());
+            }
+            if entries > 0 {
+                // removing entries is not itself logged
+                unsafe { self.write_raw(options, &batch)? };
+            }
+            truncated += entries;
+            if entries < TRUNCATE_BATCH {
+                return Ok(truncated);
+            }
+        }
+    }
+}
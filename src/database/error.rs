@@ -6,7 +6,7 @@ use std;
 
 /// A leveldb error, just containing the error string
 /// provided by leveldb.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     message: String,
 }
@@ -14,13 +14,17 @@ pub struct Error {
 impl Error {
     /// create a new Error, using the String provided
     pub fn new(message: String) -> Error {
-        Error { message: message }
+        Error { message }
     }
 
     /// create an error from a c-string buffer.
     ///
     /// This method is `unsafe` because the pointer must be valid and point to heap.
     /// The pointer will be passed to `free`!
+    ///
+    /// # Safety
+    ///
+    /// `message` must be a valid, nul-terminated string allocated by leveldb.
     pub unsafe fn new_from_i8(message: *const i8) -> Error {
         use std::str::from_utf8;
         use std::ffi::CStr;
@@ -43,7 +47,7 @@ impl std::error::Error for Error {
     fn description(&self) -> &str {
         &self.message
     }
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         None
     }
 }
@@ -0,0 +1,366 @@
+//! Logical export and import of key-value pairs
+//!
+//! `export` writes the user data of a database, or of a range of keys, as
+//! a stream independent of leveldb's file formats and version, and `import`
+//! writes such a stream into a database. The binary format starts with a
+//! header, all integers big endian:
+//!
+//! * the magic bytes `LDBX`
+//! * the format version, as a `u32`
+//! * the name of the comparator, as a `u32` length followed by the name
+//! * the number of entries, as a `u64`
+//! * the CRC32C checksum of all entry bytes, as a `u32`
+//!
+//! followed by the entries, each a `u32` key length, the key, a `u32`
+//! value length and the value.
+//!
+//! `export_json_lines` and `import_json_lines` use a human-readable
+//! alternative: one JSON object per line, with the base64 encoded key and
+//! value, like `{"key":"AQI=","value":"aGVsbG8="}`.
+//!
+//! Values are exported as reads return them: expired ttl values are left
+//! out, merge operands are folded in and bookkeeping records are skipped.
+use super::batch::Writebatch;
+use super::error::Error;
+use super::iterator::DatabaseIterator;
+use super::snapshots::Snapshot;
+use super::Database;
+use crate::format::crc32c;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::{Bound, RangeBounds};
+
+const MAGIC: &[u8] = b"LDBX";
+const FORMAT_VERSION: u32 = 1;
+/// The name of the comparator databases opened by this crate use.
+pub const COMPARATOR_NAME: &str = "leveldb.BytewiseComparator";
+
+fn io_error(error: io::Error) -> Error {
+    Error::new(error.to_string())
+}
+
+/// The entries of a snapshot in a key range.
+struct Entries<'a> {
+    iter: Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+/// The plain entries of a database without a merge operator.
+struct Plain<'a>(DatabaseIterator<'a>);
+
+impl<'a> Iterator for Plain<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        if !self.0.valid() {
+            return None;
+        }
+        let entry = (self.0.key().to_vec(), self.0.value().to_vec());
+        self.0.next();
+        Some(entry)
+    }
+}
+
+fn owned(bound: Bound<&&[u8]>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl<'a> Entries<'a> {
+    fn new<'r, R: RangeBounds<&'r [u8]>>(
+        snapshot: &Snapshot<'a>,
+        range: &R,
+    ) -> Result<Entries<'a>, Error> {
+        let options = ReadOptions::default();
+        let seek = match range.start_bound() {
+            Bound::Included(key) | Bound::Excluded(key) => *key,
+            Bound::Unbounded => &[][..],
+        };
+        let iter: Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> =
+            if snapshot.database().merge_operator.is_some() {
+                Box::new(snapshot.merged_iter_from(&options, seek)?)
+            } else {
+                let mut iter = snapshot.iter(&options);
+                iter.seek(seek);
+                Box::new(Plain(iter))
+            };
+        Ok(Entries {
+            iter,
+            start: owned(range.start_bound()),
+            end: owned(range.end_bound()),
+        })
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        loop {
+            let (key, value) = self.iter.next()?;
+            if let Bound::Excluded(ref start) = self.start {
+                if &key == start {
+                    continue;
+                }
+            }
+            let in_range = match self.end {
+                Bound::Included(ref end) => &key <= end,
+                Bound::Excluded(ref end) => &key < end,
+                Bound::Unbounded => true,
+            };
+            return if in_range { Some((key, value)) } else { None };
+        }
+    }
+}
+
+fn encode_entry(out: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    out.clear();
+    out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    out.extend_from_slice(key);
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+/// export the entries in `range` in the binary format.
+///
+/// The header needs the number and checksum of the entries, so they are
+/// read twice, from the same snapshot. Returns the number of entries
+/// written.
+pub fn export<'r, W: Write, R: RangeBounds<&'r [u8]>>(
+    database: &Database,
+    writer: W,
+    range: R,
+) -> Result<u64, Error> {
+    let snapshot = database.snapshot();
+    let mut buf = Vec::new();
+    let mut count = 0u64;
+    let mut checksum = 0;
+    for (key, value) in Entries::new(&snapshot, &range)? {
+        encode_entry(&mut buf, &key, &value);
+        checksum = crc32c::extend(checksum, &buf);
+        count += 1;
+    }
+
+    let mut writer = BufWriter::new(writer);
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    header.extend_from_slice(&(COMPARATOR_NAME.len() as u32).to_be_bytes());
+    header.extend_from_slice(COMPARATOR_NAME.as_bytes());
+    header.extend_from_slice(&count.to_be_bytes());
+    header.extend_from_slice(&checksum.to_be_bytes());
+    writer.write_all(&header).map_err(io_error)?;
+    for (key, value) in Entries::new(&snapshot, &range)? {
+        encode_entry(&mut buf, &key, &value);
+        writer.write_all(&buf).map_err(io_error)?;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(count)
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], Error> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    Ok(buf)
+}
+
+/// read a length-prefixed byte string. The bytes are buffered as they
+/// arrive, so a corrupt length can't make this allocate more than the
+/// stream holds.
+fn read_bytes<R: Read>(reader: &mut R, checksum: &mut u32) -> Result<Vec<u8>, Error> {
+    let len = read_array::<_, 4>(reader)?;
+    let expected = u32::from_be_bytes(len) as usize;
+    let mut bytes = Vec::new();
+    reader
+        .take(expected as u64)
+        .read_to_end(&mut bytes)
+        .map_err(io_error)?;
+    if bytes.len() < expected {
+        return Err(io_error(io::ErrorKind::UnexpectedEof.into()));
+    }
+    *checksum = crc32c::extend(crc32c::extend(*checksum, &len), &bytes);
+    Ok(bytes)
+}
+
+/// import entries in the binary format written by `export`.
+///
+/// The entries are collected in a single batch that is only written once
+/// the checksum matched, so a corrupt stream imports nothing. The whole
+/// export is held in memory meanwhile. Returns the number of entries
+/// imported.
+pub fn import<R: Read>(database: &Database, reader: R) -> Result<u64, Error> {
+    let mut reader = BufReader::new(reader);
+    if read_array::<_, 4>(&mut reader)? != MAGIC {
+        return Err(Error::new("not a leveldb export".to_string()));
+    }
+    let version = u32::from_be_bytes(read_array(&mut reader)?);
+    if version != FORMAT_VERSION {
+        return Err(Error::new(format!(
+            "unsupported export format version {}",
+            version
+        )));
+    }
+    let mut unchecked = 0;
+    let comparator = read_bytes(&mut reader, &mut unchecked)?;
+    if comparator != COMPARATOR_NAME.as_bytes() {
+        return Err(Error::new(format!(
+            "export was sorted by comparator {}",
+            String::from_utf8_lossy(&comparator)
+        )));
+    }
+    let count = u64::from_be_bytes(read_array(&mut reader)?);
+    let expected = u32::from_be_bytes(read_array(&mut reader)?);
+
+    let mut checksum = 0;
+    let mut batch = Writebatch::new();
+    for _ in 0..count {
+        let key = read_bytes(&mut reader, &mut checksum)?;
+        let value = read_bytes(&mut reader, &mut checksum)?;
+        batch.put(&key, &value);
+    }
+    if checksum != expected {
+        return Err(Error::new("export checksum mismatch".to_string()));
+    }
+    database.write(&WriteOptions::default(), &batch)?;
+    Ok(count)
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(out: &mut String, bytes: &[u8]) {
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|&b| b == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// export the entries in `range` as JSON Lines.
+///
+/// Returns the number of entries written.
+pub fn export_json_lines<'r, W: Write, R: RangeBounds<&'r [u8]>>(
+    database: &Database,
+    writer: W,
+    range: R,
+) -> Result<u64, Error> {
+    let snapshot = database.snapshot();
+    let mut writer = BufWriter::new(writer);
+    let mut line = String::new();
+    let mut count = 0;
+    for (key, value) in Entries::new(&snapshot, &range)? {
+        line.clear();
+        line.push_str("{\"key\":\"");
+        base64_encode(&mut line, &key);
+        line.push_str("\",\"value\":\"");
+        base64_encode(&mut line, &value);
+        line.push_str("\"}\n");
+        writer.write_all(line.as_bytes()).map_err(io_error)?;
+        count += 1;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(count)
+}
+
+/// parse a line of a JSON Lines export into its key and value.
+///
+/// Only objects with string members are accepted, which is all
+/// `export_json_lines` writes.
+fn parse_json_line(line: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    fn string(input: &mut &str) -> Option<String> {
+        *input = input.trim_start().strip_prefix('"')?;
+        let mut out = String::new();
+        let mut chars = input.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    *input = &input[i + 1..];
+                    return Some(out);
+                }
+                '\\' => match chars.next()?.1 {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    c => out.push(c),
+                },
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    let mut input = line.trim().strip_prefix('{')?;
+    let (mut key, mut value) = (None, None);
+    loop {
+        let name = string(&mut input)?;
+        input = input.trim_start().strip_prefix(':')?;
+        let member = string(&mut input)?;
+        match &name[..] {
+            "key" => key = Some(base64_decode(&member)?),
+            "value" => value = Some(base64_decode(&member)?),
+            _ => {}
+        }
+        input = input.trim_start();
+        if let Some(rest) = input.strip_prefix(',') {
+            input = rest;
+        } else {
+            if !input.strip_prefix('}')?.trim().is_empty() {
+                return None;
+            }
+            return Some((key?, value?));
+        }
+    }
+}
+
+/// import entries from JSON Lines written by `export_json_lines`.
+///
+/// Empty lines are skipped and an invalid line is reported with its line
+/// number. Like `import`, entries are written in one batch once all lines
+/// were read, so an invalid line imports nothing. Returns the number of
+/// entries imported.
+pub fn import_json_lines<R: Read>(database: &Database, reader: R) -> Result<u64, Error> {
+    let mut batch = Writebatch::new();
+    let mut count = 0;
+    for (number, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = parse_json_line(&line)
+            .ok_or_else(|| Error::new(format!("invalid entry on line {}", number + 1)))?;
+        batch.put(&key, &value);
+        count += 1;
+    }
+    database.write(&WriteOptions::default(), &batch)?;
+    Ok(count)
+}
@@ -0,0 +1,185 @@
+//! Group commit for synchronous writes
+//!
+//! Every synchronous write pays for an fsync of the log. A
+//! `GroupCommitWriter` lets concurrent synchronous writers share one: the
+//! first writer to arrive becomes the leader, waits for a short window,
+//! then writes everything queued in the meantime as a single batch with
+//! one sync and hands the result to all writers in the group.
+use super::batch::Writebatch;
+use super::error::Error;
+use super::Database;
+use crate::options::WriteOptions;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+enum Status {
+    Waiting,
+    Lead,
+    Done(Result<(), Error>),
+}
+
+struct Completion {
+    status: Mutex<Status>,
+    changed: Condvar,
+}
+
+impl Completion {
+    fn set(&self, status: Status) {
+        *self.status.lock().unwrap_or_else(PoisonError::into_inner) = status;
+        self.changed.notify_one();
+    }
+}
+
+struct Request {
+    batch: Writebatch,
+    completion: Arc<Completion>,
+}
+
+struct State {
+    queue: VecDeque<Request>,
+    leader: bool,
+}
+
+/// Coalesces concurrent synchronous writes into shared batches.
+pub struct GroupCommitWriter {
+    database: Arc<Database>,
+    window: Duration,
+    state: Mutex<State>,
+}
+
+impl GroupCommitWriter {
+    /// create a writer that collects writes for `window` before committing
+    /// them. Writes queued while a group is being committed form the next
+    /// group, even with an empty window.
+    pub fn new(database: Arc<Database>, window: Duration) -> GroupCommitWriter {
+        GroupCommitWriter {
+            database,
+            window,
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                leader: false,
+            }),
+        }
+    }
+
+    /// The underlying database.
+    pub fn database(&self) -> &Arc<Database> {
+        &self.database
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// put a value, as part of the next group.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn put(&self, options: &WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        batch.put(key, value);
+        self.write(options, batch)
+    }
+
+    /// delete a value, as part of the next group.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn delete(&self, options: &WriteOptions, key: &[u8]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        batch.delete(key);
+        self.write(options, batch)
+    }
+
+    /// write a batch atomically, as part of the next group.
+    ///
+    /// Writes with `options.sync == false` have no sync to share and go
+    /// straight to the database. Otherwise this returns once the group
+    /// containing the batch was written and synced; if that failed, every
+    /// writer in the group gets the error.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn write(&self, options: &WriteOptions, batch: Writebatch) -> Result<(), Error> {
+        if !options.sync {
+            return self.database.write(options, &batch);
+        }
+
+        let completion = Arc::new(Completion {
+            status: Mutex::new(Status::Waiting),
+            changed: Condvar::new(),
+        });
+        let lead = {
+            let mut state = self.state();
+            state.queue.push_back(Request {
+                batch,
+                completion: completion.clone(),
+            });
+            !std::mem::replace(&mut state.leader, true)
+        };
+        if lead {
+            self.lead();
+        }
+
+        loop {
+            let mut status = completion
+                .status
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            loop {
+                match std::mem::replace(&mut *status, Status::Waiting) {
+                    Status::Waiting => {
+                        status = completion
+                            .changed
+                            .wait(status)
+                            .unwrap_or_else(PoisonError::into_inner)
+                    }
+                    Status::Lead => break,
+                    Status::Done(result) => return result,
+                }
+            }
+            drop(status);
+            self.lead();
+        }
+    }
+
+    /// commit one group, then pass leadership on to the next writer.
+    fn lead(&self) {
+        if self.window > Duration::from_secs(0) {
+            thread::sleep(self.window);
+        }
+        let mut leadership = Leadership {
+            writer: self,
+            group: self.state().queue.drain(..).collect(),
+        };
+
+        let mut combined = Writebatch::new();
+        for request in &leadership.group {
+            combined.append(&request.batch);
+        }
+        let result = self.database.write(&WriteOptions { sync: true }, &combined);
+        for request in leadership.group.drain(..) {
+            request.completion.set(Status::Done(result.clone()));
+        }
+    }
+}
+
+/// The group a leader is committing. Dropping it passes leadership on,
+/// also when committing panicked, after failing the requests still in the
+/// group.
+struct Leadership<'a> {
+    writer: &'a GroupCommitWriter,
+    group: Vec<Request>,
+}
+
+impl<'a> Drop for Leadership<'a> {
+    fn drop(&mut self) {
+        for request in self.group.drain(..) {
+            request.completion.set(Status::Done(Err(Error::new(
+                "group commit leader panicked".to_string(),
+            ))));
+        }
+        let mut state = self.writer.state();
+        match state.queue.front() {
+            Some(next) => next.completion.set(Status::Lead),
+            None => state.leader = false,
+        }
+    }
+}
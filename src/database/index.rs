@@ -0,0 +1,233 @@
+//! Secondary indexes
+//!
+//! An `IndexedDatabase` wraps a `Database` together with a set of named
+//! index functions. Each index function maps a key and value to the index
+//! values it should be found under. Every write through the
+//! `IndexedDatabase` updates the affected index entries in the same batch,
+//! reading the previous values to remove stale entries.
+//!
+//! Index entries are stored under the reserved key prefix `\xff\xff`. Writes
+//! made directly to the underlying `Database`, including merges, bypass the
+//! indexes; `rebuild_index` brings an index back in sync.
+use super::batch::{Writebatch, WritebatchIterator};
+use super::error::Error;
+use super::reserved::escape;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{PoisonError, RwLock};
+
+const PREFIX: &[u8] = b"\xff\xffindex\x00";
+const REBUILD_BATCH: usize = 1000;
+
+type IndexFn = dyn Fn(&[u8], &[u8]) -> Vec<Vec<u8>> + Send + Sync;
+
+/// A database with automatically maintained secondary indexes.
+pub struct IndexedDatabase {
+    database: Database,
+    indexes: BTreeMap<String, Box<IndexFn>>,
+    // held exclusively while an index is rebuilt
+    rebuild: RwLock<()>,
+}
+
+fn entry_prefix(index: &str, value: &[u8]) -> Vec<u8> {
+    let mut prefix = PREFIX.to_vec();
+    escape(&mut prefix, index.as_bytes());
+    escape(&mut prefix, value);
+    prefix
+}
+
+fn entry_key(index: &str, value: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut entry = entry_prefix(index, value);
+    entry.extend_from_slice(key);
+    entry
+}
+
+/// the final state of every key written by a batch.
+struct Changes(BTreeMap<Vec<u8>, Option<Vec<u8>>>);
+
+impl WritebatchIterator for Changes {
+    fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    fn deleted(&mut self, key: &[u8]) {
+        self.0.insert(key.to_vec(), None);
+    }
+}
+
+impl IndexedDatabase {
+    /// wrap a database. Indexes have to be registered with `add_index`.
+    pub fn new(database: Database) -> IndexedDatabase {
+        IndexedDatabase {
+            database,
+            indexes: BTreeMap::new(),
+            rebuild: RwLock::new(()),
+        }
+    }
+
+    /// register an index function under `name`.
+    ///
+    /// Indexes must be registered every time the database is opened. If the
+    /// function changed, or existing data was written without it, call
+    /// `rebuild_index`.
+    pub fn add_index<F>(&mut self, name: &str, index: F)
+    where
+        F: Fn(&[u8], &[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.indexes.insert(name.to_string(), Box::new(index));
+    }
+
+    /// The underlying database.
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    /// Unwrap the underlying database.
+    pub fn into_inner(self) -> Database {
+        self.database
+    }
+
+    /// put a value, updating all indexes.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn put(&self, options: &WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        batch.put(key, value);
+        self.write(options, &batch)
+    }
+
+    /// delete a value, updating all indexes.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn delete(&self, options: &WriteOptions, key: &[u8]) -> Result<(), Error> {
+        let mut batch = Writebatch::new();
+        batch.delete(key);
+        self.write(options, &batch)
+    }
+
+    /// write a batch atomically, together with the index updates it causes.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn write(&self, options: &WriteOptions, batch: &Writebatch) -> Result<(), Error> {
+        let _rebuild = self.rebuild.read().unwrap_or_else(PoisonError::into_inner);
+        let changes = batch.iterate(Box::new(Changes(BTreeMap::new()))).0;
+        let _locks = self
+            .database
+            .locks
+            .lock_many(changes.keys().map(|k| &k[..]));
+
+        let mut updated = Writebatch::new();
+        updated.append(batch);
+        let read_options = ReadOptions::default();
+        for (key, value) in &changes {
+            let old = self.database.get(&read_options, key)?;
+            for (name, index) in &self.indexes {
+                let stale: BTreeSet<Vec<u8>> = match old {
+                    Some(ref old) => index(key, old).into_iter().collect(),
+                    None => BTreeSet::new(),
+                };
+                let fresh: BTreeSet<Vec<u8>> = match *value {
+                    Some(ref value) => index(key, value).into_iter().collect(),
+                    None => BTreeSet::new(),
+                };
+                for v in stale.difference(&fresh) {
+                    updated.delete(&entry_key(name, v, key));
+                }
+                for v in fresh.difference(&stale) {
+                    updated.put(&entry_key(name, v, key), &[]);
+                }
+            }
+        }
+        // the locks of the user keys cover the index entries, which no
+        // merge operand is ever added to
+        self.database.write_locked(options, &updated)
+    }
+
+    /// get the keys whose values are indexed under `value` in `index`, in
+    /// key order.
+    pub fn lookup_keys_by_index(
+        &self,
+        options: &ReadOptions,
+        index: &str,
+        value: &[u8],
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let prefix = entry_prefix(index, value);
+        let mut keys = Vec::new();
+        let mut iter = self.database.iter(options).raw();
+        iter.seek(&prefix);
+        while iter.valid() && iter.key().starts_with(&prefix) {
+            keys.push(iter.key()[prefix.len()..].to_vec());
+            iter.next();
+        }
+        Ok(keys)
+    }
+
+    /// get the keys and values indexed under `value` in `index`.
+    pub fn lookup_by_index(
+        &self,
+        options: &ReadOptions,
+        index: &str,
+        value: &[u8],
+    ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, Error> {
+        let mut entries = BTreeMap::new();
+        for key in self.lookup_keys_by_index(options, index, value)? {
+            if let Some(value) = self.database.get(options, &key)? {
+                entries.insert(key, value);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// drop all entries of an index and recompute them from the stored data.
+    ///
+    /// Writes through this `IndexedDatabase` wait until the rebuild is
+    /// done. Returns the number of entries written.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn rebuild_index(&self, options: &WriteOptions, name: &str) -> Result<usize, Error> {
+        let index = match self.indexes.get(name) {
+            Some(index) => index,
+            None => return Err(Error::new(format!("unknown index {}", name))),
+        };
+        let _rebuild = self.rebuild.write().unwrap_or_else(PoisonError::into_inner);
+        let read_options = ReadOptions::default();
+
+        let mut prefix = PREFIX.to_vec();
+        escape(&mut prefix, name.as_bytes());
+        let mut batch = Writebatch::new();
+        let mut pending = 0;
+        {
+            let mut iter = self.database.iter(&read_options).raw();
+            iter.seek(&prefix);
+            while iter.valid() && iter.key().starts_with(&prefix) {
+                batch.delete(iter.key());
+                pending += 1;
+                if pending == REBUILD_BATCH {
+                    self.database.write(options, &batch)?;
+                    batch.clear();
+                    pending = 0;
+                }
+                iter.next();
+            }
+        }
+
+        let mut written = 0;
+        let mut iter = self.database.iter(&read_options);
+        iter.seek_to_first();
+        while iter.valid() {
+            for value in index(iter.key(), iter.value()) {
+                batch.put(&entry_key(name, &value, iter.key()), &[]);
+                pending += 1;
+                written += 1;
+            }
+            if pending >= REBUILD_BATCH {
+                self.database.write(options, &batch)?;
+                batch.clear();
+                pending = 0;
+            }
+            iter.next();
+        }
+        if pending > 0 {
+            self.database.write(options, &batch)?;
+        }
+        Ok(written)
+    }
+}
@@ -3,11 +3,14 @@
 //! Iteration is one of the most important parts of leveldb. This module provides
 //! Iterators to iterate over key, values and pairs of both.
 use super::options::{c_readoptions, ReadOptions};
+use super::reserved::RESERVED_PREFIX;
+use super::ttl;
 use super::Database;
 use leveldb_sys::{
     leveldb_create_iterator, leveldb_iter_destroy, leveldb_iter_key, leveldb_iter_next,
     leveldb_iter_prev, leveldb_iter_seek, leveldb_iter_seek_to_first, leveldb_iter_seek_to_last,
     leveldb_iter_valid, leveldb_iter_value, leveldb_iterator_t, leveldb_readoptions_destroy,
+    leveldb_readoptions_t,
 };
 use libc::{c_char, size_t};
 use std::marker::PhantomData;
@@ -15,24 +18,100 @@ use std::slice::from_raw_parts;
 
 /// An iterator over the leveldb keyspace.
 ///
-/// Returns key and value as a tuple.
+/// Returns key and value as a tuple. Bookkeeping records under the
+/// reserved key prefix `\xff\xff` are skipped.
 pub struct DatabaseIterator<'a> {
     // Iterator accesses the Database through a leveldb_iter_t pointer
     // but needs to hold the reference for lifetime tracking
     #[allow(dead_code)]
     database: PhantomData<&'a Database>,
     iter: *mut leveldb_iterator_t,
+    // Whether reserved records and expired values are shown.
+    raw: bool,
+    // When the database stores expiry headers, the time (in ms since the
+    // epoch) to hide expired entries against.
+    expiry: Option<u64>,
 }
 
 impl<'a> DatabaseIterator<'a> {
     pub fn new(database: &'a Database, options: &ReadOptions) -> DatabaseIterator<'a> {
         unsafe {
             let c_readoptions = c_readoptions(options);
-            let ptr = leveldb_create_iterator(database.database.ptr, c_readoptions);
+            let iter = DatabaseIterator::new_c(database, c_readoptions);
             leveldb_readoptions_destroy(c_readoptions);
-            DatabaseIterator {
-                iter: ptr,
-                database: PhantomData,
+            iter
+        }
+    }
+
+    pub(crate) unsafe fn new_c(
+        database: &'a Database,
+        c_readoptions: *const leveldb_readoptions_t,
+    ) -> DatabaseIterator<'a> {
+        let ptr = leveldb_create_iterator(database.database.ptr, c_readoptions);
+        DatabaseIterator {
+            iter: ptr,
+            database: PhantomData,
+            raw: false,
+            expiry: if database.ttl {
+                Some(ttl::now_millis())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Show all records as stored, including expiry headers and
+    /// bookkeeping records.
+    pub(crate) fn raw(mut self) -> DatabaseIterator<'a> {
+        self.raw = true;
+        self.expiry = None;
+        self
+    }
+
+    fn hidden(&self) -> bool {
+        match self.expiry {
+            Some(now) => ttl::hidden(self.key(), self.raw_value(), now),
+            None => false,
+        }
+    }
+
+    fn reserved(&self) -> bool {
+        !self.raw && self.key().starts_with(RESERVED_PREFIX)
+    }
+
+    // Reserved keys sort after all user keys, so the skips below jump over
+    // them at once instead of stepping through every bookkeeping record.
+
+    fn skip_forward(&mut self) {
+        while self.valid() {
+            if self.reserved() {
+                unsafe {
+                    leveldb_iter_seek_to_last(self.iter);
+                    leveldb_iter_next(self.iter);
+                }
+            } else if self.hidden() {
+                unsafe { leveldb_iter_next(self.iter) };
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn skip_backward(&mut self) {
+        while self.valid() {
+            if self.reserved() {
+                unsafe {
+                    leveldb_iter_seek(
+                        self.iter,
+                        RESERVED_PREFIX.as_ptr() as *const c_char,
+                        RESERVED_PREFIX.len() as size_t,
+                    );
+                    leveldb_iter_prev(self.iter);
+                }
+            } else if self.hidden() {
+                unsafe { leveldb_iter_prev(self.iter) };
+            } else {
+                break;
             }
         }
     }
@@ -49,12 +128,14 @@ impl<'a> DatabaseIterator<'a> {
 
     pub fn seek_to_first(&mut self) {
         unsafe { leveldb_iter_seek_to_first(self.iter) }
+        self.skip_forward();
     }
 
     pub fn seek_to_last(&mut self) {
         unsafe {
             leveldb_iter_seek_to_last(self.iter);
         }
+        self.skip_backward();
     }
 
     pub fn seek(&mut self, key: &[u8]) {
@@ -65,6 +146,7 @@ impl<'a> DatabaseIterator<'a> {
                 key.len() as size_t,
             );
         }
+        self.skip_forward();
     }
 
     pub fn next(&mut self) {
@@ -73,6 +155,7 @@ impl<'a> DatabaseIterator<'a> {
         unsafe {
             leveldb_iter_next(self.iter);
         }
+        self.skip_forward();
     }
 
     pub fn prev(&mut self) {
@@ -81,8 +164,10 @@ impl<'a> DatabaseIterator<'a> {
         unsafe {
             leveldb_iter_prev(self.iter);
         }
+        self.skip_backward();
     }
 
+    #[allow(clippy::unnecessary_mut_passed)]
     pub fn key(&self) -> &[u8] {
         self.check_valid();
 
@@ -94,6 +179,14 @@ impl<'a> DatabaseIterator<'a> {
     }
 
     pub fn value(&self) -> &[u8] {
+        match self.expiry {
+            Some(_) => ttl::strip_header(self.raw_value()),
+            None => self.raw_value(),
+        }
+    }
+
+    #[allow(clippy::unnecessary_mut_passed)]
+    fn raw_value(&self) -> &[u8] {
         self.check_valid();
 
         unsafe {
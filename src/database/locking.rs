@@ -0,0 +1,348 @@
+//! Pessimistic transactions
+//!
+//! A `LockingTransaction` takes shared or exclusive locks on keys and key
+//! ranges before reading or writing them, and holds them until it is
+//! committed or dropped. Writes are buffered and applied as one batch on
+//! commit.
+//!
+//! Locks are only visible to transactions on the same `Database` handle;
+//! plain reads and writes do not take them. Ranges are compared bytewise.
+//!
+//! A transaction that would have to wait for a lock held, directly or
+//! through other waiting transactions, by itself fails with
+//! `TransactionError::Deadlock`. Waiting longer than the transaction's
+//! timeout fails with `TransactionError::Timeout`. In both cases the
+//! transaction should be dropped to release its locks, and can be retried.
+use super::batch::Writebatch;
+use super::transaction::TransactionError;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The mode a lock is taken in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockMode {
+    /// allows other shared locks on overlapping keys
+    Shared,
+    /// excludes all other locks on overlapping keys
+    Exclusive,
+}
+
+/// A half-open key range `[start, end)`, `end == None` meaning unbounded.
+#[derive(Clone, PartialEq, Eq)]
+struct KeyRange {
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+}
+
+impl KeyRange {
+    fn key(key: &[u8]) -> KeyRange {
+        let mut end = key.to_vec();
+        end.push(0);
+        KeyRange {
+            start: key.to_vec(),
+            end: Some(end),
+        }
+    }
+
+    fn overlaps(&self, other: &KeyRange) -> bool {
+        let before_end = |start: &[u8], end: &Option<Vec<u8>>| match end {
+            Some(end) => start < &end[..],
+            None => true,
+        };
+        before_end(&self.start, &other.end) && before_end(&other.start, &self.end)
+    }
+
+    fn contains(&self, other: &KeyRange) -> bool {
+        self.start <= other.start
+            && match (&self.end, &other.end) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(end), Some(other_end)) => other_end <= end,
+            }
+    }
+}
+
+struct HeldLock {
+    owner: u64,
+    range: KeyRange,
+    mode: LockMode,
+}
+
+#[derive(Default)]
+struct LockState {
+    held: Vec<HeldLock>,
+    waits_for: HashMap<u64, HashSet<u64>>,
+}
+
+impl LockState {
+    fn blockers(&self, owner: u64, range: &KeyRange, mode: LockMode) -> HashSet<u64> {
+        self.held
+            .iter()
+            .filter(|lock| lock.owner != owner)
+            .filter(|lock| mode == LockMode::Exclusive || lock.mode == LockMode::Exclusive)
+            .filter(|lock| lock.range.overlaps(range))
+            .map(|lock| lock.owner)
+            .collect()
+    }
+
+    fn already_held(&self, owner: u64, range: &KeyRange, mode: LockMode) -> bool {
+        self.held.iter().any(|lock| {
+            lock.owner == owner
+                && (lock.mode == LockMode::Exclusive || mode == LockMode::Shared)
+                && lock.range.contains(range)
+        })
+    }
+
+    /// whether `owner` is reachable from itself in the wait-for graph.
+    fn deadlocked(&self, owner: u64) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<u64> = self
+            .waits_for
+            .get(&owner)
+            .map(|s| s.iter().cloned().collect())
+            .unwrap_or_default();
+        while let Some(next) = stack.pop() {
+            if next == owner {
+                return true;
+            }
+            if seen.insert(next) {
+                if let Some(blockers) = self.waits_for.get(&next) {
+                    stack.extend(blockers.iter().cloned());
+                }
+            }
+        }
+        false
+    }
+}
+
+/// The lock table shared by all `LockingTransaction`s on a database.
+pub(crate) struct LockTable {
+    next_owner: AtomicU64,
+    state: Mutex<LockState>,
+    released: Condvar,
+}
+
+impl LockTable {
+    pub(crate) fn new() -> LockTable {
+        LockTable {
+            next_owner: AtomicU64::new(1),
+            state: Mutex::new(LockState::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn acquire(
+        &self,
+        owner: u64,
+        range: KeyRange,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<(), TransactionError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state();
+        loop {
+            if state.already_held(owner, &range, mode) {
+                state.waits_for.remove(&owner);
+                return Ok(());
+            }
+            let blockers = state.blockers(owner, &range, mode);
+            if blockers.is_empty() {
+                state.waits_for.remove(&owner);
+                state.held.push(HeldLock { owner, range, mode });
+                return Ok(());
+            }
+            state.waits_for.insert(owner, blockers);
+            if state.deadlocked(owner) {
+                state.waits_for.remove(&owner);
+                return Err(TransactionError::Deadlock);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                state.waits_for.remove(&owner);
+                return Err(TransactionError::Timeout);
+            }
+            state = self
+                .released
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn release_all(&self, owner: u64) {
+        let mut state = self.state();
+        state.held.retain(|lock| lock.owner != owner);
+        state.waits_for.remove(&owner);
+        for blockers in state.waits_for.values_mut() {
+            blockers.remove(&owner);
+        }
+        self.released.notify_all();
+    }
+}
+
+/// A pessimistic transaction
+///
+/// See the module documentation for details.
+pub struct LockingTransaction<'a> {
+    database: &'a Database,
+    owner: u64,
+    timeout: Duration,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Database {
+    /// start a new pessimistic transaction, which waits at most `timeout`
+    /// for each lock it takes.
+    pub fn locking_transaction(&self, timeout: Duration) -> LockingTransaction<'_> {
+        LockingTransaction {
+            database: self,
+            owner: self.lock_table.next_owner.fetch_add(1, Ordering::SeqCst),
+            timeout,
+            writes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'a> LockingTransaction<'a> {
+    /// lock a single key.
+    pub fn lock_key(&mut self, key: &[u8], mode: LockMode) -> Result<(), TransactionError> {
+        self.database
+            .lock_table
+            .acquire(self.owner, KeyRange::key(key), mode, self.timeout)
+    }
+
+    /// lock all keys in `[start, end)`, or all keys from `start` on if `end`
+    /// is `None`.
+    pub fn lock_range(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        mode: LockMode,
+    ) -> Result<(), TransactionError> {
+        let range = KeyRange {
+            start: start.to_vec(),
+            end: end.map(|e| e.to_vec()),
+        };
+        self.database
+            .lock_table
+            .acquire(self.owner, range, mode, self.timeout)
+    }
+
+    /// take a shared lock on `key` and read it, including this
+    /// transaction's own writes.
+    pub fn get(
+        &mut self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.lock_key(key, LockMode::Shared)?;
+        self.read(options, key)
+    }
+
+    /// take an exclusive lock on `key` and read it, including this
+    /// transaction's own writes.
+    pub fn get_for_update(
+        &mut self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.lock_key(key, LockMode::Exclusive)?;
+        self.read(options, key)
+    }
+
+    fn read(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        Ok(self.database.get(options, key)?)
+    }
+
+    /// take a shared lock on `[start, end)` and read all entries in it,
+    /// including this transaction's own writes.
+    pub fn scan(
+        &mut self,
+        options: &ReadOptions,
+        start: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, TransactionError> {
+        self.lock_range(start, end, LockMode::Shared)?;
+
+        let in_range = |key: &[u8]| match end {
+            Some(end) => key < end,
+            None => true,
+        };
+        let mut entries = BTreeMap::new();
+        if self.database.merge_operator.is_some() {
+            let snapshot = self.database.snapshot();
+            for (key, value) in snapshot.merged_iter_from(options, start)? {
+                if !in_range(&key) {
+                    break;
+                }
+                entries.insert(key, value);
+            }
+        } else {
+            let mut iter = self.database.iter(options);
+            iter.seek(start);
+            while iter.valid() && in_range(iter.key()) {
+                entries.insert(iter.key().to_vec(), iter.value().to_vec());
+                iter.next();
+            }
+        }
+        for (key, value) in self.writes.range(start.to_vec()..) {
+            if !in_range(key) {
+                break;
+            }
+            match *value {
+                Some(ref value) => entries.insert(key.clone(), value.clone()),
+                None => entries.remove(key),
+            };
+        }
+        Ok(entries)
+    }
+
+    /// take an exclusive lock on `key` and buffer a put.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), TransactionError> {
+        self.lock_key(key, LockMode::Exclusive)?;
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    /// take an exclusive lock on `key` and buffer a delete.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), TransactionError> {
+        self.lock_key(key, LockMode::Exclusive)?;
+        self.writes.insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    /// apply all buffered writes as one batch and release all locks.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn commit(self, options: &WriteOptions) -> Result<(), TransactionError> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+        let mut batch = Writebatch::new();
+        for (key, value) in &self.writes {
+            match *value {
+                Some(ref value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+        Ok(self.database.write(options, &batch)?)
+    }
+}
+
+#[allow(missing_docs)]
+impl<'a> Drop for LockingTransaction<'a> {
+    fn drop(&mut self) {
+        self.database.lock_table.release_all(self.owner);
+    }
+}
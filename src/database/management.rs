@@ -1,8 +1,21 @@
 //! Management functions, e.g. for destroying and reparing a database.
+use crate::batch::Writebatch;
+use crate::database::change_log::is_log_key;
+use crate::database::ttl::MODE_KEY;
+use crate::database::Database;
 use crate::error::Error;
-use crate::options::{c_options, Options};
+use crate::format;
+use crate::format::log::{LogReader, WriteBatch};
+use crate::format::manifest::{FileMetaData, Manifest, Version, VersionEdit, NUM_LEVELS};
+use crate::format::table::Table;
+use crate::format::{FormatError, InternalKey, ValueType, BYTEWISE_COMPARATOR};
+use crate::options::{c_options, Options, WriteOptions};
+use std::collections::BTreeMap;
 use std::ffi::CString;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use std::ptr;
 
 use leveldb_sys::{leveldb_destroy_db, leveldb_repair_db};
@@ -46,3 +59,729 @@ pub fn repair(name: &Path, options: &Options) -> Result<(), Error> {
         }
     }
 }
+
+// how often to retry a copy that raced with a compaction
+const COPY_ATTEMPTS: usize = 10;
+
+fn io_error(error: io::Error) -> Error {
+    Error::new(error.to_string())
+}
+
+/// the MANIFEST named by CURRENT, and its size.
+fn current_manifest(dir: &Path) -> io::Result<(PathBuf, u64)> {
+    let path = format::manifest::current_manifest(dir)?;
+    let len = fs::metadata(&path)?.len();
+    Ok((path, len))
+}
+
+/// link or copy the files of the database in `src` to `dest`.
+///
+/// Only the tables the MANIFEST lists are linked: a table still being
+/// written by a compaction would be recreated, and truncated, when the copy
+/// reuses its file number.
+fn copy_files(src: &Path, dest: &Path, manifest_path: &Path) -> io::Result<()> {
+    let manifest_data = fs::read(manifest_path)?;
+    let manifest = Manifest::read(&manifest_data[..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    // damage at the end is taken to be a record still being appended
+    if manifest.corruptions.len() > manifest.trailing_corruptions {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unreadable MANIFEST: {}", manifest.corruptions[0]),
+        ));
+    }
+    let version = &manifest.version;
+    for number in version.live_files() {
+        let mut name = format!("{:06}.ldb", number);
+        if !src.join(&name).exists() {
+            name = format!("{:06}.sst", number);
+        }
+        // tables are immutable, so the copy can share them
+        if fs::hard_link(src.join(&name), dest.join(&name)).is_err() {
+            fs::copy(src.join(&name), dest.join(&name))?;
+        }
+    }
+    // copied after the tables, so that data flushed from a log into a table
+    // in the meantime is still found in one of them
+    for entry in fs::read_dir(src)? {
+        let name = entry?.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let number = name
+            .strip_suffix(".log")
+            .and_then(|number| number.parse::<u64>().ok());
+        match number {
+            Some(number) if number >= version.min_log_number() => {
+                fs::copy(src.join(name), dest.join(name))?;
+            }
+            _ => {}
+        }
+    }
+    let name = manifest_path.file_name().unwrap_or_default();
+    fs::write(dest.join(name), manifest_data)?;
+    fs::write(
+        dest.join("CURRENT"),
+        format!("{}\n", name.to_string_lossy()),
+    )
+}
+
+fn clear_dir(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        fs::remove_file(entry?.path())?;
+    }
+    Ok(())
+}
+
+/// copy the files of the database in `src` into the empty directory
+/// `dest`, starting over if a compaction changes the set of files
+/// meanwhile.
+pub(crate) fn copy_database(src: &Path, dest: &Path) -> Result<(), Error> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let (manifest, len) = current_manifest(src).map_err(io_error)?;
+        let copied = copy_files(src, dest, &manifest);
+        let unchanged = match current_manifest(src) {
+            Ok((after, after_len)) => after == manifest && after_len == len,
+            Err(_) => false,
+        };
+        match copied {
+            Ok(()) if unchanged => return Ok(()),
+            // a file was deleted by a compaction while copying
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io_error(e)),
+            Ok(()) => {}
+        }
+        if attempts == COPY_ATTEMPTS {
+            return Err(Error::new(
+                "database kept changing while copying it".to_string(),
+            ));
+        }
+        clear_dir(dest).map_err(io_error)?;
+    }
+}
+
+/// create an openable copy of a live database in `dest`, which must not
+/// exist yet.
+///
+/// The table files listed in the MANIFEST are hard-linked where possible,
+/// the MANIFEST, CURRENT and live log files are copied. A snapshot is held
+/// while copying; if a compaction changes the set of files anyway, the copy
+/// is started over. Finally the copy is opened to verify it.
+pub fn checkpoint(database: &Database, dest: &Path) -> Result<(), Error> {
+    let src = database.path();
+    if dest.exists() {
+        return Err(Error::new(format!("{} already exists", dest.display())));
+    }
+    fs::create_dir_all(dest).map_err(io_error)?;
+
+    let _snapshot = database.snapshot();
+    copy_database(src, dest)?;
+
+    let options = Options {
+        merge_operator: database.merge_operator.clone(),
+        merge_fold_interval: None,
+        ttl: database.ttl,
+        change_log: database.change_log.is_some(),
+        ..Options::default()
+    };
+    Database::open(dest, options).map(|_| ())
+}
+
+/// A range of user keys, as start and end bound.
+pub type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/// A damaged part of a database, found by `verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// the damaged file, relative to the database directory
+    pub file: String,
+    /// what is wrong with it
+    pub reason: String,
+    /// the user keys whose data may be lost, if known
+    pub keys: Option<KeyRange>,
+}
+
+/// The result of checking a database with `verify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// the number of tables read
+    pub tables: usize,
+    /// the number of entries read from tables
+    pub entries: u64,
+    /// the number of write batches read from logs
+    pub log_records: u64,
+    /// tables the MANIFEST lists that are not on disk
+    pub missing_tables: Vec<FileMetaData>,
+    /// tables on disk the MANIFEST doesn't list. These are usually left
+    /// over from an interrupted compaction, and harmless.
+    pub orphaned_tables: Vec<u64>,
+    /// the damage found
+    pub corruptions: Vec<Corruption>,
+    /// damage at the end of the MANIFEST or the newest log, after their
+    /// last intact record. This is what a crash while appending leaves
+    /// behind, and opening the database drops it.
+    pub warnings: Vec<Corruption>,
+}
+
+impl VerifyReport {
+    /// whether the database is intact, with all its tables present.
+    pub fn is_ok(&self) -> bool {
+        self.missing_tables.is_empty() && self.corruptions.is_empty()
+    }
+}
+
+/// the number of a file named `<number>.<extension>`.
+fn file_number(name: &str, extension: &str) -> Option<u64> {
+    name.strip_suffix(extension)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+/// the names of the table and log files in `dir`, by number.
+fn data_files(dir: &Path) -> io::Result<(BTreeMap<u64, String>, BTreeMap<u64, String>)> {
+    let mut tables = BTreeMap::new();
+    let mut logs = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let name = match name.to_str() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        if let Some(number) = file_number(&name, "ldb").or_else(|| file_number(&name, "sst")) {
+            tables.insert(number, name);
+        } else if let Some(number) = file_number(&name, "log") {
+            logs.insert(number, name);
+        }
+    }
+    Ok((tables, logs))
+}
+
+/// read every entry of a table, passing it to `f`, and report damage
+/// with the keys around it.
+///
+/// `file` is what the MANIFEST says about the table, if known. Returns
+/// false if the table can't be opened at all.
+fn read_table<F>(
+    dir: &Path,
+    name: &str,
+    file: Option<&FileMetaData>,
+    corruptions: &mut Vec<Corruption>,
+    mut f: F,
+) -> bool
+where
+    F: FnMut(InternalKey, Vec<u8>),
+{
+    let smallest = || match file {
+        Some(file) => Bound::Included(file.smallest.user_key.clone()),
+        None => Bound::Unbounded,
+    };
+    let largest = || match file {
+        Some(file) => Bound::Included(file.largest.user_key.clone()),
+        None => Bound::Unbounded,
+    };
+    let mut corrupt = |reason: String, keys| {
+        corruptions.push(Corruption {
+            file: name.to_string(),
+            reason,
+            keys: Some(keys),
+        })
+    };
+    let table = match Table::open(&dir.join(name)) {
+        Ok(table) => table,
+        Err(e) => {
+            corrupt(e.to_string(), (smallest(), largest()));
+            return false;
+        }
+    };
+    if let Some(file) = file {
+        if table.file_size() != file.size {
+            corrupt(
+                format!(
+                    "size is {} bytes, the MANIFEST says {}",
+                    table.file_size(),
+                    file.size
+                ),
+                (smallest(), largest()),
+            );
+        }
+    }
+
+    let mut last: Option<Vec<u8>> = None;
+    // damage still waiting for the next intact key to bound it
+    let mut damaged = Vec::new();
+    for entry in table.iter() {
+        match entry {
+            Ok((key, value)) => {
+                for (reason, start) in damaged.drain(..) {
+                    corrupt(reason, (start, Bound::Excluded(key.user_key.clone())));
+                }
+                last = Some(key.user_key.clone());
+                f(key, value);
+            }
+            Err(e) => {
+                let start = match last {
+                    Some(ref key) => Bound::Excluded(key.clone()),
+                    None => smallest(),
+                };
+                damaged.push((e.to_string(), start));
+            }
+        }
+    }
+    for (reason, start) in damaged {
+        corrupt(reason, (start, largest()));
+    }
+    true
+}
+
+/// read every write batch of a log, passing it to `f`, and report damage.
+/// Returns how many of the reported corruptions come after the last intact
+/// batch.
+fn read_log<F>(
+    dir: &Path,
+    name: &str,
+    corruptions: &mut Vec<Corruption>,
+    mut f: F,
+) -> io::Result<usize>
+where
+    F: FnMut(WriteBatch),
+{
+    let log = File::open(dir.join(name))?;
+    let mut trailing = 0;
+    for record in LogReader::new(BufReader::new(log)) {
+        match record.and_then(|record| WriteBatch::decode(&record)) {
+            Ok(batch) => {
+                f(batch);
+                trailing = 0;
+            }
+            Err(FormatError::Io(e)) => return Err(e),
+            Err(e) => {
+                corruptions.push(Corruption {
+                    file: name.to_string(),
+                    reason: e.to_string(),
+                    keys: None,
+                });
+                trailing += 1;
+            }
+        }
+    }
+    Ok(trailing)
+}
+
+/// check a closed database for damage, without modifying it.
+///
+/// All tables the MANIFEST lists are read with checksums verified, as are
+/// the MANIFEST itself and the logs still holding writes. This covers what
+/// a full scan of the opened database with `verify_checksums: true` would
+/// read, but opening would replay the logs into new tables, so the files
+/// are read directly instead. For damaged tables, the report gives the
+/// range of keys affected; damage in logs or the MANIFEST can't be
+/// attributed to keys. Damage at the end of the MANIFEST or the newest log
+/// is reported as a warning, since a crash leaves a partly written record
+/// there. An error is only returned if the CURRENT file or the MANIFEST
+/// can't be read at all.
+pub fn verify(path: &Path) -> Result<VerifyReport, Error> {
+    let mut report = VerifyReport::default();
+    let manifest_path = format::manifest::current_manifest(path).map_err(io_error)?;
+    let manifest = Manifest::open(&manifest_path).map_err(|e| Error::new(e.to_string()))?;
+    let manifest_name = manifest_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let intact = manifest.corruptions.len() - manifest.trailing_corruptions;
+    for (i, e) in manifest.corruptions.iter().enumerate() {
+        let corruption = Corruption {
+            file: manifest_name.clone(),
+            reason: e.to_string(),
+            keys: None,
+        };
+        if i < intact {
+            report.corruptions.push(corruption);
+        } else {
+            report.warnings.push(corruption);
+        }
+    }
+
+    let (mut tables, logs) = data_files(path).map_err(io_error)?;
+    let version = &manifest.version;
+    for file in version.levels.iter().flatten() {
+        let name = match tables.remove(&file.number) {
+            Some(name) => name,
+            None => {
+                report.missing_tables.push(file.clone());
+                continue;
+            }
+        };
+        let mut entries = 0;
+        if read_table(path, &name, Some(file), &mut report.corruptions, |_, _| {
+            entries += 1
+        }) {
+            report.tables += 1;
+        }
+        report.entries += entries;
+    }
+    report.orphaned_tables = tables.into_keys().collect();
+
+    let newest = logs.keys().next_back().copied();
+    for (&number, name) in logs.range(version.min_log_number()..) {
+        let mut records = 0;
+        let trailing =
+            read_log(path, name, &mut report.corruptions, |_| records += 1).map_err(io_error)?;
+        if Some(number) == newest {
+            let start = report.corruptions.len() - trailing;
+            report.warnings.extend(report.corruptions.drain(start..));
+        }
+        report.log_records += records;
+    }
+    Ok(report)
+}
+
+/// The result of `salvage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// the number of tables read
+    pub tables: usize,
+    /// the number of logs read
+    pub logs: usize,
+    /// the number of entries read, including old versions and deletions
+    pub entries: u64,
+    /// the number of keys written to the destination
+    pub keys: u64,
+    /// the damage found; the data in it is lost
+    pub corruptions: Vec<Corruption>,
+}
+
+/// write a batch of stored entries as they are, without the
+/// transformations `Database::write` applies to user data. The batch is
+/// still recorded in `database`'s change log.
+fn write_stored(database: &Database, batch: &Writebatch) -> Result<(), Error> {
+    let _guard = database.versions.mutation();
+    let publisher = database
+        .watchers
+        .publisher(|events| events.stored_batch(batch, database.ttl));
+    unsafe { database.write_c(&WriteOptions::default(), batch)? };
+    database.versions.bump_batch(batch);
+    if let Some(publisher) = publisher {
+        publisher.publish();
+    }
+    Ok(())
+}
+
+/// recover what can be read from a damaged database in `src` into `dst`,
+/// which should be a new, empty database.
+///
+/// Unlike `repair`, this doesn't modify `src` and doesn't rely on its
+/// MANIFEST: every table and log file in the directory is read, skipping
+/// damaged blocks and records, and for every key the version with the
+/// highest sequence number is kept. Keys whose newest version is a
+/// deletion are left out. The report lists the damage, with the affected
+/// keys where they are known.
+///
+/// Values are copied as stored, together with the bookkeeping records of
+/// merge operators, ttl and secondary indexes, so `dst` has to be opened
+/// with the same `ttl` setting `src` was created with; otherwise salvage
+/// fails before writing anything. `src`'s change log isn't copied: if
+/// `dst` has one, it records the salvaged entries instead.
+///
+/// If the newest version of a key was in a damaged part, an older version
+/// is restored instead. Every key (but not its value) is held in memory.
+pub fn salvage(src: &Path, dst: &Database) -> Result<SalvageReport, Error> {
+    let mut report = SalvageReport::default();
+    let (tables, logs) = data_files(src).map_err(io_error)?;
+    // if the MANIFEST is intact, it gives the key ranges of damaged tables
+    let listed: BTreeMap<u64, FileMetaData> = format::manifest::current_manifest(src)
+        .ok()
+        .and_then(|path| Manifest::open(&path).ok())
+        .map(|manifest| {
+            let files = manifest.version.levels.into_iter().flatten();
+            files.map(|file| (file.number, file)).collect()
+        })
+        .unwrap_or_default();
+
+    // find the sequence number of the newest version of every key, and
+    // whether it is a deletion
+    let mut newest: BTreeMap<Vec<u8>, (u64, ValueType)> = BTreeMap::new();
+    let mut note = |key: &[u8], sequence: u64, value_type: ValueType| match newest.get_mut(key) {
+        Some(version) if version.0 >= sequence => {}
+        Some(version) => *version = (sequence, value_type),
+        None => {
+            newest.insert(key.to_vec(), (sequence, value_type));
+        }
+    };
+    let mut entries = 0;
+    for (number, name) in &tables {
+        let file = listed.get(number);
+        if read_table(src, name, file, &mut report.corruptions, |key, _| {
+            entries += 1;
+            note(&key.user_key, key.sequence, key.value_type);
+        }) {
+            report.tables += 1;
+        }
+    }
+    for name in logs.values() {
+        read_log(src, name, &mut report.corruptions, |batch| {
+            for (i, entry) in batch.entries.iter().enumerate() {
+                entries += 1;
+                note(&entry.key, batch.sequence + i as u64, entry.value_type);
+            }
+        })
+        .map_err(io_error)?;
+        report.logs += 1;
+    }
+    report.entries = entries;
+
+    let src_ttl = matches!(newest.get(MODE_KEY), Some((_, ValueType::Value)));
+    if src_ttl != dst.ttl {
+        let setting = if src_ttl { "with" } else { "without" };
+        return Err(Error::new(format!(
+            "{} was created {} ttl, open the destination {} Options::ttl",
+            src.display(),
+            setting,
+            setting
+        )));
+    }
+    newest.retain(|key, _| !is_log_key(key));
+
+    // copy the newest versions, reading everything again; a key is
+    // removed once written, as a version can be in more than one file
+    let mut batch = Writebatch::new();
+    let mut pending = 0;
+    let mut keys = 0;
+    let mut result = Ok(());
+    let mut copy = |key: &[u8], sequence: u64, value: &[u8]| {
+        if result.is_err() || newest.get(key) != Some(&(sequence, ValueType::Value)) {
+            return;
+        }
+        newest.remove(key);
+        batch.put(key, value);
+        keys += 1;
+        pending += 1;
+        if pending == 1000 {
+            result = write_stored(dst, &batch);
+            batch.clear();
+            pending = 0;
+        }
+    };
+    // damage was reported by the first pass
+    let mut ignored = Vec::new();
+    for name in tables.values() {
+        read_table(src, name, None, &mut ignored, |key, value| {
+            copy(&key.user_key, key.sequence, &value)
+        });
+    }
+    for name in logs.values() {
+        read_log(src, name, &mut ignored, |batch| {
+            for (i, entry) in batch.entries.iter().enumerate() {
+                copy(&entry.key, batch.sequence + i as u64, &entry.value);
+            }
+        })
+        .map_err(io_error)?;
+    }
+    result?;
+    write_stored(dst, &batch)?;
+    report.keys = keys;
+    Ok(report)
+}
+
+/// read the MANIFEST of a closed database, which has to be intact and use
+/// the bytewise comparator.
+fn read_intact_manifest(path: &Path) -> Result<(PathBuf, Version), Error> {
+    let manifest_path = format::manifest::current_manifest(path).map_err(io_error)?;
+    let manifest = Manifest::open(&manifest_path).map_err(|e| Error::new(e.to_string()))?;
+    if let Some(e) = manifest.corruptions.first() {
+        return Err(Error::new(format!("damaged MANIFEST: {}", e)));
+    }
+    match manifest.version.comparator {
+        Some(ref comparator) if comparator != BYTEWISE_COMPARATOR => {
+            Err(Error::new(format!("unsupported comparator {}", comparator)))
+        }
+        _ => Ok((manifest_path, manifest.version)),
+    }
+}
+
+/// The writes still only in the logs of a closed database.
+struct LogWrites {
+    /// the sequence number of the latest write, in the logs or tables
+    last_sequence: u64,
+    /// the smallest and largest key written, if any
+    keys: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn log_writes(path: &Path, version: &Version) -> Result<LogWrites, Error> {
+    let (_, logs) = data_files(path).map_err(io_error)?;
+    let mut writes = LogWrites {
+        last_sequence: version.last_sequence,
+        keys: None,
+    };
+    let mut ignored = Vec::new();
+    for (_, name) in logs.range(version.min_log_number()..) {
+        read_log(path, name, &mut ignored, |batch| {
+            for (i, entry) in batch.entries.iter().enumerate() {
+                let sequence = batch.sequence + i as u64;
+                writes.last_sequence = writes.last_sequence.max(sequence);
+                writes.keys = match writes.keys.take() {
+                    Some((smallest, largest)) => Some((
+                        smallest.min(entry.key.clone()),
+                        largest.max(entry.key.clone()),
+                    )),
+                    None => Some((entry.key.clone(), entry.key.clone())),
+                };
+            }
+        })
+        .map_err(io_error)?;
+    }
+    Ok(writes)
+}
+
+/// the sequence number of the latest write to the closed database at
+/// `path`. Tables for `ingest` have to be written with a higher one.
+pub fn last_sequence(path: &Path) -> Result<u64, Error> {
+    let (_, version) = read_intact_manifest(path)?;
+    Ok(log_writes(path, &version)?.last_sequence)
+}
+
+/// the key range and the lowest and highest sequence number of a table
+/// to ingest, reading all of it to verify its checksums.
+fn describe_table(table: &Path) -> Result<(FileMetaData, u64, u64), Error> {
+    let error = |reason: String| Error::new(format!("{}: {}", table.display(), reason));
+    let opened = Table::open(table).map_err(|e| error(e.to_string()))?;
+    let mut range: Option<(InternalKey, InternalKey)> = None;
+    let mut min_sequence = u64::MAX;
+    let mut max_sequence = 0;
+    for entry in opened.iter() {
+        let (key, _) = entry.map_err(|e| error(e.to_string()))?;
+        min_sequence = min_sequence.min(key.sequence);
+        max_sequence = max_sequence.max(key.sequence);
+        range = match range {
+            Some((smallest, _)) => Some((smallest, key)),
+            None => Some((key.clone(), key)),
+        };
+    }
+    let (smallest, largest) = range.ok_or_else(|| error("empty table".to_string()))?;
+    let file = FileMetaData {
+        number: 0,
+        size: opened.file_size(),
+        smallest,
+        largest,
+    };
+    Ok((file, min_sequence, max_sequence))
+}
+
+/// whether a file of `files` holds keys in the user key range of `file`.
+fn overlaps(files: &[FileMetaData], file: &FileMetaData) -> bool {
+    files.iter().any(|other| {
+        other.smallest.user_key <= file.largest.user_key
+            && other.largest.user_key >= file.smallest.user_key
+    })
+}
+
+/// move `src` to `dest`, copying it if it can't be renamed.
+fn move_file(src: &Path, dest: &Path) -> io::Result<()> {
+    if fs::rename(src, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dest)?;
+    File::open(dest)?.sync_all()?;
+    fs::remove_file(src)
+}
+
+/// add tables written by `format::sst_writer::SstWriter` to the closed
+/// database at `path`, bypassing the write path.
+///
+/// Every entry of the tables must have a sequence number above
+/// `last_sequence(path)`, so that it replaces the versions of its key
+/// already in the database, and the tables may not hold overlapping key
+/// ranges. Nor may they overlap the writes still in the database's logs,
+/// which opening and closing the database moves to tables. Each table is
+/// read once to verify it, then moved into the database directory and
+/// recorded in the MANIFEST, at the deepest level where no table with
+/// overlapping keys is at or above it. Returns the level and metadata of
+/// every table added.
+///
+/// The database must not be open, here or in another process, while
+/// tables are ingested.
+pub fn ingest(path: &Path, tables: &[&Path]) -> Result<Vec<(usize, FileMetaData)>, Error> {
+    let (manifest_path, mut version) = read_intact_manifest(path)?;
+    let writes = log_writes(path, &version)?;
+    let last = writes.last_sequence;
+
+    let mut described = Vec::new();
+    let mut max_sequence = last;
+    for table in tables {
+        let (file, sequence, newest) = describe_table(table)?;
+        if sequence <= last {
+            return Err(Error::new(format!(
+                "{}: sequence number {} is not above the database's last sequence number {}",
+                table.display(),
+                sequence,
+                last
+            )));
+        }
+        if let Some((ref smallest, ref largest)) = writes.keys {
+            // leveldb moves these writes to a table when the database is
+            // opened, and would look there before looking at `table`
+            if *smallest <= file.largest.user_key && *largest >= file.smallest.user_key {
+                return Err(Error::new(format!(
+                    "{}: the database's logs hold writes to keys in the table; open and close \
+                     the database to move them to tables first",
+                    table.display()
+                )));
+            }
+        }
+        max_sequence = max_sequence.max(newest);
+        described.push((*table, file));
+    }
+    described.sort_by(|(_, a), (_, b)| a.smallest.user_key.cmp(&b.smallest.user_key));
+    for pair in described.windows(2) {
+        if pair[0].1.largest.user_key >= pair[1].1.smallest.user_key {
+            return Err(Error::new(format!(
+                "{} and {} hold overlapping keys",
+                pair[0].0.display(),
+                pair[1].0.display()
+            )));
+        }
+    }
+
+    let mut edit = VersionEdit::default();
+    let mut next_file_number = version.next_file_number;
+    let mut moved: Vec<(&Path, PathBuf)> = Vec::new();
+    let mut result = Ok(());
+    for (table, mut file) in described {
+        let mut level = 0;
+        if !overlaps(&version.levels[0], &file) {
+            while level + 1 < NUM_LEVELS && !overlaps(&version.levels[level + 1], &file) {
+                level += 1;
+            }
+        }
+        file.number = next_file_number;
+        next_file_number += 1;
+        let dest = path.join(format!("{:06}.ldb", file.number));
+        if dest.exists() {
+            result = Err(Error::new(format!("{} already exists", dest.display())));
+            break;
+        }
+        if let Err(e) = move_file(table, &dest) {
+            result = Err(io_error(e));
+            break;
+        }
+        moved.push((table, dest));
+        version.levels[level].push(file.clone());
+        edit.new_files.push((level, file));
+    }
+    edit.next_file_number = Some(next_file_number);
+    edit.last_sequence = Some(max_sequence);
+    if result.is_ok() {
+        result = format::manifest::append_edit(&manifest_path, &edit).map_err(io_error);
+    }
+    if let Err(e) = result {
+        // leave the tables where they were, so they aren't lost
+        for (table, dest) in moved {
+            let _ = move_file(&dest, table);
+        }
+        return Err(e);
+    }
+    Ok(edit.new_files)
+}
@@ -0,0 +1,430 @@
+//! Merge operators
+//!
+//! leveldb has no native merge support, so it is emulated: every call to
+//! `Database::merge` stores its operand as a separate record, and reads
+//! through `Database::get` or `Database::merged_iter` fold the operands into
+//! the base value using the `MergeOperator` given in `Options`.
+//!
+//! Operands pile up until a put or delete replaces the key, or until
+//! `Database::fold_merges` collapses all pending operands into plain
+//! values. `Database::open` starts a background thread that calls it every
+//! `Options::merge_fold_interval`; the thread is stopped when the database
+//! is dropped. Applications that turn it off should call `fold_merges`
+//! themselves from time to time.
+//!
+//! The emulation has a cost even for keys that are never merged. With a
+//! merge operator, every `get` takes a snapshot and seeks an iterator to
+//! look for operands, and every put and delete seeks an iterator to find
+//! the operands it replaces, holding the key's lock meanwhile so that no
+//! merge can slip in before the write.
+//!
+//! Operand records are stored under the reserved key prefix `\xff\xff`,
+//! which user keys must not start with. Reads through `get_bytes`, `get`,
+//! snapshots and transactions fold operands in too, but `iter` and
+//! `Snapshot::iter` return the base values without folding; use
+//! `merged_iter` to iterate over folded values.
+use super::batch::{Writebatch, WritebatchIterator};
+use super::error::Error;
+use super::iterator::DatabaseIterator;
+use super::reserved::{escape, unescape};
+use super::snapshots::Snapshot;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const PREFIX: &[u8] = b"\xff\xffmerge\x00";
+
+/// Combines a base value with a sequence of merge operands.
+pub trait MergeOperator: Send + Sync {
+    /// merge `operands`, oldest first, into `existing`, which is `None` if
+    /// the key has no base value. Returning `None` deletes the key.
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Option<Vec<u8>>;
+}
+
+/// Treats values and operands as little-endian `u64`s and adds them.
+///
+/// Values that are not 8 bytes long count as 0.
+pub struct U64AddOperator;
+
+impl MergeOperator for U64AddOperator {
+    fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Option<Vec<u8>> {
+        let decode = |bytes: &[u8]| {
+            let mut buf = [0; 8];
+            if bytes.len() == 8 {
+                buf.copy_from_slice(bytes);
+            }
+            u64::from_le_bytes(buf)
+        };
+        let sum = operands.iter().fold(existing.map_or(0, decode), |acc, op| {
+            acc.wrapping_add(decode(op))
+        });
+        Some(sum.to_le_bytes().to_vec())
+    }
+}
+
+/// Appends operands to the existing value.
+pub struct AppendOperator;
+
+impl MergeOperator for AppendOperator {
+    fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Option<Vec<u8>> {
+        let mut value = existing.map(|v| v.to_vec()).unwrap_or_default();
+        for op in operands {
+            value.extend_from_slice(op);
+        }
+        Some(value)
+    }
+}
+
+/// The prefix of all operand records of `key`.
+fn operand_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = PREFIX.to_vec();
+    escape(&mut prefix, key);
+    prefix
+}
+
+fn operand_key(key: &[u8], seq: u64) -> Vec<u8> {
+    let mut k = operand_prefix(key);
+    k.extend_from_slice(&seq.to_be_bytes());
+    k
+}
+
+/// decode the user key of an operand record.
+fn operand_user_key(record: &[u8]) -> Option<Vec<u8>> {
+    if !record.starts_with(PREFIX) {
+        return None;
+    }
+    unescape(&record[PREFIX.len()..]).map(|(key, _)| key)
+}
+
+fn fold(
+    operator: &dyn MergeOperator,
+    key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &[Vec<u8>],
+) -> Option<Vec<u8>> {
+    if operands.is_empty() {
+        return existing.map(|v| v.to_vec());
+    }
+    let operands: Vec<&[u8]> = operands.iter().map(|o| &o[..]).collect();
+    operator.merge(key, existing, &operands)
+}
+
+/// collect the operands of `key` at the iterator position after seeking.
+fn collect_operands(iter: &mut DatabaseIterator, key: &[u8]) -> Vec<Vec<u8>> {
+    let prefix = operand_prefix(key);
+    let mut operands = Vec::new();
+    iter.seek(&prefix);
+    while iter.valid() && iter.key().starts_with(&prefix) {
+        operands.push(iter.value().to_vec());
+        iter.next();
+    }
+    operands
+}
+
+/// A background thread folding merge operands at a fixed interval.
+pub(crate) struct Folder {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Folder {
+    pub(crate) fn start(database: Database, interval: Duration) -> Folder {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // failures are retried on the next pass
+                let _ = database.fold_merges(&WriteOptions::default());
+            }
+        });
+        Folder {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Folder {
+    fn drop(&mut self) {
+        // dropping the sender wakes the thread up
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Database {
+    fn merge_operator(&self) -> Result<&Arc<dyn MergeOperator>, Error> {
+        self.merge_operator
+            .as_ref()
+            .ok_or_else(|| Error::new("no merge operator configured".to_string()))
+    }
+
+    /// record a merge operand for `key`.
+    ///
+    /// The operand is folded into the value on reads. Fails if the database
+    /// was opened without a merge operator.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn merge(&self, options: &WriteOptions, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        self.merge_operator()?;
+        let _lock = self.locks.lock(key);
+
+        let prefix = operand_prefix(key);
+        let mut upper = prefix.clone();
+        upper.extend_from_slice(&[0xff; 8]);
+        let last = {
+            let mut iter = self.iter(&ReadOptions::default()).raw();
+            iter.seek(&upper);
+            if iter.valid() {
+                iter.prev();
+            } else {
+                iter.seek_to_last();
+            }
+            if iter.valid() && iter.key().starts_with(&prefix) {
+                let mut seq = [0; 8];
+                seq.copy_from_slice(&iter.key()[prefix.len()..]);
+                u64::from_be_bytes(seq)
+            } else {
+                0
+            }
+        };
+
+        let mut batch = Writebatch::new();
+        batch.put(&operand_key(key, last + 1), operand);
+        let _guard = self.versions.mutation();
+        let publisher = self.watchers.publisher(|events| events.merge(key, operand));
+        unsafe { self.write_c(options, &batch)? };
+        self.versions.bump(key);
+        if let Some(publisher) = publisher {
+            publisher.publish();
+        }
+        Ok(())
+    }
+
+    /// get a value with its pending merge operands folded in.
+    pub(crate) fn get_merged(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.snapshot().get_merged(options, key)
+    }
+
+    /// add deletes for the pending operands of all keys written by `batch`,
+    /// so that puts and deletes replace earlier merges.
+    pub(crate) fn clear_operands(&self, batch: &Writebatch) -> Writebatch {
+        struct Copy<'a> {
+            database: &'a Database,
+            batch: Writebatch,
+        }
+
+        impl<'a> Copy<'a> {
+            fn clear(&mut self, key: &[u8]) {
+                let prefix = operand_prefix(key);
+                let mut iter = self.database.iter(&ReadOptions::default()).raw();
+                iter.seek(&prefix);
+                while iter.valid() && iter.key().starts_with(&prefix) {
+                    self.batch.delete(iter.key());
+                    iter.next();
+                }
+            }
+        }
+
+        impl<'a> WritebatchIterator for Copy<'a> {
+            fn put(&mut self, key: &[u8], value: &[u8]) {
+                self.clear(key);
+                self.batch.put(key, value);
+            }
+
+            fn deleted(&mut self, key: &[u8]) {
+                self.clear(key);
+                self.batch.delete(key);
+            }
+        }
+
+        batch
+            .iterate(Box::new(Copy {
+                database: self,
+                batch: Writebatch::new(),
+            }))
+            .batch
+    }
+
+    /// lock the stripes of all keys written by `batch`, so that no merge
+    /// operand can be added to them between looking up their operands and
+    /// writing the batch. Takes no locks without a merge operator.
+    pub(crate) fn lock_operands(&self, batch: &Writebatch) -> Vec<MutexGuard<'_, ()>> {
+        struct Keys(Vec<Vec<u8>>);
+
+        impl WritebatchIterator for Keys {
+            fn put(&mut self, key: &[u8], _value: &[u8]) {
+                self.0.push(key.to_vec());
+            }
+
+            fn deleted(&mut self, key: &[u8]) {
+                self.0.push(key.to_vec());
+            }
+        }
+
+        if self.merge_operator.is_none() {
+            return Vec::new();
+        }
+        let keys = batch.iterate(Box::new(Keys(Vec::new()))).0;
+        self.locks.lock_many(keys.iter().map(|key| &key[..]))
+    }
+
+    /// iterate over all keys, with pending merge operands folded in.
+    ///
+    /// Fails if the database was opened without a merge operator.
+    pub fn merged_iter(&self, options: &ReadOptions) -> Result<MergedIterator<'_>, Error> {
+        let snapshot = self.snapshot();
+        let mut iter = snapshot.merged_iter_from(options, &[])?;
+        iter._snapshot = Some(snapshot);
+        Ok(iter)
+    }
+
+    /// collapse all pending merge operands into plain values.
+    ///
+    /// Returns the number of keys folded.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn fold_merges(&self, options: &WriteOptions) -> Result<usize, Error> {
+        let mut keys = Vec::new();
+        {
+            let mut iter = self.iter(&ReadOptions::default()).raw();
+            iter.seek(PREFIX);
+            while iter.valid() && iter.key().starts_with(PREFIX) {
+                if let Some(key) = operand_user_key(iter.key()) {
+                    if keys.last() != Some(&key) {
+                        keys.push(key);
+                    }
+                }
+                iter.next();
+            }
+        }
+
+        let read_options = ReadOptions::default();
+        for key in &keys {
+            // the folded value is written back unchanged, so other writers
+            // only need to be kept out while this key is rewritten.
+            let _lock = self.locks.lock(key);
+            let _guard = self.versions.exclusive();
+            let mut batch = Writebatch::new();
+            match self.get_merged(&read_options, key)? {
+                Some(value) => batch.put(key, &value),
+                None => batch.delete(key),
+            }
+            let publisher = self.watchers.publisher(|events| events.batch(&batch));
+            let batch = self.clear_operands(&batch);
+            unsafe { self.write_c(options, &batch)? };
+            if let Some(publisher) = publisher {
+                publisher.publish();
+            }
+        }
+        Ok(keys.len())
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// get a value with its pending merge operands folded in, as it was
+    /// when the snapshot was taken.
+    pub(crate) fn get_merged(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let operator = self.database().merge_operator()?;
+        let existing = self.get_unmerged(options, key)?;
+        let operands = collect_operands(&mut self.iter(options).raw(), key);
+        Ok(fold(&**operator, key, existing.as_deref(), &operands))
+    }
+
+    /// iterate over all keys as they were when the snapshot was taken,
+    /// with pending merge operands folded in.
+    ///
+    /// Fails if the database was opened without a merge operator.
+    pub fn merged_iter(&self, options: &ReadOptions) -> Result<MergedIterator<'a>, Error> {
+        self.merged_iter_from(options, &[])
+    }
+
+    /// iterate over the keys from `start` on, with merge operands folded
+    /// in.
+    pub(crate) fn merged_iter_from(
+        &self,
+        options: &ReadOptions,
+        start: &[u8],
+    ) -> Result<MergedIterator<'a>, Error> {
+        let operator = self.database().merge_operator()?.clone();
+        let mut base = self.iter(options);
+        base.seek(start);
+        let mut operands = self.iter(options).raw();
+        operands.seek(&operand_prefix(start));
+        Ok(MergedIterator {
+            base,
+            operands,
+            operator,
+            _snapshot: None,
+        })
+    }
+}
+
+/// An iterator over keys and values with merge operands folded in.
+///
+/// Reads from a snapshot taken when the iterator was created.
+pub struct MergedIterator<'a> {
+    base: DatabaseIterator<'a>,
+    operands: DatabaseIterator<'a>,
+    operator: Arc<dyn MergeOperator>,
+    _snapshot: Option<Snapshot<'a>>,
+}
+
+impl<'a> MergedIterator<'a> {
+    fn base_key(&self) -> Option<Vec<u8>> {
+        if self.base.valid() {
+            Some(self.base.key().to_vec())
+        } else {
+            None
+        }
+    }
+
+    fn operand_key(&self) -> Option<Vec<u8>> {
+        if self.operands.valid() {
+            operand_user_key(self.operands.key())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for MergedIterator<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        loop {
+            let key = match (self.base_key(), self.operand_key()) {
+                (None, None) => return None,
+                (Some(b), None) => b,
+                (None, Some(o)) => o,
+                (Some(b), Some(o)) => b.min(o),
+            };
+
+            let mut existing = None;
+            if self.base_key().as_ref() == Some(&key) {
+                existing = Some(self.base.value().to_vec());
+                self.base.next();
+            }
+            let mut operands = Vec::new();
+            while self.operand_key().as_ref() == Some(&key) {
+                operands.push(self.operands.value().to_vec());
+                self.operands.next();
+            }
+
+            if let Some(value) = fold(&*self.operator, &key, existing.as_deref(), &operands) {
+                return Some((key, value));
+            }
+        }
+    }
+}
@@ -3,22 +3,49 @@
 use self::bytes::Bytes;
 use self::options::{c_options, Options};
 use leveldb_sys::*;
-use libc::{c_char, size_t};
-use std::ffi::CString;
+use libc::{c_char, c_void, size_t};
+use std::ffi::{CStr, CString};
 
 use self::error::Error;
 use crate::options::{c_readoptions, c_writeoptions, ReadOptions, WriteOptions};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::iterator::DatabaseIterator;
 use std::ptr;
+use std::sync::Arc;
 
+use self::atomic::KeyLocks;
+use self::batch::Writebatch;
+use self::change_log::ChangeLog;
+use self::locking::LockTable;
+use self::merge::{Folder, MergeOperator};
+use self::transaction::VersionTable;
+use self::watch::Watchers;
+
+mod atomic;
+#[cfg(feature = "async")]
+pub mod async_database;
+pub mod backup;
+pub mod batch;
 pub mod bytes;
+pub mod change_log;
 pub mod error;
+pub mod export;
+pub mod group_commit;
+pub mod index;
 pub mod iterator;
+pub mod locking;
 pub mod management;
+pub mod merge;
 pub mod options;
+pub mod read_only;
+pub mod replication;
+mod reserved;
+pub mod snapshots;
+pub mod transaction;
+pub mod ttl;
+pub mod watch;
 
 #[allow(missing_docs)]
 struct RawDB {
@@ -34,6 +61,10 @@ impl Drop for RawDB {
     }
 }
 
+// leveldb databases can be used from multiple threads.
+unsafe impl Sync for RawDB {}
+unsafe impl Send for RawDB {}
+
 /// The main database object.
 ///
 /// leveldb databases are based on ordered keys. By default, leveldb orders
@@ -47,16 +78,57 @@ impl Drop for RawDB {
 /// Multiple Database objects can be kept around, as leveldb synchronises
 /// internally.
 pub struct Database {
-    database: RawDB,
+    // dropped first, so that the folding thread is stopped before the
+    // database it shares is closed.
+    folder: Option<Folder>,
+    database: Arc<RawDB>,
+    path: PathBuf,
+    merge_operator: Option<Arc<dyn MergeOperator>>,
+    ttl: bool,
+    change_log: Option<Arc<ChangeLog>>,
+    locks: Arc<KeyLocks>,
+    versions: Arc<VersionTable>,
+    lock_table: Arc<LockTable>,
+    watchers: Arc<Watchers>,
 }
 
 unsafe impl Sync for Database {}
 unsafe impl Send for Database {}
 
 impl Database {
-    unsafe fn new(database: *mut leveldb_t) -> Database {
+    unsafe fn new(database: *mut leveldb_t, path: &Path, options: &Options) -> Database {
         Database {
-            database: RawDB { ptr: database },
+            folder: None,
+            database: Arc::new(RawDB { ptr: database }),
+            path: path.to_path_buf(),
+            merge_operator: options.merge_operator.clone(),
+            ttl: options.ttl,
+            change_log: if options.change_log {
+                Some(Arc::new(ChangeLog::new()))
+            } else {
+                None
+            },
+            locks: Arc::new(KeyLocks::new()),
+            versions: Arc::new(VersionTable::new()),
+            lock_table: Arc::new(LockTable::new()),
+            watchers: Arc::new(Watchers::new()),
+        }
+    }
+
+    /// another handle on the same open database, sharing its locks,
+    /// watchers and change log. The handle does not fold merges itself.
+    fn share(&self) -> Database {
+        Database {
+            folder: None,
+            database: self.database.clone(),
+            path: self.path.clone(),
+            merge_operator: self.merge_operator.clone(),
+            ttl: self.ttl,
+            change_log: self.change_log.clone(),
+            locks: self.locks.clone(),
+            versions: self.versions.clone(),
+            lock_table: self.lock_table.clone(),
+            watchers: self.watchers.clone(),
         }
     }
 
@@ -65,6 +137,11 @@ impl Database {
     /// If the database is missing, the behaviour depends on `options.create_if_missing`.
     /// The database will be created using the settings given in `options`.
     pub fn open(name: &Path, options: Options) -> Result<Database, Error> {
+        if options.ttl && options.merge_operator.is_some() {
+            return Err(Error::new(
+                "merge operators can't be combined with ttl".to_string(),
+            ));
+        }
         let mut error = ptr::null_mut();
         unsafe {
             let c_string = CString::new(name.to_str().unwrap()).unwrap();
@@ -77,13 +154,28 @@ impl Database {
             leveldb_options_destroy(c_options);
 
             if error.is_null() {
-                Ok(Database::new(db))
+                let mut database = Database::new(db, name, &options);
+                database.check_ttl_mode()?;
+                if let Some(ref change_log) = database.change_log {
+                    change_log.recover(&database)?;
+                }
+                if let (Some(_), Some(interval)) =
+                    (&options.merge_operator, options.merge_fold_interval)
+                {
+                    database.folder = Some(Folder::start(database.share(), interval));
+                }
+                Ok(database)
             } else {
                 Err(Error::new_from_i8(error))
             }
         }
     }
 
+    /// The directory the database was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// put a binary value into the database.
     ///
     /// If the key is already present in the database, it will be overwritten.
@@ -94,6 +186,13 @@ impl Database {
     /// NOT the default.
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub fn put(&self, options: &WriteOptions, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if self.merge_operator.is_some() || self.ttl || self.change_log.is_some() {
+            let mut batch = Writebatch::new();
+            batch.put(key, value);
+            return self.write(options, &batch);
+        }
+        let _guard = self.versions.mutation();
+        let publisher = self.watchers.publisher(|events| events.put(key, value));
         unsafe {
             let mut error = ptr::null_mut();
             let c_writeoptions = c_writeoptions(options);
@@ -109,6 +208,10 @@ impl Database {
             leveldb_writeoptions_destroy(c_writeoptions);
 
             if error.is_null() {
+                self.versions.bump(key);
+                if let Some(publisher) = publisher {
+                    publisher.publish();
+                }
                 Ok(())
             } else {
                 Err(Error::new_from_i8(error))
@@ -124,6 +227,13 @@ impl Database {
     /// NOT the default.
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub fn delete(&self, options: &WriteOptions, key: &[u8]) -> Result<(), Error> {
+        if self.merge_operator.is_some() || self.change_log.is_some() {
+            let mut batch = Writebatch::new();
+            batch.delete(key);
+            return self.write(options, &batch);
+        }
+        let _guard = self.versions.mutation();
+        let publisher = self.watchers.publisher(|events| events.delete(key));
         unsafe {
             let mut error = ptr::null_mut();
             let c_writeoptions = c_writeoptions(options);
@@ -136,6 +246,10 @@ impl Database {
             );
             leveldb_writeoptions_destroy(c_writeoptions);
             if error.is_null() {
+                self.versions.bump(key);
+                if let Some(publisher) = publisher {
+                    publisher.publish();
+                }
                 Ok(())
             } else {
                 Err(Error::new_from_i8(error))
@@ -143,29 +257,138 @@ impl Database {
         }
     }
 
+    /// get a value from the database, without copying it out of the
+    /// buffer leveldb allocated for it.
+    ///
+    /// The passed key will be compared using the comparator. If the
+    /// database has a merge operator, pending merge operands are folded
+    /// into the value, which is then copied.
     pub fn get_bytes(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Bytes>, Error> {
+        if self.merge_operator.is_some() {
+            return self
+                .get_merged(options, key)
+                .map(|v| v.map(|v| Bytes::copy_from(&v)));
+        }
+        self.get_bytes_raw(options, key)
+            .map(|value| self.visible(key, value))
+    }
+
+    /// get a value as stored, including any expiry header.
+    pub(crate) fn get_bytes_raw(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Bytes>, Error> {
         unsafe {
-            let mut error = ptr::null_mut();
-            let mut length: size_t = 0;
             let c_readoptions = c_readoptions(options);
-            let result = leveldb_get(
-                self.database.ptr,
-                c_readoptions,
-                key.as_ptr() as *mut c_char,
-                key.len() as size_t,
-                &mut length,
-                &mut error,
-            );
+            let result = self.get_bytes_c(c_readoptions, key);
             leveldb_readoptions_destroy(c_readoptions);
+            result
+        }
+    }
 
-            if error.is_null() {
-                Ok(Bytes::from_raw(result as *mut u8, length))
-            } else {
-                Err(Error::new_from_i8(error))
-            }
+    unsafe fn get_bytes_c(
+        &self,
+        c_readoptions: *const leveldb_readoptions_t,
+        key: &[u8],
+    ) -> Result<Option<Bytes>, Error> {
+        let mut error = ptr::null_mut();
+        let mut length: size_t = 0;
+        let result = leveldb_get(
+            self.database.ptr,
+            c_readoptions,
+            key.as_ptr() as *mut c_char,
+            key.len() as size_t,
+            &mut length,
+            &mut error,
+        );
+
+        if error.is_null() {
+            Ok(Bytes::from_raw(result as *mut u8, length))
+        } else {
+            Err(Error::new_from_i8(error))
+        }
+    }
+
+    /// apply the rewrites the configured features need to a batch of user
+    /// writes, if any.
+    pub(crate) fn prepare_batch(&self, batch: &Writebatch) -> Option<Writebatch> {
+        if self.merge_operator.is_some() {
+            Some(self.clear_operands(batch))
+        } else if self.ttl {
+            Some(self.add_expiry_headers(batch))
+        } else {
+            None
         }
     }
 
+    /// get a value from the database as an owned `Vec<u8>`.
+    ///
+    /// Returns `None` if the key is not present. If the database has a merge
+    /// operator, pending merge operands are folded into the value.
+    pub fn get(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if self.merge_operator.is_some() {
+            return self.get_merged(options, key);
+        }
+        self.get_bytes(options, key).map(|v| v.map(Vec::from))
+    }
+
+    /// check whether a key is present in the database.
+    pub fn contains_key(&self, options: &ReadOptions, key: &[u8]) -> Result<bool, Error> {
+        if self.merge_operator.is_some() {
+            return self.get(options, key).map(|v| v.is_some());
+        }
+        self.get_bytes(options, key).map(|v| v.is_some())
+    }
+
+    /// get a value from the database, or an empty value if the key is
+    /// not present.
+    pub fn get_or_default(&self, options: &ReadOptions, key: &[u8]) -> Result<Vec<u8>, Error> {
+        self.get_or_else(options, key, Vec::new)
+    }
+
+    /// get a value from the database, or a copy of `default` if the key is
+    /// not present.
+    pub fn get_or(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+        default: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.get_or_else(options, key, || default.to_vec())
+    }
+
+    /// get a value from the database, or the result of `default` if the key
+    /// is not present.
+    pub fn get_or_else<F>(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+        default: F,
+    ) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce() -> Vec<u8>,
+    {
+        self.get(options, key).map(|v| v.unwrap_or_else(default))
+    }
+
+    /// get a value from the database and convert it using `decode`.
+    ///
+    /// Returns `T::default()` if the key is not present.
+    pub fn get_as_or_default<T, F>(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+        decode: F,
+    ) -> Result<T, Error>
+    where
+        T: Default,
+        F: FnOnce(&[u8]) -> T,
+    {
+        self.get(options, key)
+            .map(|v| v.map(|bytes| decode(&bytes)).unwrap_or_default())
+    }
+
     pub fn iter<'a>(&'a self, options: &ReadOptions) -> DatabaseIterator<'a> {
         DatabaseIterator::new(self, options)
     }
@@ -181,4 +404,59 @@ impl Database {
             );
         }
     }
+
+    /// compact the keys from `start` up to `limit`, where `None` stands
+    /// for the start or end of the keyspace.
+    pub fn compact_range(&self, start: Option<&[u8]>, limit: Option<&[u8]>) {
+        let raw = |key: Option<&[u8]>| match key {
+            Some(key) => (key.as_ptr() as *const c_char, key.len() as size_t),
+            None => (ptr::null(), 0),
+        };
+        let (start, start_len) = raw(start);
+        let (limit, limit_len) = raw(limit);
+        unsafe {
+            leveldb_compact_range(self.database.ptr, start, start_len, limit, limit_len);
+        }
+    }
+
+    /// get the value of a leveldb property, like `leveldb.stats` or
+    /// `leveldb.num-files-at-level0`.
+    ///
+    /// Returns `None` for unknown properties.
+    pub fn property(&self, name: &str) -> Option<String> {
+        let name = CString::new(name).ok()?;
+        unsafe {
+            let value = leveldb_property_value(self.database.ptr, name.as_ptr());
+            if value.is_null() {
+                return None;
+            }
+            let result = CStr::from_ptr(value).to_string_lossy().into_owned();
+            leveldb_free(value as *mut c_void);
+            Some(result)
+        }
+    }
+
+    /// get the approximate number of bytes the keys from `start` up to
+    /// `limit` take up on disk.
+    ///
+    /// Data still in memory is not counted.
+    pub fn approximate_size(&self, start: &[u8], limit: &[u8]) -> u64 {
+        let start_ptr = start.as_ptr() as *const c_char;
+        let limit_ptr = limit.as_ptr() as *const c_char;
+        let start_len = start.len() as size_t;
+        let limit_len = limit.len() as size_t;
+        let mut size = 0;
+        unsafe {
+            leveldb_approximate_sizes(
+                self.database.ptr,
+                1,
+                &start_ptr,
+                &start_len,
+                &limit_ptr,
+                &limit_len,
+                &mut size,
+            );
+        }
+        size
+    }
 }
@@ -4,9 +4,12 @@
 //! * `Options`: used when opening a database
 //! * `ReadOptions`: used when reading from leveldb
 //! * `WriteOptions`: used when writng to leveldb
+use crate::database::merge::MergeOperator;
 use leveldb_sys::*;
 
 use libc::size_t;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use leveldb_sys::Compression;
 
@@ -51,6 +54,26 @@ pub struct Options {
     ///
     /// default: Compression::No
     pub compression: Compression,
+    /// The operator used to fold values written with `Database::merge`.
+    ///
+    /// default: None
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
+    /// How often a background thread collapses pending merge operands
+    /// into plain values, see `Database::fold_merges`. Only used with a
+    /// merge operator; `None` leaves folding to the application.
+    ///
+    /// default: 60 seconds
+    pub merge_fold_interval: Option<Duration>,
+    /// Store an expiry header with every value, allowing `put_with_ttl`.
+    /// Can't be combined with a merge operator.
+    ///
+    /// default: false
+    pub ttl: bool,
+    /// Record every write batch in a change log, see
+    /// `database::change_log`.
+    ///
+    /// default: false
+    pub change_log: bool,
 }
 
 impl Default for Options {
@@ -65,6 +88,10 @@ impl Default for Options {
             block_size: None,
             block_restart_interval: None,
             compression: Compression::No,
+            merge_operator: None,
+            merge_fold_interval: Some(Duration::from_secs(60)),
+            ttl: false,
+            change_log: false,
         }
     }
 }
@@ -110,6 +137,7 @@ impl Default for ReadOptions {
 }
 
 #[allow(missing_docs)]
+#[allow(clippy::missing_safety_doc)]
 pub unsafe fn c_options(
     options: &Options,
     comparator: Option<*mut leveldb_comparator_t>,
@@ -138,6 +166,7 @@ pub unsafe fn c_options(
 }
 
 #[allow(missing_docs)]
+#[allow(clippy::missing_safety_doc)]
 #[allow(clippy::trivially_copy_pass_by_ref)]
 pub unsafe fn c_writeoptions(options: &WriteOptions) -> *mut leveldb_writeoptions_t {
     let c_writeoptions = leveldb_writeoptions_create();
@@ -146,6 +175,7 @@ pub unsafe fn c_writeoptions(options: &WriteOptions) -> *mut leveldb_writeoption
 }
 
 #[allow(missing_docs)]
+#[allow(clippy::missing_safety_doc)]
 pub unsafe fn c_readoptions(options: &ReadOptions) -> *mut leveldb_readoptions_t {
     let c_readoptions = leveldb_readoptions_create();
     leveldb_readoptions_set_verify_checksums(c_readoptions, options.verify_checksums as u8);
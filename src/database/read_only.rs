@@ -0,0 +1,189 @@
+//! Read-only access to a database directory
+//!
+//! leveldb writes to the directory of every database it opens: it takes
+//! the LOCK, starts a new log and MANIFEST and may compact. To leave the
+//! directory as it is, `Database::open_read_only` opens a private copy
+//! instead: the tables are hard-linked where possible, the MANIFEST and
+//! logs are copied. The copy is deleted again when the `ReadOnlyDatabase`
+//! is dropped.
+//!
+//! The copy is staged in the system's temporary directory, in a directory
+//! named `<name>.read-only-<pid>-<n>`. When that is on another file system
+//! than the database, the tables have to be copied too;
+//! `Database::open_read_only_in` stages the copy in a directory of the
+//! caller's choosing instead, where the tables can be hard-linked. Opening
+//! always costs a copy of the logs, up to the write buffer size, and of the
+//! MANIFEST. `ReadOnlyDatabase::staging_path` tells where the copy is. A
+//! copy is only removed by the `ReadOnlyDatabase` that made it; one left
+//! behind by a process that exited without dropping it stays until the
+//! temporary directory is cleaned.
+//!
+//! The copy is a consistent view of the database at the time it was
+//! opened; later writes to the original are not visible.
+//!
+//! ```rust
+//! use leveldb::database::Database;
+//! use leveldb::options::{Options, ReadOptions, WriteOptions};
+//! use std::fs;
+//! use std::path::Path;
+//! use tempdir::TempDir;
+//!
+//! let tempdir = TempDir::new("demo").unwrap();
+//! let path = tempdir.path();
+//!
+//! let mut options = Options::default();
+//! options.create_if_missing = true;
+//! let database = Database::open(path, options).unwrap();
+//! database.put(&WriteOptions::default(), b"key", b"value").unwrap();
+//! drop(database);
+//!
+//! let listing = |path: &Path| {
+//!     let mut files: Vec<_> = fs::read_dir(path)
+//!         .unwrap()
+//!         .map(|entry| {
+//!             let entry = entry.unwrap();
+//!             let metadata = entry.metadata().unwrap();
+//!             (entry.file_name(), metadata.len(), metadata.modified().unwrap())
+//!         })
+//!         .collect();
+//!     files.sort();
+//!     files
+//! };
+//! let before = listing(path);
+//!
+//! let read_only = Database::open_read_only(path, Options::default()).unwrap();
+//! let value = read_only.get(&ReadOptions::default(), b"key").unwrap();
+//! assert_eq!(value, Some(b"value".to_vec()));
+//! drop(read_only);
+//!
+//! assert_eq!(listing(path), before);
+//! ```
+use super::bytes::Bytes;
+use super::error::Error;
+use super::iterator::DatabaseIterator;
+use super::management::copy_database;
+use super::Database;
+use crate::options::{Options, ReadOptions};
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory deleted on drop.
+struct PrivateDir {
+    path: PathBuf,
+}
+
+impl PrivateDir {
+    /// create a staging directory in `parent` for a copy of the database
+    /// at `source`.
+    fn create(parent: &Path, source: &Path) -> Result<PrivateDir, Error> {
+        let mut prefix = source
+            .file_name()
+            .unwrap_or_else(|| OsStr::new("leveldb"))
+            .to_os_string();
+        prefix.push(".read-only-");
+        loop {
+            let mut name = OsString::from(&prefix);
+            name.push(format!(
+                "{}-{}",
+                process::id(),
+                COPIES.fetch_add(1, Ordering::Relaxed)
+            ));
+            let path = parent.join(name);
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(PrivateDir { path }),
+                // left behind by an earlier process with the same id
+                Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(Error::new(e.to_string())),
+            }
+        }
+    }
+}
+
+impl Drop for PrivateDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A database opened by `Database::open_read_only`, offering only reads.
+pub struct ReadOnlyDatabase {
+    // dropped before the copy is deleted
+    database: Database,
+    path: PathBuf,
+    copy: PrivateDir,
+}
+
+impl Database {
+    /// open the database at `name` for reading, without modifying its
+    /// directory.
+    ///
+    /// Reads go to a private copy of the database, staged in the system's
+    /// temporary directory, so this also works for databases another
+    /// process has open. See the `read_only` module for what the copy
+    /// costs. `create_if_missing` is ignored.
+    pub fn open_read_only(name: &Path, options: Options) -> Result<ReadOnlyDatabase, Error> {
+        Database::open_read_only_in(name, &env::temp_dir(), options)
+    }
+
+    /// open the database at `name` for reading like `open_read_only`,
+    /// staging the copy in `staging`, which has to exist.
+    ///
+    /// On the same file system as the database, the tables are hard-linked
+    /// rather than copied.
+    pub fn open_read_only_in(
+        name: &Path,
+        staging: &Path,
+        mut options: Options,
+    ) -> Result<ReadOnlyDatabase, Error> {
+        let copy = PrivateDir::create(staging, name)?;
+        copy_database(name, &copy.path)?;
+        options.create_if_missing = false;
+        options.error_if_exists = false;
+        let database = Database::open(&copy.path, options)?;
+        Ok(ReadOnlyDatabase {
+            database,
+            path: name.to_path_buf(),
+            copy,
+        })
+    }
+}
+
+impl ReadOnlyDatabase {
+    /// The directory the database was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The directory holding the private copy reads go to.
+    pub fn staging_path(&self) -> &Path {
+        &self.copy.path
+    }
+
+    /// get a value from the database, without copying it.
+    pub fn get_bytes(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Bytes>, Error> {
+        self.database.get_bytes(options, key)
+    }
+
+    /// get a value from the database.
+    pub fn get(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.database.get(options, key)
+    }
+
+    /// iterate the keys and values of the database.
+    pub fn iter<'a>(&'a self, options: &ReadOptions) -> DatabaseIterator<'a> {
+        self.database.iter(options)
+    }
+
+    /// a leveldb property, like `leveldb.stats` or `leveldb.sstables`.
+    pub fn property(&self, name: &str) -> Option<String> {
+        self.database.property(name)
+    }
+}
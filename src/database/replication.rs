@@ -0,0 +1,221 @@
+//! Primary-replica replication on top of the change log
+//!
+//! A primary opened with `Options::change_log` records every write batch
+//! with a sequence number. `send_changes` writes those records to any
+//! `Write` stream, and a `ReplicaApplier` applies them to a follower
+//! database, from any `Read` stream or an in-process channel.
+//!
+//! The follower stores the sequence number of the last applied record
+//! under the reserved key prefix `\xff\xff`, in the same batch as the
+//! record's writes. Records that were already applied are skipped, so a
+//! stream can safely be replayed from an earlier point. A gap in the
+//! sequence numbers, for example because the primary truncated its log
+//! before the follower caught up, is reported as an error; the follower
+//! then has to be re-seeded from a copy of the primary.
+//!
+//! Records hold writes as stored, including bookkeeping records, so the
+//! follower should be opened with the same `merge_operator` and `ttl`
+//! settings as the primary, and must not be written to otherwise.
+//!
+//! `serve_replica` and `ReplicaApplier::follow` stream changes over TCP:
+//! the follower sends its applied sequence number and the primary keeps
+//! sending newer records as they are written.
+use super::batch::Writebatch;
+use super::change_log::ChangeRecord;
+use super::error::Error;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::Receiver;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+const APPLIED_KEY: &[u8] = b"\xff\xffreplica\x00applied";
+
+fn io_error(error: io::Error) -> Error {
+    Error::new(error.to_string())
+}
+
+/// write all change log records after `since` to `writer`.
+///
+/// Returns the sequence number of the last record written, or `since` if
+/// there were none.
+pub fn send_changes<W: Write>(
+    database: &Database,
+    writer: &mut W,
+    since: u64,
+) -> Result<u64, Error> {
+    let mut last = since;
+    for record in database.changes_since(since)? {
+        let record = record?;
+        record.write_to(writer).map_err(io_error)?;
+        last = record.seq;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(last)
+}
+
+/// stream changes to a follower connected through `stream`.
+///
+/// Reads the follower's applied sequence number, then sends every newer
+/// record, checking for new ones every `poll_interval`. Returns once the
+/// follower closed the connection, or with an error if sending fails.
+pub fn serve_replica(
+    database: &Database,
+    stream: TcpStream,
+    poll_interval: Duration,
+) -> Result<(), Error> {
+    let mut reader = stream.try_clone().map_err(io_error)?;
+    let mut applied = [0; 8];
+    reader.read_exact(&mut applied).map_err(io_error)?;
+    let mut last = u64::from_be_bytes(applied);
+
+    // the follower sends nothing after its sequence number, so waiting
+    // for input doubles as the poll interval and notices disconnects
+    let poll_interval = poll_interval.max(Duration::from_millis(1));
+    reader
+        .set_read_timeout(Some(poll_interval))
+        .map_err(io_error)?;
+    let mut writer = BufWriter::new(stream);
+    let mut buf = [0; 64];
+    loop {
+        last = send_changes(database, &mut writer, last)?;
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+}
+
+/// Applies change log records to a follower database.
+pub struct ReplicaApplier<'a> {
+    database: &'a Database,
+    // serializes checking and advancing the applied sequence number
+    apply: Mutex<()>,
+}
+
+impl<'a> ReplicaApplier<'a> {
+    /// create an applier writing to `database`.
+    pub fn new(database: &'a Database) -> ReplicaApplier<'a> {
+        ReplicaApplier {
+            database,
+            apply: Mutex::new(()),
+        }
+    }
+
+    /// The follower database.
+    pub fn database(&self) -> &'a Database {
+        self.database
+    }
+
+    /// The sequence number of the last record applied to the follower, or
+    /// 0 if none was.
+    pub fn applied_seq(&self) -> Result<u64, Error> {
+        match self
+            .database
+            .get_bytes_raw(&ReadOptions::default(), APPLIED_KEY)?
+        {
+            Some(value) if value.len() == 8 => {
+                let mut seq = [0; 8];
+                seq.copy_from_slice(&value);
+                Ok(u64::from_be_bytes(seq))
+            }
+            Some(_) => Err(Error::new("corrupt applied sequence number".to_string())),
+            None => Ok(0),
+        }
+    }
+
+    /// apply a record, unless it was applied before.
+    ///
+    /// Returns whether the record was applied.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn apply(&self, options: &WriteOptions, record: &ChangeRecord) -> Result<bool, Error> {
+        let _apply = self.apply.lock().unwrap_or_else(PoisonError::into_inner);
+        let applied = self.applied_seq()?;
+        if record.seq <= applied {
+            return Ok(false);
+        }
+        if record.seq != applied + 1 {
+            return Err(Error::new(format!(
+                "missing change log records {} to {}",
+                applied + 1,
+                record.seq - 1
+            )));
+        }
+
+        let batch = record.to_writebatch();
+        let database = self.database;
+        // the record holds writes as stored, so it bypasses prepare_batch
+        let _guard = database.versions.mutation();
+        let publisher = database
+            .watchers
+            .publisher(|events| events.stored_batch(&batch, database.ttl));
+        let mut written = Writebatch::new();
+        written.append(&batch);
+        written.put(APPLIED_KEY, &record.seq.to_be_bytes());
+        unsafe { database.write_c(options, &written)? };
+        database.versions.bump_batch(&batch);
+        if let Some(publisher) = publisher {
+            publisher.publish();
+        }
+        Ok(true)
+    }
+
+    /// apply all records read from `reader`, until it ends.
+    ///
+    /// Returns the number of records applied.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn apply_from<R: Read>(&self, options: &WriteOptions, reader: R) -> Result<u64, Error> {
+        let mut reader = BufReader::new(reader);
+        let mut applied = 0;
+        while let Some(record) = ChangeRecord::read_from(&mut reader).map_err(io_error)? {
+            if self.apply(options, &record)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// apply all records received from `receiver`, until all senders are
+    /// gone.
+    ///
+    /// Returns the number of records applied.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn apply_from_channel(
+        &self,
+        options: &WriteOptions,
+        receiver: &Receiver<ChangeRecord>,
+    ) -> Result<u64, Error> {
+        let mut applied = 0;
+        for record in receiver.iter() {
+            if self.apply(options, &record)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// connect to a primary running `serve_replica` and apply its changes
+    /// until the connection is closed.
+    ///
+    /// Returns the number of records applied.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn follow<A: ToSocketAddrs>(
+        &self,
+        options: &WriteOptions,
+        primary: A,
+    ) -> Result<u64, Error> {
+        let mut stream = TcpStream::connect(primary).map_err(io_error)?;
+        stream
+            .write_all(&self.applied_seq()?.to_be_bytes())
+            .map_err(io_error)?;
+        self.apply_from(options, stream)
+    }
+}
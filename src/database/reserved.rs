@@ -0,0 +1,43 @@
+//! Helpers for the keys of internal bookkeeping records.
+//!
+//! Features like merge operators, ttl and secondary indexes store their
+//! records next to user data, under a prefix that user keys must not use.
+
+/// Keys starting with this prefix are reserved for bookkeeping records.
+pub(crate) const RESERVED_PREFIX: &[u8] = b"\xff\xff";
+
+/// append `bytes` to `out` in an order-preserving, self-delimiting encoding.
+///
+/// 0 bytes are escaped as `0 0xff` and the end is marked by `0 0`, so no
+/// encoding is a prefix of another one.
+pub(crate) fn escape(out: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        out.push(b);
+        if b == 0 {
+            out.push(0xff);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+/// decode a value written by `escape` from the start of `bytes`.
+///
+/// Returns the value and the remaining bytes.
+pub(crate) fn unescape(bytes: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut value = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0 {
+            match bytes.get(i + 1) {
+                Some(0xff) => value.push(0),
+                Some(0) => return Some((value, &bytes[i + 2..])),
+                _ => return None,
+            }
+            i += 2;
+        } else {
+            value.push(bytes[i]);
+            i += 1;
+        }
+    }
+    None
+}
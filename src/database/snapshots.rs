@@ -0,0 +1,111 @@
+//! leveldb snapshots
+//!
+//! Snapshots give you a reference to the database at a certain
+//! point in time and won't change while you work with them.
+use super::bytes::Bytes;
+use super::error::Error;
+use super::Database;
+use crate::iterator::DatabaseIterator;
+use crate::options::{c_readoptions, ReadOptions};
+use leveldb_sys::*;
+
+#[allow(missing_docs)]
+struct RawSnapshot<'a> {
+    database: &'a Database,
+    ptr: *mut leveldb_snapshot_t,
+}
+
+#[allow(missing_docs)]
+impl<'a> Drop for RawSnapshot<'a> {
+    fn drop(&mut self) {
+        unsafe { leveldb_release_snapshot(self.database.database.ptr, self.ptr) };
+    }
+}
+
+/// A database snapshot
+///
+/// Represents a database at a certain point in time,
+/// and allows for all read operations (get and iteration).
+pub struct Snapshot<'a> {
+    raw: RawSnapshot<'a>,
+}
+
+unsafe impl<'a> Sync for Snapshot<'a> {}
+unsafe impl<'a> Send for Snapshot<'a> {}
+
+impl Database {
+    /// Creates a snapshot of the database at the current point in time.
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        let ptr = unsafe { leveldb_create_snapshot(self.database.ptr) };
+        Snapshot {
+            raw: RawSnapshot {
+                database: self,
+                ptr,
+            },
+        }
+    }
+}
+
+impl<'a> Snapshot<'a> {
+    /// The database this snapshot was taken of.
+    pub fn database(&self) -> &'a Database {
+        self.raw.database
+    }
+
+    /// get a value as it was when the snapshot was taken, without copying
+    /// it out of the buffer leveldb allocated for it.
+    ///
+    /// If the database has a merge operator, pending merge operands are
+    /// folded into the value, which is then copied.
+    pub fn get_bytes(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Bytes>, Error> {
+        if self.raw.database.merge_operator.is_some() {
+            return self
+                .get_merged(options, key)
+                .map(|v| v.map(|v| Bytes::copy_from(&v)));
+        }
+        self.get_unmerged(options, key)
+    }
+
+    /// get a value without folding in merge operands.
+    pub(crate) fn get_unmerged(
+        &self,
+        options: &ReadOptions,
+        key: &[u8],
+    ) -> Result<Option<Bytes>, Error> {
+        unsafe {
+            let c_readoptions = self.c_readoptions(options);
+            let result = self.raw.database.get_bytes_c(c_readoptions, key);
+            leveldb_readoptions_destroy(c_readoptions);
+            result.map(|value| self.raw.database.visible(key, value))
+        }
+    }
+
+    /// get a value as it was when the snapshot was taken.
+    ///
+    /// If the database has a merge operator, pending merge operands are
+    /// folded into the value.
+    pub fn get(&self, options: &ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if self.raw.database.merge_operator.is_some() {
+            return self.get_merged(options, key);
+        }
+        self.get_bytes(options, key).map(|v| v.map(Vec::from))
+    }
+
+    /// iterate over the database as it was when the snapshot was taken.
+    ///
+    /// Merge operands are not folded in, use `merged_iter` for that.
+    pub fn iter(&self, options: &ReadOptions) -> DatabaseIterator<'a> {
+        unsafe {
+            let c_readoptions = self.c_readoptions(options);
+            let iter = DatabaseIterator::new_c(self.raw.database, c_readoptions);
+            leveldb_readoptions_destroy(c_readoptions);
+            iter
+        }
+    }
+
+    unsafe fn c_readoptions(&self, options: &ReadOptions) -> *mut leveldb_readoptions_t {
+        let c_readoptions = c_readoptions(options);
+        leveldb_readoptions_set_snapshot(c_readoptions, self.raw.ptr);
+        c_readoptions
+    }
+}
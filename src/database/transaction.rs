@@ -0,0 +1,224 @@
+//! Optimistic transactions
+//!
+//! A `Transaction` reads from a snapshot and buffers its writes. On
+//! `commit`, it checks that none of the keys it read were written since the
+//! snapshot was taken and then applies all writes as a single batch.
+//!
+//! Changes are tracked in an in-process version table that covers every
+//! write made through the same `Database` handle. Writes from other
+//! processes, or other handles, are not detected. The table is striped by
+//! key hash, so unrelated keys can occasionally cause a spurious conflict.
+use super::atomic::key_hash;
+use super::batch::{Writebatch, WritebatchIterator};
+use super::error::Error;
+use super::snapshots::Snapshot;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+const SLOTS: usize = 1024;
+
+/// Tracks, per key stripe, the version of the last write.
+///
+/// Mutations hold the lock shared while writing and bumping versions, so
+/// that taking it exclusively gives a consistent view of data and versions.
+pub(crate) struct VersionTable {
+    clock: AtomicU64,
+    slots: Vec<AtomicU64>,
+    lock: RwLock<()>,
+}
+
+impl VersionTable {
+    pub(crate) fn new() -> VersionTable {
+        VersionTable {
+            clock: AtomicU64::new(0),
+            slots: (0..SLOTS).map(|_| AtomicU64::new(0)).collect(),
+            lock: RwLock::new(()),
+        }
+    }
+
+    fn slot(&self, key: &[u8]) -> &AtomicU64 {
+        &self.slots[(key_hash(key) % self.slots.len() as u64) as usize]
+    }
+
+    /// to be held while performing a write.
+    pub(crate) fn mutation(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// excludes all writes while held.
+    pub(crate) fn exclusive(&self) -> RwLockWriteGuard<'_, ()> {
+        self.lock.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn current(&self) -> u64 {
+        self.clock.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn bump(&self, key: &[u8]) {
+        let version = self.clock.fetch_add(1, Ordering::SeqCst) + 1;
+        self.slot(key).fetch_max(version, Ordering::SeqCst);
+    }
+
+    pub(crate) fn bump_batch(&self, batch: &Writebatch) {
+        batch.iterate(Box::new(Bumper(self)));
+    }
+
+    pub(crate) fn changed_since(&self, key: &[u8], version: u64) -> bool {
+        self.slot(key).load(Ordering::SeqCst) > version
+    }
+}
+
+struct Bumper<'a>(&'a VersionTable);
+
+impl<'a> WritebatchIterator for Bumper<'a> {
+    fn put(&mut self, key: &[u8], _value: &[u8]) {
+        self.0.bump(key);
+    }
+
+    fn deleted(&mut self, key: &[u8]) {
+        self.0.bump(key);
+    }
+}
+
+/// The error returned when committing a transaction fails.
+#[derive(Debug)]
+pub enum TransactionError {
+    /// a key read by the transaction was written since its snapshot was taken.
+    /// Nothing was written, the transaction can be retried.
+    Conflict,
+    /// waiting for a lock would have deadlocked.
+    Deadlock,
+    /// a lock could not be acquired within the timeout.
+    Timeout,
+    /// leveldb reported an error.
+    Database(Error),
+}
+
+impl From<Error> for TransactionError {
+    fn from(error: Error) -> TransactionError {
+        TransactionError::Database(error)
+    }
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransactionError::Conflict => write!(f, "transaction conflict"),
+            TransactionError::Deadlock => write!(f, "transaction deadlock"),
+            TransactionError::Timeout => write!(f, "transaction lock timeout"),
+            TransactionError::Database(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            TransactionError::Database(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An optimistic transaction
+///
+/// Reads see the database as of the start of the transaction, plus the
+/// transaction's own writes. Writes are buffered until `commit`. Dropping
+/// a transaction without committing discards its writes.
+pub struct Transaction<'a> {
+    snapshot: Snapshot<'a>,
+    version: u64,
+    reads: HashSet<Vec<u8>>,
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl Database {
+    /// start a new optimistic transaction.
+    pub fn transaction(&self) -> Transaction<'_> {
+        let _guard = self.versions.exclusive();
+        Transaction {
+            version: self.versions.current(),
+            snapshot: self.snapshot(),
+            reads: HashSet::new(),
+            writes: BTreeMap::new(),
+        }
+    }
+}
+
+impl<'a> Transaction<'a> {
+    /// get a value, as seen by this transaction.
+    ///
+    /// Unless the key was written by this transaction, it is recorded as
+    /// read and checked for conflicts on commit.
+    pub fn get(&mut self, options: &ReadOptions, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        self.reads.insert(key.to_vec());
+        self.snapshot.get(options, key)
+    }
+
+    /// check whether a key is present, as seen by this transaction.
+    pub fn contains_key(&mut self, options: &ReadOptions, key: &[u8]) -> Result<bool, Error> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.is_some());
+        }
+        self.reads.insert(key.to_vec());
+        self.snapshot.get_bytes(options, key).map(|v| v.is_some())
+    }
+
+    /// buffer a put of `value` under `key`.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    /// buffer a delete of `key`.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// validate the transaction and apply its writes atomically.
+    ///
+    /// Returns `TransactionError::Conflict` without writing anything if a
+    /// key read by the transaction was changed since it started.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn commit(self, options: &WriteOptions) -> Result<(), TransactionError> {
+        let database = self.snapshot.database();
+        let mut batch = Writebatch::new();
+        for (key, value) in &self.writes {
+            match *value {
+                Some(ref value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+        }
+        let _locks = database.lock_operands(&batch);
+        let _guard = database.versions.exclusive();
+
+        if self
+            .reads
+            .iter()
+            .any(|key| database.versions.changed_since(key, self.version))
+        {
+            return Err(TransactionError::Conflict);
+        }
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+
+        let prepared = database.prepare_batch(&batch);
+        let publisher = database.watchers.publisher(|events| events.batch(&batch));
+        unsafe { database.write_c(options, prepared.as_ref().unwrap_or(&batch))? };
+        for key in self.writes.keys() {
+            database.versions.bump(key);
+        }
+        if let Some(publisher) = publisher {
+            publisher.publish();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Values with a time-to-live
+//!
+//! When a database is opened with `Options::ttl`, every value is stored
+//! behind an 8 byte header holding its expiry time in milliseconds since
+//! the Unix epoch, or 0 for values that never expire. Reads through
+//! `get_bytes`, `get`, iterators and snapshots strip the header and hide
+//! expired values, but expired values keep taking up space until
+//! `purge_expired` deletes them.
+//!
+//! `put_with_ttl` also records the key in an index ordered by expiry
+//! time, under the reserved key prefix `\xff\xff`, so that purging does not
+//! have to scan the whole database.
+//!
+//! A database must always be opened with the same `ttl` setting. Opening an
+//! empty database with `ttl` marks it with a reserved key; opening a marked
+//! database without `ttl`, or a database holding plain values with `ttl`,
+//! fails.
+use super::batch::{Writebatch, WritebatchIterator};
+use super::bytes::Bytes;
+use super::error::Error;
+use super::reserved::RESERVED_PREFIX;
+use super::Database;
+use crate::options::{ReadOptions, WriteOptions};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INDEX_PREFIX: &[u8] = b"\xff\xffttl\x00";
+// present in databases opened with ttl. Sorts after all index entries.
+pub(crate) const MODE_KEY: &[u8] = b"\xff\xffttl\x01";
+const HEADER_LEN: usize = 8;
+const PURGE_BATCH: usize = 256;
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn expiry_of(value: &[u8]) -> u64 {
+    if value.len() < HEADER_LEN {
+        return 0;
+    }
+    let mut header = [0; HEADER_LEN];
+    header.copy_from_slice(&value[..HEADER_LEN]);
+    u64::from_be_bytes(header)
+}
+
+pub(crate) fn strip_header(value: &[u8]) -> &[u8] {
+    &value[HEADER_LEN.min(value.len())..]
+}
+
+/// whether a stored record should be invisible to readers at `now`.
+pub(crate) fn hidden(key: &[u8], value: &[u8], now: u64) -> bool {
+    let expiry = expiry_of(value);
+    key.starts_with(RESERVED_PREFIX) || (expiry != 0 && expiry <= now)
+}
+
+fn with_header(expiry: u64, value: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(HEADER_LEN + value.len());
+    stored.extend_from_slice(&expiry.to_be_bytes());
+    stored.extend_from_slice(value);
+    stored
+}
+
+fn index_key(expiry: u64, key: &[u8]) -> Vec<u8> {
+    let mut index = INDEX_PREFIX.to_vec();
+    index.extend_from_slice(&expiry.to_be_bytes());
+    index.extend_from_slice(key);
+    index
+}
+
+impl Database {
+    /// check that the database was created with the `ttl` setting it is
+    /// opened with, marking new databases opened with `ttl`.
+    pub(crate) fn check_ttl_mode(&self) -> Result<(), Error> {
+        let read_options = ReadOptions::default();
+        let marked = self.get_bytes_raw(&read_options, MODE_KEY)?.is_some();
+        if marked && !self.ttl {
+            return Err(Error::new(
+                "database was created with ttl, open it with Options::ttl".to_string(),
+            ));
+        }
+        if marked || !self.ttl {
+            return Ok(());
+        }
+        let has_values = {
+            let mut iter = self.iter(&read_options).raw();
+            iter.seek_to_first();
+            iter.valid() && !iter.key().starts_with(RESERVED_PREFIX)
+        };
+        if has_values {
+            return Err(Error::new(
+                "database holds values without expiry headers, open it without Options::ttl"
+                    .to_string(),
+            ));
+        }
+        let mut batch = Writebatch::new();
+        batch.put(MODE_KEY, &[]);
+        unsafe { self.write_raw(&WriteOptions { sync: true }, &batch) }
+    }
+
+    /// strip the header from a stored value, hiding it if expired.
+    pub(crate) fn visible(&self, key: &[u8], value: Option<Bytes>) -> Option<Bytes> {
+        if !self.ttl {
+            return value;
+        }
+        value
+            .filter(|v| !hidden(key, v, now_millis()))
+            .map(|v| v.skip(HEADER_LEN))
+    }
+
+    /// copy a user batch, adding a never-expiring header to its values.
+    pub(crate) fn add_expiry_headers(&self, batch: &Writebatch) -> Writebatch {
+        struct Copy(Writebatch);
+
+        impl WritebatchIterator for Copy {
+            fn put(&mut self, key: &[u8], value: &[u8]) {
+                self.0.put(key, &with_header(0, value));
+            }
+
+            fn deleted(&mut self, key: &[u8]) {
+                self.0.delete(key);
+            }
+        }
+
+        batch.iterate(Box::new(Copy(Writebatch::new()))).0
+    }
+
+    /// put a value that expires after `ttl`.
+    ///
+    /// Fails if the database was not opened with `Options::ttl`.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn put_with_ttl(
+        &self,
+        options: &WriteOptions,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        if !self.ttl {
+            return Err(Error::new("database not opened with ttl".to_string()));
+        }
+        let expiry = now_millis().saturating_add(ttl.as_millis() as u64).max(1);
+        let mut batch = Writebatch::new();
+        batch.put(key, &with_header(expiry, value));
+        batch.put(&index_key(expiry, key), &[]);
+
+        let _guard = self.versions.mutation();
+        let publisher = self.watchers.publisher(|events| events.put(key, value));
+        unsafe { self.write_c(options, &batch)? };
+        self.versions.bump(key);
+        if let Some(publisher) = publisher {
+            publisher.publish();
+        }
+        Ok(())
+    }
+
+    /// delete all expired values, in batches.
+    ///
+    /// Only values written with `put_with_ttl` are found. Expired entries
+    /// are looked up without blocking writers; writes are only held off
+    /// while a batch of them is checked and deleted. Returns the number of
+    /// values deleted.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn purge_expired(&self, options: &WriteOptions) -> Result<usize, Error> {
+        let now = now_millis();
+        let read_options = ReadOptions::default();
+        let mut purged = 0;
+        loop {
+            let mut entries = Vec::new();
+            {
+                let mut iter = self.iter(&read_options).raw();
+                iter.seek(INDEX_PREFIX);
+                while entries.len() < PURGE_BATCH
+                    && iter.valid()
+                    && iter.key().starts_with(INDEX_PREFIX)
+                {
+                    if expiry_of(&iter.key()[INDEX_PREFIX.len()..]) > now {
+                        break;
+                    }
+                    entries.push(iter.key().to_vec());
+                    iter.next();
+                }
+            }
+            if entries.is_empty() {
+                return Ok(purged);
+            }
+
+            // keep writers out between checking a value and deleting it
+            let _guard = self.versions.exclusive();
+            let mut batch = Writebatch::new();
+            let mut keys = Vec::new();
+            for entry in &entries {
+                let expiry = expiry_of(&entry[INDEX_PREFIX.len()..]);
+                if entry.len() >= INDEX_PREFIX.len() + HEADER_LEN {
+                    let key = &entry[INDEX_PREFIX.len() + HEADER_LEN..];
+                    // the key may have been overwritten since
+                    if let Some(value) = self.get_bytes_raw(&read_options, key)? {
+                        if expiry_of(&value) == expiry {
+                            batch.delete(key);
+                            keys.push(key.to_vec());
+                        }
+                    }
+                }
+                batch.delete(entry);
+            }
+            let publisher = self.watchers.publisher(|events| {
+                for key in &keys {
+                    events.delete(key);
+                }
+            });
+            unsafe { self.write_c(options, &batch)? };
+            for key in &keys {
+                self.versions.bump(key);
+            }
+            if let Some(publisher) = publisher {
+                publisher.publish();
+            }
+            purged += keys.len();
+            if entries.len() < PURGE_BATCH {
+                return Ok(purged);
+            }
+        }
+    }
+}
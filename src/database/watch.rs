@@ -0,0 +1,335 @@
+//! Change notifications for key ranges
+//!
+//! `Database::watch` returns a channel receiving an event for every write
+//! made through the same `Database` handle to a key in the watched range:
+//! single puts, deletes and merges, transactions, every operation of a
+//! write batch, values removed by `purge_expired` and values rewritten by
+//! `fold_merges`. Writes made by other processes or other handles to the
+//! same directory are not seen, and neither are internal bookkeeping
+//! records.
+//!
+//! Writes to the same key are delivered in the order they were applied,
+//! and a write that finished before another one started is delivered
+//! first. Events are sent once the write was applied, so subscribing never
+//! waits for a write in progress. Channels are
+//! bounded so that a slow subscriber can't make memory grow without limit
+//! or stall writers: a subscriber whose channel is full when an event
+//! arrives is dropped. It receives the events buffered so far and then
+//! sees the channel disconnect, after which it has to re-read the range and
+//! call `watch` again.
+use super::atomic::KeyLocks;
+use super::batch::{Writebatch, WritebatchIterator};
+use super::reserved::RESERVED_PREFIX;
+use super::ttl::strip_header;
+use super::Database;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The number of events buffered for a subscriber by `Database::watch`.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// A write to a watched key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+    /// `key` was set to `value`
+    Put {
+        /// the written key
+        key: Vec<u8>,
+        /// the new value
+        value: Vec<u8>,
+    },
+    /// `key` was deleted
+    Delete {
+        /// the deleted key
+        key: Vec<u8>,
+    },
+    /// a merge operand was recorded for `key`
+    Merge {
+        /// the merged key
+        key: Vec<u8>,
+        /// the operand
+        operand: Vec<u8>,
+    },
+}
+
+impl ChangeEvent {
+    /// The key the event is about.
+    pub fn key(&self) -> &[u8] {
+        match *self {
+            ChangeEvent::Put { ref key, .. }
+            | ChangeEvent::Delete { ref key }
+            | ChangeEvent::Merge { ref key, .. } => key,
+        }
+    }
+}
+
+/// The keys a subscriber is interested in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchRange {
+    /// all keys starting with the prefix
+    Prefix(Vec<u8>),
+    /// all keys from `start` (inclusive) up to `end` (exclusive), or to the
+    /// end of the keyspace if `end` is `None`
+    Range {
+        /// the first key of the range
+        start: Vec<u8>,
+        /// the key after the range
+        end: Option<Vec<u8>>,
+    },
+}
+
+impl WatchRange {
+    /// Watch every key.
+    pub fn all() -> WatchRange {
+        WatchRange::Prefix(Vec::new())
+    }
+
+    /// Watch the keys starting with `prefix`.
+    pub fn prefix(prefix: &[u8]) -> WatchRange {
+        WatchRange::Prefix(prefix.to_vec())
+    }
+
+    /// Watch the keys in `start..end`.
+    pub fn range(start: &[u8], end: Option<&[u8]>) -> WatchRange {
+        WatchRange::Range {
+            start: start.to_vec(),
+            end: end.map(|end| end.to_vec()),
+        }
+    }
+
+    /// check whether `key` is part of the range.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        match *self {
+            WatchRange::Prefix(ref prefix) => key.starts_with(prefix),
+            WatchRange::Range { ref start, ref end } => {
+                key >= &start[..]
+                    && match *end {
+                        Some(ref end) => key < &end[..],
+                        None => true,
+                    }
+            }
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> From<T> for WatchRange {
+    /// watch the keys starting with `prefix`.
+    fn from(prefix: T) -> WatchRange {
+        WatchRange::prefix(prefix.as_ref())
+    }
+}
+
+struct Subscriber {
+    range: WatchRange,
+    sender: SyncSender<ChangeEvent>,
+}
+
+/// Events of writes that finished, waiting for earlier writes.
+struct Pending {
+    // the sequence number of the next write to publish
+    next: u64,
+    events: BTreeMap<u64, Vec<ChangeEvent>>,
+}
+
+/// The subscribers of a database.
+pub(crate) struct Watchers {
+    active: AtomicBool,
+    subscribers: Mutex<Vec<Subscriber>>,
+    // orders writes to the same key, see `publisher`
+    order: KeyLocks,
+    sequence: AtomicU64,
+    pending: Mutex<Pending>,
+}
+
+impl Watchers {
+    pub(crate) fn new() -> Watchers {
+        Watchers {
+            active: AtomicBool::new(false),
+            subscribers: Mutex::new(Vec::new()),
+            order: KeyLocks::new(),
+            sequence: AtomicU64::new(0),
+            pending: Mutex::new(Pending {
+                next: 0,
+                events: BTreeMap::new(),
+            }),
+        }
+    }
+
+    fn subscribers(&self) -> MutexGuard<'_, Vec<Subscriber>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn subscribe(&self, range: WatchRange, capacity: usize) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = sync_channel(capacity);
+        let mut subscribers = self.subscribers();
+        subscribers.push(Subscriber { range, sender });
+        self.active.store(true, Ordering::SeqCst);
+        receiver
+    }
+
+    /// get a publisher for the events of a write, if anyone is watching.
+    ///
+    /// `events` records the events of the write. The publisher has to be
+    /// taken before the write is applied: it locks the written keys, so
+    /// that writes to the same key get increasing sequence numbers, and
+    /// events are published in sequence order. Dropping the publisher
+    /// without calling `publish`, because the write failed, publishes
+    /// nothing.
+    pub(crate) fn publisher<F>(&self, events: F) -> Option<Publisher<'_>>
+    where
+        F: FnOnce(&mut Events),
+    {
+        if !self.active.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut recorded = Events(Vec::new());
+        events(&mut recorded);
+        let order = self
+            .order
+            .lock_many(recorded.0.iter().map(|event| event.key()));
+        Some(Publisher {
+            watchers: self,
+            sequence: self.sequence.fetch_add(1, Ordering::SeqCst),
+            events: recorded.0,
+            written: false,
+            _order: order,
+        })
+    }
+
+    /// publish the events of write `sequence`, and of all later writes
+    /// that were only waiting for it.
+    fn finish(&self, sequence: u64, events: Vec<ChangeEvent>) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.events.insert(sequence, events);
+        loop {
+            let next = pending.next;
+            let events = match pending.events.remove(&next) {
+                Some(events) => events,
+                None => return,
+            };
+            pending.next += 1;
+            if events.is_empty() {
+                continue;
+            }
+            let mut subscribers = self.subscribers();
+            for event in events {
+                subscribers.retain(|subscriber| {
+                    if !subscriber.range.contains(event.key()) {
+                        return true;
+                    }
+                    match subscriber.sender.try_send(event.clone()) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => false,
+                    }
+                });
+            }
+            self.active.store(!subscribers.is_empty(), Ordering::SeqCst);
+        }
+    }
+}
+
+/// The events of one write, collected for a `Publisher`.
+pub(crate) struct Events(Vec<ChangeEvent>);
+
+impl Events {
+    fn push(&mut self, event: ChangeEvent) {
+        if !event.key().starts_with(RESERVED_PREFIX) {
+            self.0.push(event);
+        }
+    }
+
+    pub(crate) fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.push(ChangeEvent::Put {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+    }
+
+    pub(crate) fn delete(&mut self, key: &[u8]) {
+        self.push(ChangeEvent::Delete { key: key.to_vec() });
+    }
+
+    pub(crate) fn merge(&mut self, key: &[u8], operand: &[u8]) {
+        self.push(ChangeEvent::Merge {
+            key: key.to_vec(),
+            operand: operand.to_vec(),
+        });
+    }
+
+    pub(crate) fn batch(&mut self, batch: &Writebatch) {
+        self.stored_batch(batch, false);
+    }
+
+    /// the events of a batch of values as stored, with their expiry
+    /// headers if `ttl` is set.
+    pub(crate) fn stored_batch(&mut self, batch: &Writebatch, ttl: bool) {
+        struct Collect<'a>(&'a mut Events, bool);
+
+        impl<'a> WritebatchIterator for Collect<'a> {
+            fn put(&mut self, key: &[u8], value: &[u8]) {
+                let value = if self.1 { strip_header(value) } else { value };
+                self.0.put(key, value);
+            }
+
+            fn deleted(&mut self, key: &[u8]) {
+                self.0.delete(key);
+            }
+        }
+
+        batch.iterate(Box::new(Collect(self, ttl)));
+    }
+}
+
+/// Publishes the events of one write, once it was applied.
+pub(crate) struct Publisher<'a> {
+    watchers: &'a Watchers,
+    sequence: u64,
+    events: Vec<ChangeEvent>,
+    written: bool,
+    _order: Vec<MutexGuard<'a, ()>>,
+}
+
+impl<'a> Publisher<'a> {
+    /// publish the events, after the write was applied.
+    pub(crate) fn publish(mut self) {
+        self.written = true;
+    }
+}
+
+#[allow(missing_docs)]
+impl<'a> Drop for Publisher<'a> {
+    fn drop(&mut self) {
+        let events = if self.written {
+            std::mem::take(&mut self.events)
+        } else {
+            Vec::new()
+        };
+        self.watchers.finish(self.sequence, events);
+    }
+}
+
+impl Database {
+    /// watch a prefix or range of keys for writes through this handle.
+    ///
+    /// Buffers up to `DEFAULT_CAPACITY` events; see the module
+    /// documentation for what happens to subscribers that fall behind.
+    ///
+    /// `range` is a `WatchRange` or a key prefix, like `b"user:"`.
+    pub fn watch<R: Into<WatchRange>>(&self, range: R) -> Receiver<ChangeEvent> {
+        self.watch_with_capacity(range, DEFAULT_CAPACITY)
+    }
+
+    /// watch a prefix or range of keys, buffering up to `capacity` events.
+    pub fn watch_with_capacity<R: Into<WatchRange>>(
+        &self,
+        range: R,
+        capacity: usize,
+    ) -> Receiver<ChangeEvent> {
+        self.watchers.subscribe(range.into(), capacity.max(1))
+    }
+}
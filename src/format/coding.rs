@@ -0,0 +1,69 @@
+//! The integer and slice encodings used throughout leveldb's files.
+
+/// decode a varint from the start of `input`, advancing it.
+pub(crate) fn get_varint64(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// decode a varint that has to fit 32 bits.
+pub(crate) fn get_varint32(input: &mut &[u8]) -> Option<u32> {
+    let value = get_varint64(input)?;
+    if value > u64::from(u32::MAX) {
+        return None;
+    }
+    Some(value as u32)
+}
+
+/// decode a slice prefixed with its varint length, advancing `input`.
+pub(crate) fn get_length_prefixed<'a>(input: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = get_varint32(input)? as usize;
+    if input.len() < len {
+        return None;
+    }
+    let (slice, rest) = input.split_at(len);
+    *input = rest;
+    Some(slice)
+}
+
+/// decode a little endian `u32` from the start of `input`.
+pub(crate) fn decode_fixed32(input: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&input[..4]);
+    u32::from_le_bytes(buf)
+}
+
+/// decode a little endian `u64` from the start of `input`.
+pub(crate) fn decode_fixed64(input: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&input[..8]);
+    u64::from_le_bytes(buf)
+}
+
+/// append `value` as a varint.
+pub(crate) fn put_varint64(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// append `value` as a varint.
+pub(crate) fn put_varint32(out: &mut Vec<u8>, value: u32) {
+    put_varint64(out, u64::from(value))
+}
+
+/// append `slice`, prefixed with its varint length.
+pub(crate) fn put_length_prefixed(out: &mut Vec<u8>, slice: &[u8]) {
+    put_varint32(out, slice.len() as u32);
+    out.extend_from_slice(slice);
+}
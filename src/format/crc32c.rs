@@ -0,0 +1,51 @@
+//! CRC32C (Castagnoli) checksums, as used by leveldb's file formats.
+
+const POLY: u32 = 0x82f6_3b78;
+const MASK_DELTA: u32 = 0xa282_ead8;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// continue the checksum `crc` of some data with `data`.
+pub(crate) fn extend(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// the checksum of `data`.
+pub(crate) fn value(data: &[u8]) -> u32 {
+    extend(0, data)
+}
+
+/// the form checksums are stored in, which keeps checksums of data
+/// containing checksums from degenerating.
+pub(crate) fn mask(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(MASK_DELTA)
+}
+
+/// undo `mask`.
+pub(crate) fn unmask(masked: u32) -> u32 {
+    masked.wrapping_sub(MASK_DELTA).rotate_left(15)
+}
@@ -0,0 +1,184 @@
+//! leveldb's builtin bloom filter and the filter blocks of tables.
+//!
+//! A filter block holds one filter per 2KB (by default) range of data
+//! block offsets, followed by the offsets of the filters, the offset of
+//! that array and the base-2 logarithm of the range size.
+use super::coding::decode_fixed32;
+
+/// the base-2 logarithm of the range of block offsets a filter covers.
+const FILTER_BASE_LG: u8 = 11;
+
+/// the name tables record the builtin bloom filter under.
+pub(crate) const BLOOM_FILTER_NAME: &str = "leveldb.BuiltinBloomFilter2";
+
+/// leveldb's hash function, a variant of murmur.
+pub(crate) fn hash(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0xc6a4_a793;
+    let mut h = seed ^ (data.len() as u32).wrapping_mul(M);
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        h = h.wrapping_add(decode_fixed32(chunk)).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, &b) in rest.iter().enumerate() {
+            h = h.wrapping_add(u32::from(b) << (8 * i));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> 24;
+    }
+    h
+}
+
+fn bloom_hash(key: &[u8]) -> u32 {
+    hash(key, 0xbc9f_1d34)
+}
+
+/// append a bloom filter for `keys` to `out`, using about `bits_per_key`
+/// bits per key.
+pub(crate) fn create_bloom_filter(keys: &[Vec<u8>], bits_per_key: usize, out: &mut Vec<u8>) {
+    // rounding down reduces probing cost a little
+    let k = ((bits_per_key as f64 * 0.69) as u8).clamp(1, 30);
+    // small sets would have a high false positive rate
+    let bytes = (keys.len() * bits_per_key).max(64).div_ceil(8);
+    let nbits = bytes as u32 * 8;
+    let start = out.len();
+    out.resize(start + bytes, 0);
+    out.push(k);
+    let bits = &mut out[start..start + bytes];
+    for key in keys {
+        let mut h = bloom_hash(key);
+        let delta = h.rotate_right(17);
+        for _ in 0..k {
+            let pos = h % nbits;
+            bits[(pos / 8) as usize] |= 1 << (pos % 8);
+            h = h.wrapping_add(delta);
+        }
+    }
+}
+
+/// whether `key` may be in the set `filter` was built from.
+pub(crate) fn bloom_may_match(filter: &[u8], key: &[u8]) -> bool {
+    if filter.len() < 2 {
+        return false;
+    }
+    let (bits, k) = filter.split_at(filter.len() - 1);
+    let k = k[0];
+    if k > 30 {
+        // reserved for new encodings, so treat as a match
+        return true;
+    }
+    let nbits = bits.len() as u32 * 8;
+    let mut h = bloom_hash(key);
+    let delta = h.rotate_right(17);
+    for _ in 0..k {
+        let pos = h % nbits;
+        if bits[(pos / 8) as usize] & (1 << (pos % 8)) == 0 {
+            return false;
+        }
+        h = h.wrapping_add(delta);
+    }
+    true
+}
+
+/// The filter block of a table.
+pub(crate) struct FilterBlock {
+    data: Vec<u8>,
+    // start of the filter offsets in `data`
+    offsets: usize,
+    num: usize,
+    base_lg: u8,
+}
+
+impl FilterBlock {
+    /// parse a filter block, `None` if it is malformed.
+    pub(crate) fn new(data: Vec<u8>) -> Option<FilterBlock> {
+        let n = data.len();
+        if n < 5 {
+            return None;
+        }
+        let base_lg = data[n - 1];
+        let offsets = decode_fixed32(&data[n - 5..]) as usize;
+        if offsets > n - 5 {
+            return None;
+        }
+        Some(FilterBlock {
+            num: (n - 5 - offsets) / 4,
+            data,
+            offsets,
+            base_lg,
+        })
+    }
+
+    /// whether the data block at `block_offset` may contain `key`.
+    pub(crate) fn may_contain(&self, block_offset: u64, key: &[u8]) -> bool {
+        let index = (block_offset >> self.base_lg) as usize;
+        if index >= self.num {
+            // errors are treated as potential matches
+            return true;
+        }
+        let at = self.offsets + index * 4;
+        let start = decode_fixed32(&self.data[at..]) as usize;
+        let limit = decode_fixed32(&self.data[at + 4..]) as usize;
+        if start <= limit && limit <= self.offsets {
+            bloom_may_match(&self.data[start..limit], key)
+        } else {
+            start != limit
+        }
+    }
+}
+
+/// Builds the filter block of a table as its data blocks are written.
+pub(crate) struct FilterBlockBuilder {
+    bits_per_key: usize,
+    // the keys of the filter being built
+    keys: Vec<Vec<u8>>,
+    result: Vec<u8>,
+    filter_offsets: Vec<u32>,
+}
+
+impl FilterBlockBuilder {
+    pub(crate) fn new(bits_per_key: usize) -> FilterBlockBuilder {
+        FilterBlockBuilder {
+            bits_per_key,
+            keys: Vec::new(),
+            result: Vec::new(),
+            filter_offsets: Vec::new(),
+        }
+    }
+
+    /// start the data block at `block_offset`.
+    pub(crate) fn start_block(&mut self, block_offset: u64) {
+        let index = (block_offset >> FILTER_BASE_LG) as usize;
+        while index > self.filter_offsets.len() {
+            self.generate_filter();
+        }
+    }
+
+    pub(crate) fn add_key(&mut self, key: &[u8]) {
+        self.keys.push(key.to_vec());
+    }
+
+    /// the finished filter block.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        if !self.keys.is_empty() {
+            self.generate_filter();
+        }
+        let array_offset = self.result.len() as u32;
+        for offset in &self.filter_offsets {
+            self.result.extend_from_slice(&offset.to_le_bytes());
+        }
+        self.result.extend_from_slice(&array_offset.to_le_bytes());
+        self.result.push(FILTER_BASE_LG);
+        self.result
+    }
+
+    fn generate_filter(&mut self) {
+        self.filter_offsets.push(self.result.len() as u32);
+        if !self.keys.is_empty() {
+            create_bloom_filter(&self.keys, self.bits_per_key, &mut self.result);
+            self.keys.clear();
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! leveldb's write-ahead log files
+//!
+//! Log files (`*.log`, and the MANIFEST) are a sequence of 32KB blocks.
+//! Every record is stored as one or more fragments, each with a 7 byte
+//! header: the masked CRC32C of the fragment type and data, the length of
+//! the data and the fragment type. A record that fits the current block is
+//! written as a single FULL fragment, larger ones are split into FIRST,
+//! MIDDLE and LAST fragments. Block trailers too small for a header are
+//! zero-filled.
+//!
+//! `LogReader` reassembles records and reports damaged fragments as
+//! `FormatError::Corruption` with their file offset, then continues with
+//! the next intact record. `LogWriter` appends records. In `*.log` files,
+//! every record is a write batch, which `WriteBatch::decode` decodes.
+use super::coding::{decode_fixed32, decode_fixed64, get_length_prefixed};
+use super::crc32c;
+use super::{FormatError, ValueType};
+
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+
+/// The size of the blocks log files are divided into.
+pub const BLOCK_SIZE: usize = 32768;
+/// The size of a fragment header.
+pub const HEADER_SIZE: usize = 7;
+
+const ZERO_TYPE: u8 = 0;
+const FULL_TYPE: u8 = 1;
+const FIRST_TYPE: u8 = 2;
+const MIDDLE_TYPE: u8 = 3;
+const LAST_TYPE: u8 = 4;
+
+/// A record read from a log file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// the offset of the record's first fragment in the file
+    pub offset: u64,
+    /// the contents of the record
+    pub data: Vec<u8>,
+}
+
+/// A fragment, or what was found instead of one.
+enum Fragment {
+    Data {
+        kind: u8,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    Bad(FormatError),
+    Eof,
+}
+
+/// Reads the records of a log file.
+///
+/// Iterating yields records in file order. Damaged data is reported as an
+/// error item, after which reading continues; a failure to read the
+/// underlying stream ends the iteration.
+pub struct LogReader<R> {
+    reader: R,
+    block: Vec<u8>,
+    // position of the next fragment in `block`
+    pos: usize,
+    // file offset of the start of `block`
+    block_offset: u64,
+    eof: bool,
+    done: bool,
+    // a fragmented record being reassembled, with its offset
+    partial: Option<(u64, Vec<u8>)>,
+    pending: VecDeque<Result<Record, FormatError>>,
+}
+
+impl<R: Read> LogReader<R> {
+    /// read the log in `reader`, from its start.
+    pub fn new(reader: R) -> LogReader<R> {
+        LogReader {
+            reader,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            pos: 0,
+            block_offset: 0,
+            eof: false,
+            done: false,
+            partial: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        self.block_offset + self.pos as u64
+    }
+
+    /// read the next block, returning false at the end of the file.
+    fn read_block(&mut self) -> Result<bool, FormatError> {
+        self.block_offset += self.block.len() as u64;
+        self.block.resize(BLOCK_SIZE, 0);
+        self.pos = 0;
+        let mut filled = 0;
+        while filled < BLOCK_SIZE {
+            match self.reader.read(&mut self.block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.block.truncate(filled);
+                    return Err(e.into());
+                }
+            }
+        }
+        self.block.truncate(filled);
+        self.eof = filled < BLOCK_SIZE;
+        Ok(filled > 0)
+    }
+
+    fn read_fragment(&mut self) -> Fragment {
+        loop {
+            let left = self.block.len() - self.pos;
+            if left < HEADER_SIZE {
+                if self.eof {
+                    self.pos = self.block.len();
+                    if left > 0
+                        && self.block[self.block.len() - left..]
+                            .iter()
+                            .any(|&b| b != 0)
+                    {
+                        return Fragment::Bad(FormatError::corruption(
+                            self.offset() - left as u64,
+                            left as u64,
+                            "truncated fragment header",
+                        ));
+                    }
+                    return Fragment::Eof;
+                }
+                match self.read_block() {
+                    Ok(true) => continue,
+                    Ok(false) => return Fragment::Eof,
+                    Err(e) => return Fragment::Bad(e),
+                }
+            }
+
+            let offset = self.offset();
+            let header = &self.block[self.pos..self.pos + HEADER_SIZE];
+            let length = usize::from(u16::from_le_bytes([header[4], header[5]]));
+            let kind = header[6];
+            let expected = crc32c::unmask(decode_fixed32(header));
+            if HEADER_SIZE + length > left {
+                self.pos = self.block.len();
+                let reason = if self.eof {
+                    "truncated fragment"
+                } else {
+                    "bad fragment length"
+                };
+                return Fragment::Bad(FormatError::corruption(offset, left as u64, reason));
+            }
+            if kind == ZERO_TYPE && length == 0 {
+                // preallocated space, nothing more in this block
+                self.pos = self.block.len();
+                continue;
+            }
+
+            let data = &self.block[self.pos + HEADER_SIZE..self.pos + HEADER_SIZE + length];
+            if crc32c::extend(crc32c::value(&[kind]), data) != expected {
+                // the length may be damaged too, so skip the whole block
+                self.pos = self.block.len();
+                return Fragment::Bad(FormatError::corruption(
+                    offset,
+                    left as u64,
+                    "checksum mismatch",
+                ));
+            }
+            let data = data.to_vec();
+            self.pos += HEADER_SIZE + length;
+            return Fragment::Data { kind, offset, data };
+        }
+    }
+
+    /// report and drop a partially reassembled record.
+    fn drop_partial(&mut self, reason: &str) {
+        if let Some((offset, data)) = self.partial.take() {
+            self.pending.push_back(Err(FormatError::corruption(
+                offset,
+                data.len() as u64,
+                reason,
+            )));
+        }
+    }
+
+    /// read fragments until a record or error can be returned.
+    fn fill(&mut self) {
+        while self.pending.is_empty() && !self.done {
+            match self.read_fragment() {
+                Fragment::Eof => {
+                    self.drop_partial("record truncated at end of file");
+                    self.done = true;
+                }
+                Fragment::Bad(FormatError::Io(e)) => {
+                    self.pending.push_back(Err(FormatError::Io(e)));
+                    self.done = true;
+                }
+                Fragment::Bad(e) => {
+                    self.drop_partial("record interrupted by a damaged fragment");
+                    self.pending.push_back(Err(e));
+                }
+                Fragment::Data { kind, offset, data } => match kind {
+                    FULL_TYPE => {
+                        self.drop_partial("record missing its last fragment");
+                        self.pending.push_back(Ok(Record { offset, data }));
+                    }
+                    FIRST_TYPE => {
+                        self.drop_partial("record missing its last fragment");
+                        self.partial = Some((offset, data));
+                    }
+                    MIDDLE_TYPE | LAST_TYPE => match self.partial.take() {
+                        Some((start, mut record)) => {
+                            record.extend_from_slice(&data);
+                            if kind == LAST_TYPE {
+                                self.pending.push_back(Ok(Record {
+                                    offset: start,
+                                    data: record,
+                                }));
+                            } else {
+                                self.partial = Some((start, record));
+                            }
+                        }
+                        None => self.pending.push_back(Err(FormatError::corruption(
+                            offset,
+                            (HEADER_SIZE + data.len()) as u64,
+                            "fragment without the start of its record",
+                        ))),
+                    },
+                    _ => {
+                        self.drop_partial("record interrupted by a damaged fragment");
+                        self.pending.push_back(Err(FormatError::corruption(
+                            offset,
+                            (HEADER_SIZE + data.len()) as u64,
+                            &format!("unknown fragment type {}", kind),
+                        )));
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for LogReader<R> {
+    type Item = Result<Record, FormatError>;
+
+    fn next(&mut self) -> Option<Result<Record, FormatError>> {
+        self.fill();
+        self.pending.pop_front()
+    }
+}
+
+/// Appends records to a log file.
+pub struct LogWriter<W> {
+    writer: W,
+    // bytes used of the current block
+    block_offset: usize,
+}
+
+impl<W: Write> LogWriter<W> {
+    /// write records to `writer`, which is positioned at the end of a log
+    /// of `length` bytes.
+    pub fn new(writer: W, length: u64) -> LogWriter<W> {
+        LogWriter {
+            writer,
+            block_offset: (length % BLOCK_SIZE as u64) as usize,
+        }
+    }
+
+    /// append a record.
+    pub fn add_record(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let mut first = true;
+        loop {
+            let left = BLOCK_SIZE - self.block_offset;
+            if left < HEADER_SIZE {
+                // too small for a header, so start a new block
+                self.writer.write_all(&[0; HEADER_SIZE][..left])?;
+                self.block_offset = 0;
+                continue;
+            }
+            let length = data.len().min(left - HEADER_SIZE);
+            let last = length == data.len();
+            let kind = match (first, last) {
+                (true, true) => FULL_TYPE,
+                (true, false) => FIRST_TYPE,
+                (false, false) => MIDDLE_TYPE,
+                (false, true) => LAST_TYPE,
+            };
+            let (fragment, rest) = data.split_at(length);
+            let crc = crc32c::mask(crc32c::extend(crc32c::value(&[kind]), fragment));
+            let mut header = [0; HEADER_SIZE];
+            header[..4].copy_from_slice(&crc.to_le_bytes());
+            header[4..6].copy_from_slice(&(length as u16).to_le_bytes());
+            header[6] = kind;
+            self.writer.write_all(&header)?;
+            self.writer.write_all(fragment)?;
+            self.block_offset += HEADER_SIZE + length;
+            data = rest;
+            first = false;
+            if last {
+                return self.writer.flush();
+            }
+        }
+    }
+
+    /// the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// An operation of a write batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    /// whether the key was set or deleted
+    pub value_type: ValueType,
+    /// the key
+    pub key: Vec<u8>,
+    /// the value, empty for deletions
+    pub value: Vec<u8>,
+}
+
+/// A write batch, as stored in log records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteBatch {
+    /// the sequence number of the first operation; the following ones
+    /// have consecutive numbers
+    pub sequence: u64,
+    /// the operations, in the order they were applied
+    pub entries: Vec<BatchEntry>,
+}
+
+impl WriteBatch {
+    /// decode the write batch stored in a log record.
+    pub fn decode(record: &Record) -> Result<WriteBatch, FormatError> {
+        let corrupt =
+            |reason| FormatError::corruption(record.offset, record.data.len() as u64, reason);
+        if record.data.len() < 12 {
+            return Err(corrupt("write batch too small"));
+        }
+        let sequence = decode_fixed64(&record.data);
+        let count = decode_fixed32(&record.data[8..]) as usize;
+        let mut input = &record.data[12..];
+        let mut entries = Vec::with_capacity(count.min(input.len()));
+        while let Some((&tag, rest)) = input.split_first() {
+            input = rest;
+            let value_type =
+                ValueType::from_byte(tag).ok_or_else(|| corrupt("unknown write batch tag"))?;
+            let key =
+                get_length_prefixed(&mut input).ok_or_else(|| corrupt("bad write batch key"))?;
+            let value = match value_type {
+                ValueType::Value => get_length_prefixed(&mut input)
+                    .ok_or_else(|| corrupt("bad write batch value"))?,
+                ValueType::Deletion => &[],
+            };
+            entries.push(BatchEntry {
+                value_type,
+                key: key.to_vec(),
+                value: value.to_vec(),
+            });
+        }
+        if entries.len() != count {
+            return Err(corrupt("wrong write batch count"));
+        }
+        Ok(WriteBatch { sequence, entries })
+    }
+}
@@ -0,0 +1,374 @@
+//! leveldb's MANIFEST files
+//!
+//! The MANIFEST records the history of the database's layout as a log
+//! (see the `log` module) of version edits. Each edit changes the state
+//! left by the ones before it: it may set the comparator name, the current
+//! log number, the next file number and the last sequence number, move a
+//! level's compaction pointer, and remove or add table files to levels.
+//! The CURRENT file names the MANIFEST in use.
+//!
+//! `Manifest::open_current` replays all edits of a database's MANIFEST to
+//! reconstruct the tables of every level; `append_edit` adds an edit.
+use super::coding::{
+    get_length_prefixed, get_varint32, get_varint64, put_length_prefixed, put_varint32,
+    put_varint64,
+};
+use super::log::{LogReader, LogWriter, Record};
+use super::{FormatError, InternalKey};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// The number of levels of a leveldb database.
+pub const NUM_LEVELS: usize = 7;
+
+const COMPARATOR: u32 = 1;
+const LOG_NUMBER: u32 = 2;
+const NEXT_FILE_NUMBER: u32 = 3;
+const LAST_SEQUENCE: u32 = 4;
+const COMPACT_POINTER: u32 = 5;
+const DELETED_FILE: u32 = 6;
+const NEW_FILE: u32 = 7;
+const PREV_LOG_NUMBER: u32 = 9;
+
+/// A table file, as recorded in the MANIFEST.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMetaData {
+    /// the file number, which gives the name of the file
+    pub number: u64,
+    /// the size of the file in bytes
+    pub size: u64,
+    /// the first key in the table
+    pub smallest: InternalKey,
+    /// the last key in the table
+    pub largest: InternalKey,
+}
+
+/// A change to the layout of a database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionEdit {
+    /// the name of the database's comparator
+    pub comparator: Option<String>,
+    /// logs older than this one are no longer needed
+    pub log_number: Option<u64>,
+    /// the log of a memtable being compacted, in old databases
+    pub prev_log_number: Option<u64>,
+    /// the number the next new file gets
+    pub next_file_number: Option<u64>,
+    /// the sequence number of the latest write
+    pub last_sequence: Option<u64>,
+    /// where the next compaction of a level starts, by level
+    pub compact_pointers: Vec<(usize, InternalKey)>,
+    /// the tables removed, as level and file number
+    pub deleted_files: Vec<(usize, u64)>,
+    /// the tables added, by level
+    pub new_files: Vec<(usize, FileMetaData)>,
+}
+
+/// decode a level, which has to be one leveldb has.
+fn get_level(input: &mut &[u8]) -> Option<usize> {
+    let level = get_varint32(input)? as usize;
+    if level < NUM_LEVELS {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+fn get_internal_key(input: &mut &[u8]) -> Option<InternalKey> {
+    InternalKey::decode(get_length_prefixed(input)?)
+}
+
+impl VersionEdit {
+    /// decode the version edit stored in a MANIFEST record.
+    pub fn decode(record: &Record) -> Result<VersionEdit, FormatError> {
+        let corrupt =
+            |reason| FormatError::corruption(record.offset, record.data.len() as u64, reason);
+        let mut edit = VersionEdit::default();
+        let mut input = &record.data[..];
+        while !input.is_empty() {
+            let tag = get_varint32(&mut input).ok_or_else(|| corrupt("bad tag"))?;
+            match tag {
+                COMPARATOR => {
+                    let name = get_length_prefixed(&mut input)
+                        .ok_or_else(|| corrupt("bad comparator name"))?;
+                    edit.comparator = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                LOG_NUMBER => {
+                    edit.log_number =
+                        Some(get_varint64(&mut input).ok_or_else(|| corrupt("bad log number"))?)
+                }
+                PREV_LOG_NUMBER => {
+                    edit.prev_log_number = Some(
+                        get_varint64(&mut input)
+                            .ok_or_else(|| corrupt("bad previous log number"))?,
+                    )
+                }
+                NEXT_FILE_NUMBER => {
+                    edit.next_file_number = Some(
+                        get_varint64(&mut input).ok_or_else(|| corrupt("bad next file number"))?,
+                    )
+                }
+                LAST_SEQUENCE => {
+                    edit.last_sequence =
+                        Some(get_varint64(&mut input).ok_or_else(|| corrupt("bad last sequence"))?)
+                }
+                COMPACT_POINTER => {
+                    let pointer = get_level(&mut input)
+                        .and_then(|level| Some((level, get_internal_key(&mut input)?)))
+                        .ok_or_else(|| corrupt("bad compaction pointer"))?;
+                    edit.compact_pointers.push(pointer);
+                }
+                DELETED_FILE => {
+                    let deleted = get_level(&mut input)
+                        .and_then(|level| Some((level, get_varint64(&mut input)?)))
+                        .ok_or_else(|| corrupt("bad deleted file"))?;
+                    edit.deleted_files.push(deleted);
+                }
+                NEW_FILE => {
+                    let new_file = (|| {
+                        let level = get_level(&mut input)?;
+                        let file = FileMetaData {
+                            number: get_varint64(&mut input)?,
+                            size: get_varint64(&mut input)?,
+                            smallest: get_internal_key(&mut input)?,
+                            largest: get_internal_key(&mut input)?,
+                        };
+                        Some((level, file))
+                    })()
+                    .ok_or_else(|| corrupt("bad new file"))?;
+                    edit.new_files.push(new_file);
+                }
+                _ => return Err(corrupt(&format!("unknown tag {}", tag))),
+            }
+        }
+        Ok(edit)
+    }
+
+    /// encode the edit as a MANIFEST record.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(ref comparator) = self.comparator {
+            put_varint32(&mut out, COMPARATOR);
+            put_length_prefixed(&mut out, comparator.as_bytes());
+        }
+        let numbers = [
+            (LOG_NUMBER, self.log_number),
+            (PREV_LOG_NUMBER, self.prev_log_number),
+            (NEXT_FILE_NUMBER, self.next_file_number),
+            (LAST_SEQUENCE, self.last_sequence),
+        ];
+        for &(tag, number) in &numbers {
+            if let Some(number) = number {
+                put_varint32(&mut out, tag);
+                put_varint64(&mut out, number);
+            }
+        }
+        for (level, key) in &self.compact_pointers {
+            put_varint32(&mut out, COMPACT_POINTER);
+            put_varint32(&mut out, *level as u32);
+            put_length_prefixed(&mut out, &key.encode());
+        }
+        for &(level, number) in &self.deleted_files {
+            put_varint32(&mut out, DELETED_FILE);
+            put_varint32(&mut out, level as u32);
+            put_varint64(&mut out, number);
+        }
+        for (level, file) in &self.new_files {
+            put_varint32(&mut out, NEW_FILE);
+            put_varint32(&mut out, *level as u32);
+            put_varint64(&mut out, file.number);
+            put_varint64(&mut out, file.size);
+            put_length_prefixed(&mut out, &file.smallest.encode());
+            put_length_prefixed(&mut out, &file.largest.encode());
+        }
+        out
+    }
+}
+
+/// The layout of a database, as left by a sequence of version edits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    /// the name of the database's comparator
+    pub comparator: Option<String>,
+    /// logs older than this one are no longer needed
+    pub log_number: u64,
+    /// the log of a memtable being compacted, in old databases
+    pub prev_log_number: u64,
+    /// the number the next new file gets
+    pub next_file_number: u64,
+    /// the sequence number of the latest write
+    pub last_sequence: u64,
+    /// where the next compaction of each level starts
+    pub compact_pointers: Vec<Option<InternalKey>>,
+    /// the tables of each level, sorted by their smallest key
+    pub levels: Vec<Vec<FileMetaData>>,
+}
+
+impl Version {
+    /// the layout of an empty database.
+    pub fn new() -> Version {
+        Version {
+            comparator: None,
+            log_number: 0,
+            prev_log_number: 0,
+            next_file_number: 0,
+            last_sequence: 0,
+            compact_pointers: vec![None; NUM_LEVELS],
+            levels: vec![Vec::new(); NUM_LEVELS],
+        }
+    }
+
+    /// apply an edit.
+    pub fn apply(&mut self, edit: &VersionEdit) {
+        self.apply_unsorted(edit);
+        self.sort_levels();
+    }
+
+    /// apply a sequence of edits, sorting the levels once at the end.
+    pub fn apply_all<'a, I: IntoIterator<Item = &'a VersionEdit>>(&mut self, edits: I) {
+        for edit in edits {
+            self.apply_unsorted(edit);
+        }
+        self.sort_levels();
+    }
+
+    /// apply an edit, appending new tables to the end of their level.
+    fn apply_unsorted(&mut self, edit: &VersionEdit) {
+        if let Some(ref comparator) = edit.comparator {
+            self.comparator = Some(comparator.clone());
+        }
+        if let Some(log_number) = edit.log_number {
+            self.log_number = log_number;
+        }
+        if let Some(prev_log_number) = edit.prev_log_number {
+            self.prev_log_number = prev_log_number;
+        }
+        if let Some(next_file_number) = edit.next_file_number {
+            self.next_file_number = next_file_number;
+        }
+        if let Some(last_sequence) = edit.last_sequence {
+            self.last_sequence = last_sequence;
+        }
+        for (level, key) in &edit.compact_pointers {
+            self.compact_pointers[*level] = Some(key.clone());
+        }
+        for &(level, number) in &edit.deleted_files {
+            self.levels[level].retain(|file| file.number != number);
+        }
+        for (level, file) in &edit.new_files {
+            let files = &mut self.levels[*level];
+            files.retain(|other| other.number != file.number);
+            files.push(file.clone());
+        }
+    }
+
+    fn sort_levels(&mut self) {
+        for files in &mut self.levels {
+            files.sort_by(|a, b| {
+                a.smallest
+                    .compare(&b.smallest)
+                    .then(a.number.cmp(&b.number))
+            });
+        }
+    }
+
+    /// the oldest log that still holds writes not in any table.
+    pub fn min_log_number(&self) -> u64 {
+        if self.prev_log_number != 0 {
+            self.prev_log_number.min(self.log_number)
+        } else {
+            self.log_number
+        }
+    }
+
+    /// the number of every table in the database.
+    pub fn live_files(&self) -> Vec<u64> {
+        let mut numbers: Vec<u64> = self.levels.iter().flatten().map(|f| f.number).collect();
+        numbers.sort_unstable();
+        numbers
+    }
+}
+
+impl Default for Version {
+    fn default() -> Version {
+        Version::new()
+    }
+}
+
+/// A MANIFEST file, read and replayed.
+#[derive(Debug)]
+pub struct Manifest {
+    /// the version edits, in the order they were written
+    pub edits: Vec<VersionEdit>,
+    /// the layout after applying all edits
+    pub version: Version,
+    /// damaged records, which were skipped
+    pub corruptions: Vec<FormatError>,
+    /// how many of the `corruptions` come after the last intact edit. A
+    /// damaged end is usually a record that was still being appended.
+    pub trailing_corruptions: usize,
+}
+
+impl Manifest {
+    /// read a MANIFEST, skipping damaged records.
+    pub fn read<R: Read>(reader: R) -> Result<Manifest, FormatError> {
+        let mut manifest = Manifest {
+            edits: Vec::new(),
+            version: Version::new(),
+            corruptions: Vec::new(),
+            trailing_corruptions: 0,
+        };
+        for record in LogReader::new(reader) {
+            match record.and_then(|record| VersionEdit::decode(&record)) {
+                Ok(edit) => {
+                    manifest.edits.push(edit);
+                    manifest.trailing_corruptions = 0;
+                }
+                Err(FormatError::Io(e)) => return Err(FormatError::Io(e)),
+                Err(e) => {
+                    manifest.corruptions.push(e);
+                    manifest.trailing_corruptions += 1;
+                }
+            }
+        }
+        manifest.version.apply_all(&manifest.edits);
+        Ok(manifest)
+    }
+
+    /// read the MANIFEST at `path`.
+    pub fn open(path: &Path) -> Result<Manifest, FormatError> {
+        Manifest::read(BufReader::new(File::open(path)?))
+    }
+
+    /// read the MANIFEST the database in `dir` uses.
+    pub fn open_current(dir: &Path) -> Result<Manifest, FormatError> {
+        Manifest::open(&current_manifest(dir)?)
+    }
+}
+
+/// the path of the MANIFEST named by the CURRENT file of the database in
+/// `dir`.
+pub fn current_manifest(dir: &Path) -> io::Result<PathBuf> {
+    let current = fs::read_to_string(dir.join("CURRENT"))?;
+    let name = current.trim_end_matches('\n');
+    if !name.starts_with("MANIFEST-") || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("CURRENT names no MANIFEST: {:?}", current),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+/// append `edit` to the MANIFEST at `path` and sync it.
+///
+/// Only safe while no database has the MANIFEST open.
+pub fn append_edit(path: &Path, edit: &VersionEdit) -> io::Result<()> {
+    let file = OpenOptions::new().append(true).open(path)?;
+    let length = file.metadata()?.len();
+    let mut writer = LogWriter::new(file, length);
+    writer.add_record(&edit.encode())?;
+    writer.into_inner().sync_all()
+}
@@ -0,0 +1,174 @@
+//! Pure-Rust readers and writers for leveldb's on-disk formats
+//!
+//! These work on the files of a database directory without going through
+//! the C library, so they can inspect databases that won't open, or that
+//! are being used by another process. Readers report corrupted data
+//! together with its position instead of giving up at the first problem.
+//! `sst_writer` writes table files for bulk loading.
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::io;
+
+pub(crate) mod coding;
+pub(crate) mod crc32c;
+pub(crate) mod filter;
+pub mod log;
+pub mod manifest;
+pub mod sst_writer;
+pub mod table;
+
+/// The name of leveldb's default comparator, which orders keys bytewise.
+pub const BYTEWISE_COMPARATOR: &str = "leveldb.BytewiseComparator";
+
+/// The largest sequence number, which fits 56 bits.
+pub const MAX_SEQUENCE: u64 = (1 << 56) - 1;
+
+/// An error reading one of leveldb's files.
+#[derive(Debug)]
+pub enum FormatError {
+    /// reading the file failed
+    Io(io::Error),
+    /// the data at `offset` is damaged
+    Corruption {
+        /// the position of the damaged data in the file
+        offset: u64,
+        /// the number of bytes that had to be skipped
+        length: u64,
+        /// what is wrong with the data
+        reason: String,
+    },
+}
+
+impl FormatError {
+    pub(crate) fn corruption(offset: u64, length: u64, reason: &str) -> FormatError {
+        FormatError::Corruption {
+            offset,
+            length,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FormatError::Io(ref e) => write!(f, "{}", e),
+            FormatError::Corruption {
+                offset,
+                length,
+                ref reason,
+            } => write!(
+                f,
+                "corruption at offset {} ({} bytes): {}",
+                offset, length, reason
+            ),
+        }
+    }
+}
+
+impl error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            FormatError::Io(ref e) => Some(e),
+            FormatError::Corruption { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for FormatError {
+    fn from(e: io::Error) -> FormatError {
+        FormatError::Io(e)
+    }
+}
+
+/// The kind of an entry, as stored with every key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValueType {
+    /// the key was deleted
+    Deletion = 0,
+    /// the key was set to a value
+    Value = 1,
+}
+
+impl ValueType {
+    pub(crate) fn from_byte(byte: u8) -> Option<ValueType> {
+        match byte {
+            0 => Some(ValueType::Deletion),
+            1 => Some(ValueType::Value),
+            _ => None,
+        }
+    }
+}
+
+/// A key as stored in tables: the user's key, the sequence number of the
+/// write and whether it was a put or delete.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InternalKey {
+    /// the key as written by the user
+    pub user_key: Vec<u8>,
+    /// the sequence number of the write
+    pub sequence: u64,
+    /// whether the key was set or deleted
+    pub value_type: ValueType,
+}
+
+impl InternalKey {
+    /// the internal key for `user_key` written at `sequence`.
+    pub fn new(user_key: &[u8], sequence: u64, value_type: ValueType) -> InternalKey {
+        InternalKey {
+            user_key: user_key.to_vec(),
+            sequence,
+            value_type,
+        }
+    }
+
+    /// decode an encoded internal key, `None` if it is malformed.
+    pub fn decode(encoded: &[u8]) -> Option<InternalKey> {
+        let (user_key, tag) = split_internal_key(encoded)?;
+        Some(InternalKey {
+            user_key: user_key.to_vec(),
+            sequence: tag >> 8,
+            value_type: ValueType::from_byte(tag as u8)?,
+        })
+    }
+
+    /// encode the key: the user key followed by the sequence number and
+    /// type, packed into a little endian `u64`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.user_key.len() + 8);
+        encoded.extend_from_slice(&self.user_key);
+        let tag = (self.sequence << 8) | self.value_type as u64;
+        encoded.extend_from_slice(&tag.to_le_bytes());
+        encoded
+    }
+
+    /// compare keys the way `compare_internal_keys` compares their
+    /// encodings.
+    pub(crate) fn compare(&self, other: &InternalKey) -> Ordering {
+        self.user_key
+            .cmp(&other.user_key)
+            .then_with(|| (other.sequence, other.value_type).cmp(&(self.sequence, self.value_type)))
+    }
+}
+
+/// split an encoded internal key into the user key and the packed
+/// sequence number and type.
+fn split_internal_key(encoded: &[u8]) -> Option<(&[u8], u64)> {
+    if encoded.len() < 8 {
+        return None;
+    }
+    let (user_key, tag) = encoded.split_at(encoded.len() - 8);
+    Some((user_key, coding::decode_fixed64(tag)))
+}
+
+/// compare encoded internal keys: ascending by user key, then descending
+/// by sequence number, so the newest version of a key comes first.
+pub(crate) fn compare_internal_keys(a: &[u8], b: &[u8]) -> Ordering {
+    match (split_internal_key(a), split_internal_key(b)) {
+        (Some((a_key, a_tag)), Some((b_key, b_tag))) => {
+            a_key.cmp(b_key).then_with(|| b_tag.cmp(&a_tag))
+        }
+        _ => a.cmp(b),
+    }
+}
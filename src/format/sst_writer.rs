@@ -0,0 +1,313 @@
+//! Writer for leveldb's table files
+//!
+//! `SstWriter` writes a table (see the `table` module) from entries added
+//! in key order, the way leveldb writes the output of a compaction. All
+//! entries of a table get the same sequence number, which has to be above
+//! the sequence numbers in the database the table is added to, so that
+//! its entries replace older versions of their keys.
+//!
+//! Tables are meant for `management::ingest`, which adds them to a closed
+//! database without going through the write path:
+//!
+//! ```rust
+//! # #[cfg(feature = "native")]
+//! # fn main() {
+//! use leveldb::database::Database;
+//! use leveldb::format::sst_writer::{SstOptions, SstWriter};
+//! use leveldb::management;
+//! use leveldb::options::{Options, ReadOptions};
+//! use tempdir::TempDir;
+//!
+//! let tempdir = TempDir::new("demo").unwrap();
+//! let path = tempdir.path().join("database");
+//! let mut options = Options::default();
+//! options.create_if_missing = true;
+//! drop(Database::open(&path, options).unwrap());
+//!
+//! let table = tempdir.path().join("bulk.ldb");
+//! let sequence = management::last_sequence(&path).unwrap() + 1;
+//! let mut writer = SstWriter::create(&table, sequence, SstOptions::default()).unwrap();
+//! for i in 0..1000u32 {
+//!     writer.put(&i.to_be_bytes(), b"value").unwrap();
+//! }
+//! writer.finish().unwrap();
+//! management::ingest(&path, &[table.as_path()]).unwrap();
+//!
+//! let database = Database::open(&path, Options::default()).unwrap();
+//! let value = database.get(&ReadOptions::default(), &7u32.to_be_bytes()).unwrap();
+//! assert_eq!(value, Some(b"value".to_vec()));
+//! # }
+//! # #[cfg(not(feature = "native"))]
+//! # fn main() {}
+//! ```
+use super::coding::put_varint32;
+use super::crc32c;
+use super::filter::{FilterBlockBuilder, BLOOM_FILTER_NAME};
+use super::table::{
+    BlockHandle, BLOCK_TRAILER_SIZE, NO_COMPRESSION, SNAPPY_COMPRESSION, TABLE_MAGIC,
+};
+use super::{FormatError, InternalKey, ValueType, MAX_SEQUENCE};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Options for writing tables.
+pub struct SstOptions {
+    /// the size of the data blocks, before compression
+    ///
+    /// default: 4096
+    pub block_size: usize,
+    /// the number of keys between restart points
+    ///
+    /// default: 16
+    pub block_restart_interval: usize,
+    /// compress blocks with snappy, where that saves at least an eighth.
+    /// leveldb can only read these tables if it was built with snappy.
+    ///
+    /// default: false
+    pub compression: bool,
+    /// write a bloom filter with this many bits per key, which leveldb
+    /// uses if the database is opened with a bloom filter policy
+    ///
+    /// default: None
+    pub bloom_filter_bits_per_key: Option<usize>,
+}
+
+impl Default for SstOptions {
+    fn default() -> SstOptions {
+        SstOptions {
+            block_size: 4096,
+            block_restart_interval: 16,
+            compression: false,
+            bloom_filter_bits_per_key: None,
+        }
+    }
+}
+
+/// Builds a block with prefix-compressed keys and restart points.
+struct BlockBuilder {
+    restart_interval: usize,
+    buffer: Vec<u8>,
+    restarts: Vec<u32>,
+    // keys since the last restart point
+    counter: usize,
+    last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+    fn new(restart_interval: usize) -> BlockBuilder {
+        BlockBuilder {
+            restart_interval: restart_interval.max(1),
+            buffer: Vec::new(),
+            restarts: vec![0],
+            counter: 0,
+            last_key: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// the size of the block if it was finished now.
+    fn estimated_size(&self) -> usize {
+        self.buffer.len() + self.restarts.len() * 4 + 4
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8]) {
+        let mut shared = 0;
+        if self.counter < self.restart_interval {
+            shared = key
+                .iter()
+                .zip(&self.last_key)
+                .take_while(|(a, b)| a == b)
+                .count();
+        } else {
+            self.restarts.push(self.buffer.len() as u32);
+            self.counter = 0;
+        }
+        put_varint32(&mut self.buffer, shared as u32);
+        put_varint32(&mut self.buffer, (key.len() - shared) as u32);
+        put_varint32(&mut self.buffer, value.len() as u32);
+        self.buffer.extend_from_slice(&key[shared..]);
+        self.buffer.extend_from_slice(value);
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.counter += 1;
+    }
+
+    /// the finished block; the builder is empty again afterwards.
+    fn finish(&mut self) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.buffer);
+        for restart in &self.restarts {
+            block.extend_from_slice(&restart.to_le_bytes());
+        }
+        block.extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+        self.restarts = vec![0];
+        self.counter = 0;
+        self.last_key.clear();
+        block
+    }
+}
+
+/// Writes a table file from entries in key order.
+pub struct SstWriter {
+    file: BufWriter<File>,
+    options: SstOptions,
+    sequence: u64,
+    // the size of the file so far
+    offset: u64,
+    data_block: BlockBuilder,
+    index_block: BlockBuilder,
+    filter: Option<FilterBlockBuilder>,
+    // the last key added, and as internal key
+    last_key: Option<(Vec<u8>, Vec<u8>)>,
+    entries: u64,
+}
+
+impl SstWriter {
+    /// create the table at `path`, whose entries all get `sequence` as
+    /// their sequence number.
+    pub fn create(
+        path: &Path,
+        sequence: u64,
+        options: SstOptions,
+    ) -> Result<SstWriter, FormatError> {
+        if sequence > MAX_SEQUENCE {
+            return Err(invalid_input("sequence number too large"));
+        }
+        let file = File::create(path)?;
+        let mut filter = options
+            .bloom_filter_bits_per_key
+            .map(FilterBlockBuilder::new);
+        if let Some(ref mut filter) = filter {
+            filter.start_block(0);
+        }
+        Ok(SstWriter {
+            file: BufWriter::new(file),
+            data_block: BlockBuilder::new(options.block_restart_interval),
+            index_block: BlockBuilder::new(1),
+            options,
+            sequence,
+            offset: 0,
+            filter,
+            last_key: None,
+            entries: 0,
+        })
+    }
+
+    /// the number of entries added so far.
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// add `key` with `value`. Keys have to be added in strictly
+    /// increasing bytewise order.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), FormatError> {
+        self.add(key, ValueType::Value, value)
+    }
+
+    /// add a deletion of `key`, which hides older versions of the key in
+    /// the database the table is added to.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), FormatError> {
+        self.add(key, ValueType::Deletion, &[])
+    }
+
+    fn add(&mut self, key: &[u8], value_type: ValueType, value: &[u8]) -> Result<(), FormatError> {
+        if let Some((ref last, _)) = self.last_key {
+            if key <= last.as_slice() {
+                return Err(invalid_input("keys must be added in increasing order"));
+            }
+        }
+        let internal_key = InternalKey::new(key, self.sequence, value_type).encode();
+        if let Some(ref mut filter) = self.filter {
+            filter.add_key(key);
+        }
+        self.data_block.add(&internal_key, value);
+        self.last_key = Some((key.to_vec(), internal_key));
+        self.entries += 1;
+        if self.data_block.estimated_size() >= self.options.block_size {
+            self.flush_data_block()?;
+        }
+        Ok(())
+    }
+
+    /// write the current data block and add it to the index.
+    fn flush_data_block(&mut self) -> Result<(), FormatError> {
+        if self.data_block.is_empty() {
+            return Ok(());
+        }
+        let contents = self.data_block.finish();
+        let handle = self.write_block(&contents, self.options.compression)?;
+        // the last key of a block separates it from the next one
+        let mut encoded = Vec::new();
+        handle.encode_to(&mut encoded);
+        let (_, ref last) = self.last_key.as_ref().unwrap();
+        self.index_block.add(last, &encoded);
+        if let Some(ref mut filter) = self.filter {
+            filter.start_block(self.offset);
+        }
+        Ok(())
+    }
+
+    /// write a block with its trailer, compressed if that is worth it.
+    fn write_block(&mut self, contents: &[u8], compress: bool) -> Result<BlockHandle, FormatError> {
+        let compressed = if compress {
+            snap::raw::Encoder::new()
+                .compress_vec(contents)
+                .ok()
+                .filter(|compressed| compressed.len() < contents.len() - contents.len() / 8)
+        } else {
+            None
+        };
+        let (data, compression) = match compressed {
+            Some(ref compressed) => (compressed.as_slice(), SNAPPY_COMPRESSION),
+            None => (contents, NO_COMPRESSION),
+        };
+        let crc = crc32c::mask(crc32c::extend(crc32c::value(data), &[compression]));
+        self.file.write_all(data)?;
+        self.file.write_all(&[compression])?;
+        self.file.write_all(&crc.to_le_bytes())?;
+        let handle = BlockHandle {
+            offset: self.offset,
+            size: data.len() as u64,
+        };
+        self.offset += (data.len() + BLOCK_TRAILER_SIZE) as u64;
+        Ok(handle)
+    }
+
+    /// write the filter, index and footer and sync the file. Returns the
+    /// size of the table.
+    pub fn finish(mut self) -> Result<u64, FormatError> {
+        self.flush_data_block()?;
+
+        let mut metaindex = BlockBuilder::new(1);
+        if let Some(filter) = self.filter.take() {
+            let handle = self.write_block(&filter.finish(), false)?;
+            let mut encoded = Vec::new();
+            handle.encode_to(&mut encoded);
+            let name = format!("filter.{}", BLOOM_FILTER_NAME);
+            metaindex.add(name.as_bytes(), &encoded);
+        }
+        let metaindex_handle = self.write_block(&metaindex.finish(), self.options.compression)?;
+        let index = self.index_block.finish();
+        let index_handle = self.write_block(&index, self.options.compression)?;
+
+        let mut footer = Vec::new();
+        metaindex_handle.encode_to(&mut footer);
+        index_handle.encode_to(&mut footer);
+        footer.resize(40, 0);
+        footer.extend_from_slice(&TABLE_MAGIC.to_le_bytes());
+        self.file.write_all(&footer)?;
+        self.offset += footer.len() as u64;
+
+        let file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(self.offset)
+    }
+}
+
+fn invalid_input(reason: &str) -> FormatError {
+    io::Error::new(io::ErrorKind::InvalidInput, reason).into()
+}
@@ -0,0 +1,488 @@
+//! Reader for leveldb's table files
+//!
+//! A table (`*.ldb`, or `*.sst` in older databases) stores sorted
+//! entries in data blocks of about 4KB. After the data blocks come the
+//! filter block, the metaindex block, which names the filter block, and
+//! the index block, which holds a key and the location of every data
+//! block. A fixed-size footer at the end of the file locates the
+//! metaindex and index blocks.
+//!
+//! Every block is followed by a compression type (none or snappy) and the
+//! masked CRC32C of the stored block and type. Within a block, keys share
+//! prefixes with the key before them; every 16th key is stored in full at
+//! a restart point, listed at the end of the block, so lookups can binary
+//! search.
+//!
+//! The keys of data blocks are internal keys: the user's key, the
+//! sequence number of the write and whether it set or deleted the key.
+use super::coding::{decode_fixed32, decode_fixed64, get_varint32, get_varint64, put_varint64};
+use super::crc32c;
+use super::filter::{FilterBlock, BLOOM_FILTER_NAME};
+use super::{compare_internal_keys, FormatError, InternalKey, ValueType, MAX_SEQUENCE};
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The size of the footer at the end of every table.
+pub const FOOTER_SIZE: usize = 48;
+/// The number identifying table files, stored at their very end.
+pub const TABLE_MAGIC: u64 = 0xdb47_7524_8b80_fb57;
+
+pub(crate) const BLOCK_TRAILER_SIZE: usize = 5;
+pub(crate) const NO_COMPRESSION: u8 = 0;
+pub(crate) const SNAPPY_COMPRESSION: u8 = 1;
+
+/// The location of a block in a table file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHandle {
+    /// the offset of the block in the file
+    pub offset: u64,
+    /// the size of the block, without its trailer
+    pub size: u64,
+}
+
+impl BlockHandle {
+    /// decode a handle from the start of `input`, advancing it.
+    fn decode_from(input: &mut &[u8]) -> Option<BlockHandle> {
+        let offset = get_varint64(input)?;
+        let size = get_varint64(input)?;
+        Some(BlockHandle { offset, size })
+    }
+
+    fn decode(mut input: &[u8]) -> Option<BlockHandle> {
+        BlockHandle::decode_from(&mut input)
+    }
+
+    pub(crate) fn encode_to(&self, out: &mut Vec<u8>) {
+        put_varint64(out, self.offset);
+        put_varint64(out, self.size);
+    }
+
+    /// the number of bytes the block and its trailer take up. Saturates,
+    /// so that damaged handles can still be reported.
+    fn stored_size(&self) -> u64 {
+        self.size.saturating_add(BLOCK_TRAILER_SIZE as u64)
+    }
+
+    /// the offset just past the block's trailer, unless that overflows.
+    fn end(&self) -> Option<u64> {
+        self.size
+            .checked_add(BLOCK_TRAILER_SIZE as u64)?
+            .checked_add(self.offset)
+    }
+}
+
+/// A block, without its trailer.
+pub(crate) struct Block {
+    data: Vec<u8>,
+    // start of the restart array in `data`
+    restarts: usize,
+    num_restarts: usize,
+}
+
+impl Block {
+    pub(crate) fn new(data: Vec<u8>) -> Option<Block> {
+        if data.len() < 4 {
+            return None;
+        }
+        let num_restarts = decode_fixed32(&data[data.len() - 4..]) as usize;
+        let max_restarts = (data.len() - 4) / 4;
+        if num_restarts > max_restarts {
+            return None;
+        }
+        Some(Block {
+            restarts: data.len() - 4 - num_restarts * 4,
+            num_restarts,
+            data,
+        })
+    }
+
+    fn restart_point(&self, index: usize) -> usize {
+        decode_fixed32(&self.data[self.restarts + index * 4..]) as usize
+    }
+}
+
+/// Iterates the entries of a block.
+pub(crate) struct BlockIter {
+    block: Arc<Block>,
+    // offset of the next entry
+    next: usize,
+    key: Vec<u8>,
+    value: (usize, usize),
+}
+
+impl BlockIter {
+    pub(crate) fn new(block: Arc<Block>) -> BlockIter {
+        BlockIter {
+            block,
+            next: 0,
+            key: Vec::new(),
+            value: (0, 0),
+        }
+    }
+
+    pub(crate) fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub(crate) fn value(&self) -> &[u8] {
+        &self.block.data[self.value.0..self.value.1]
+    }
+
+    /// move to the next entry, returning false after the last one.
+    pub(crate) fn advance(&mut self) -> Result<bool, &'static str> {
+        if self.next >= self.block.restarts {
+            return Ok(false);
+        }
+        let mut input = &self.block.data[self.next..self.block.restarts];
+        let available = input.len();
+        let header = (|| {
+            let shared = get_varint32(&mut input)? as usize;
+            let non_shared = get_varint32(&mut input)? as usize;
+            let value_len = get_varint32(&mut input)? as usize;
+            Some((shared, non_shared, value_len))
+        })();
+        let (shared, non_shared, value_len) = match header {
+            Some(header) => header,
+            None => return Err("bad block entry header"),
+        };
+        if shared > self.key.len() || non_shared + value_len > input.len() {
+            return Err("bad block entry");
+        }
+        let start = self.next + available - input.len();
+        self.key.truncate(shared);
+        self.key
+            .extend_from_slice(&self.block.data[start..start + non_shared]);
+        self.value = (start + non_shared, start + non_shared + value_len);
+        self.next = self.value.1;
+        Ok(true)
+    }
+
+    /// position at the first entry at or after `target`, returning false
+    /// if there is none.
+    pub(crate) fn seek(
+        &mut self,
+        target: &[u8],
+        compare: fn(&[u8], &[u8]) -> Ordering,
+    ) -> Result<bool, &'static str> {
+        // find the last restart point with a key before the target
+        let (mut left, mut right) = (0, self.block.num_restarts.saturating_sub(1));
+        while left < right {
+            let mid = (left + right).div_ceil(2);
+            self.next = self.block.restart_point(mid);
+            self.key.clear();
+            if !self.advance()? {
+                return Err("bad restart point");
+            }
+            if compare(&self.key, target) == Ordering::Less {
+                left = mid;
+            } else {
+                right = mid - 1;
+            }
+        }
+        self.next = if self.block.num_restarts == 0 {
+            self.block.restarts
+        } else {
+            self.block.restart_point(left)
+        };
+        self.key.clear();
+        while self.advance()? {
+            if compare(&self.key, target) != Ordering::Less {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// read a block, verifying its checksum and decompressing it.
+fn read_block_contents(
+    file: &Mutex<File>,
+    file_size: u64,
+    handle: BlockHandle,
+) -> Result<Vec<u8>, FormatError> {
+    let corrupt = |reason| FormatError::corruption(handle.offset, handle.stored_size(), reason);
+    match handle.end() {
+        Some(end) if end <= file_size => {}
+        _ => return Err(corrupt("block extends past the end of the file")),
+    }
+    let mut data = vec![0; handle.stored_size() as usize];
+    {
+        let mut file = file.lock().unwrap();
+        file.seek(SeekFrom::Start(handle.offset))?;
+        file.read_exact(&mut data)?;
+    }
+
+    let size = handle.size as usize;
+    let expected = crc32c::unmask(decode_fixed32(&data[size + 1..]));
+    if crc32c::value(&data[..size + 1]) != expected {
+        return Err(corrupt("block checksum mismatch"));
+    }
+    match data[size] {
+        NO_COMPRESSION => {
+            data.truncate(size);
+            Ok(data)
+        }
+        SNAPPY_COMPRESSION => snap::raw::Decoder::new()
+            .decompress_vec(&data[..size])
+            .map_err(|_| corrupt("corrupted snappy compressed block")),
+        _ => Err(corrupt("unknown block compression type")),
+    }
+}
+
+fn read_block(
+    file: &Mutex<File>,
+    file_size: u64,
+    handle: BlockHandle,
+) -> Result<Block, FormatError> {
+    let contents = read_block_contents(file, file_size, handle)?;
+    Block::new(contents).ok_or_else(|| {
+        FormatError::corruption(handle.offset, handle.stored_size(), "bad block contents")
+    })
+}
+
+/// An open table file.
+pub struct Table {
+    file: Mutex<File>,
+    size: u64,
+    index: Arc<Block>,
+    index_handle: BlockHandle,
+    filter: Option<FilterBlock>,
+}
+
+impl Table {
+    /// open the table at `path`, reading its footer, index and filter.
+    pub fn open(path: &Path) -> Result<Table, FormatError> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Table::from_file(file, size)
+    }
+
+    /// open a table from a file of `size` bytes.
+    pub fn from_file(mut file: File, size: u64) -> Result<Table, FormatError> {
+        if size < FOOTER_SIZE as u64 {
+            return Err(FormatError::corruption(
+                0,
+                size,
+                "file too short to be a table",
+            ));
+        }
+        let footer_offset = size - FOOTER_SIZE as u64;
+        let mut footer = [0; FOOTER_SIZE];
+        file.seek(SeekFrom::Start(footer_offset))?;
+        file.read_exact(&mut footer)?;
+        let bad_footer =
+            |reason| FormatError::corruption(footer_offset, FOOTER_SIZE as u64, reason);
+        if decode_fixed64(&footer[FOOTER_SIZE - 8..]) != TABLE_MAGIC {
+            return Err(bad_footer("not a table: bad magic number"));
+        }
+        let mut input = &footer[..];
+        let metaindex = BlockHandle::decode_from(&mut input);
+        let index_handle = BlockHandle::decode_from(&mut input)
+            .ok_or_else(|| bad_footer("bad block handle in footer"))?;
+        let file = Mutex::new(file);
+        let index = read_block(&file, size, index_handle)?;
+
+        // a damaged filter only makes lookups slower, so like leveldb, the
+        // table is read without it
+        let filter = metaindex
+            .and_then(|handle| read_block(&file, size, handle).ok())
+            .and_then(|metaindex| read_filter(&file, size, metaindex));
+        Ok(Table {
+            file,
+            size,
+            index: Arc::new(index),
+            index_handle,
+            filter,
+        })
+    }
+
+    /// the size of the table file.
+    pub fn file_size(&self) -> u64 {
+        self.size
+    }
+
+    /// whether the table has a bloom filter.
+    pub fn has_filter(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// whether the table may contain an entry for `user_key`, according
+    /// to its bloom filter. Always true for tables without a filter.
+    pub fn may_contain(&self, user_key: &[u8]) -> Result<bool, FormatError> {
+        let filter = match self.filter {
+            Some(ref filter) => filter,
+            None => return Ok(true),
+        };
+        let target = InternalKey::new(user_key, MAX_SEQUENCE, ValueType::Value);
+        let mut index = BlockIter::new(self.index.clone());
+        let found = index
+            .seek(&target.encode(), compare_internal_keys)
+            .map_err(|reason| self.index_corruption(reason))?;
+        if !found {
+            return Ok(false);
+        }
+        match BlockHandle::decode(index.value()) {
+            Some(handle) => Ok(filter.may_contain(handle.offset, user_key)),
+            None => Err(self.index_corruption("bad block handle in index")),
+        }
+    }
+
+    fn index_corruption(&self, reason: &str) -> FormatError {
+        FormatError::corruption(
+            self.index_handle.offset,
+            self.index_handle.stored_size(),
+            reason,
+        )
+    }
+
+    /// iterate all entries of the table, in order.
+    pub fn iter(&self) -> TableIter<'_> {
+        TableIter {
+            table: self,
+            index: BlockIter::new(self.index.clone()),
+            data: None,
+            positioned: false,
+            seek: None,
+            done: false,
+        }
+    }
+}
+
+/// look up the filter block in the metaindex block and read it.
+fn read_filter(file: &Mutex<File>, file_size: u64, metaindex: Block) -> Option<FilterBlock> {
+    let name = format!("filter.{}", BLOOM_FILTER_NAME);
+    let mut iter = BlockIter::new(Arc::new(metaindex));
+    if !iter.seek(name.as_bytes(), <[u8]>::cmp).ok()? || iter.key() != name.as_bytes() {
+        return None;
+    }
+    let handle = BlockHandle::decode(iter.value())?;
+    FilterBlock::new(read_block_contents(file, file_size, handle).ok()?)
+}
+
+/// Iterates the entries of a table, as internal keys and values.
+///
+/// A damaged data block is reported as one error item, after which
+/// iteration continues with the next block.
+pub struct TableIter<'a> {
+    table: &'a Table,
+    index: BlockIter,
+    // the current data block, and where it is stored
+    data: Option<(BlockIter, BlockHandle)>,
+    // whether `data` is on an entry that wasn't returned yet
+    positioned: bool,
+    // a key to skip to in the next data block
+    seek: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a> TableIter<'a> {
+    /// position the iterator, so it continues with the first entry at or
+    /// after `target`.
+    ///
+    /// As the newest version of a key sorts first, seeking to a key with
+    /// `MAX_SEQUENCE` finds all its versions.
+    pub fn seek(&mut self, target: &InternalKey) -> Result<(), FormatError> {
+        let target = target.encode();
+        self.data = None;
+        self.positioned = false;
+        let found = self.index.seek(&target, compare_internal_keys);
+        self.done = !found.map_err(|reason| self.table.index_corruption(reason))?;
+        self.seek = Some(target);
+        Ok(())
+    }
+
+    /// the location of the data block the iterator is reading.
+    pub fn block(&self) -> Option<BlockHandle> {
+        self.data.as_ref().map(|&(_, handle)| handle)
+    }
+
+    /// move to the next data block, `None` after the last one.
+    fn next_block(&mut self) -> Option<Result<(), FormatError>> {
+        self.data = None;
+        if self.done {
+            return None;
+        }
+        // after a seek, the index is already on the block to read
+        let seek = self.seek.take();
+        if seek.is_none() {
+            match self.index.advance() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(reason) => {
+                    self.done = true;
+                    return Some(Err(self.table.index_corruption(reason)));
+                }
+            }
+        }
+        let handle = match BlockHandle::decode(self.index.value()) {
+            Some(handle) => handle,
+            None => {
+                return Some(Err(self
+                    .table
+                    .index_corruption("bad block handle in index")))
+            }
+        };
+        let block = match read_block(&self.table.file, self.table.size, handle) {
+            Ok(block) => block,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut iter = BlockIter::new(Arc::new(block));
+        if let Some(target) = seek {
+            match iter.seek(&target, compare_internal_keys) {
+                Ok(found) => self.positioned = found,
+                Err(reason) => {
+                    return Some(Err(FormatError::corruption(
+                        handle.offset,
+                        handle.stored_size(),
+                        reason,
+                    )))
+                }
+            }
+        }
+        self.data = Some((iter, handle));
+        Some(Ok(()))
+    }
+}
+
+impl<'a> Iterator for TableIter<'a> {
+    type Item = Result<(InternalKey, Vec<u8>), FormatError>;
+
+    fn next(&mut self) -> Option<Result<(InternalKey, Vec<u8>), FormatError>> {
+        loop {
+            if let Some((ref mut iter, handle)) = self.data {
+                let corrupt =
+                    |reason| FormatError::corruption(handle.offset, handle.stored_size(), reason);
+                let found = if self.positioned {
+                    self.positioned = false;
+                    Ok(true)
+                } else {
+                    iter.advance()
+                };
+                match found {
+                    Ok(true) => {
+                        return Some(match InternalKey::decode(iter.key()) {
+                            Some(key) => Ok((key, iter.value().to_vec())),
+                            None => Err(corrupt("bad internal key")),
+                        })
+                    }
+                    Ok(false) => {}
+                    Err(reason) => {
+                        // the rest of the block can't be parsed
+                        self.data = None;
+                        return Some(Err(corrupt(reason)));
+                    }
+                }
+            }
+            if let Err(e) = self.next_block()? {
+                return Some(Err(e));
+            }
+        }
+    }
+}
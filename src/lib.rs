@@ -2,33 +2,31 @@
 //!
 //! Usage:
 //!
-//! ```rust,ignore
-//! extern crate tempdir;
-//! extern crate leveldb;
-//!
+//! ```rust
+//! # #[cfg(feature = "native")]
+//! # fn main() {
 //! use tempdir::TempDir;
 //! use leveldb::database::Database;
-//! use leveldb::kv::KV;
-//! use leveldb::options::{Options,WriteOptions,ReadOptions};
+//! use leveldb::options::{Options, WriteOptions, ReadOptions};
 //!
 //! let tempdir = TempDir::new("demo").unwrap();
 //! let path = tempdir.path();
 //!
-//! let mut options = Options::new();
+//! let mut options = Options::default();
 //! options.create_if_missing = true;
-//! let mut database = match Database::open(path, options) {
+//! let database = match Database::open(path, options) {
 //!     Ok(db) => { db },
 //!     Err(e) => { panic!("failed to open database: {:?}", e) }
 //! };
 //!
-//! let write_opts = WriteOptions::new();
-//! match database.put(write_opts, 1, &[1]) {
+//! let write_opts = WriteOptions::default();
+//! match database.put(&write_opts, &[1], &[1]) {
 //!     Ok(_) => { () },
 //!     Err(e) => { panic!("failed to write to database: {:?}", e) }
 //! };
 //!
-//! let read_opts = ReadOptions::new();
-//! let res = database.get(read_opts, 1);
+//! let read_opts = ReadOptions::default();
+//! let res = database.get(&read_opts, &[1]);
 //!
 //! match res {
 //!   Ok(data) => {
@@ -37,6 +35,9 @@
 //!   }
 //!   Err(e) => { panic!("failed reading data: {:?}", e) }
 //! }
+//! # }
+//! # #[cfg(not(feature = "native"))]
+//! # fn main() {}
 //! ```
 
 #![crate_type = "lib"]
@@ -44,16 +45,29 @@
 #![deny(missing_docs)]
 #![warn(clippy::all)]
 
-pub use crate::database::error;
-pub use crate::database::iterator;
-pub use crate::database::management;
-pub use crate::database::options;
+#[cfg(feature = "async")]
+pub use crate::database::async_database;
+#[cfg(feature = "native")]
+pub use crate::database::{
+    backup, batch, change_log, error, export, group_commit, index, iterator, locking, management,
+    merge, options, read_only, replication, snapshots, transaction, ttl, watch,
+};
+#[cfg(feature = "native")]
 use leveldb_sys::{leveldb_major_version, leveldb_minor_version};
 
+#[cfg(feature = "native")]
 #[allow(missing_docs)]
 pub mod database;
+pub mod format;
+#[cfg(feature = "pure")]
+pub mod pure;
+
+#[cfg(all(doctest, feature = "native"))]
+#[doc = include_str!("../README.md")]
+pub struct ReadmeDoctests;
 
 /// Struct containing version information of LevelDB
+#[cfg(feature = "native")]
 #[derive(Debug, Copy, Clone)]
 pub struct Version {
     /// Major version
@@ -65,6 +79,7 @@ pub struct Version {
 /// Library version information
 ///
 /// Need a recent version of leveldb to be used.
+#[cfg(feature = "native")]
 pub fn version() -> Version {
     unsafe {
         let major = leveldb_major_version() as isize;
@@ -0,0 +1,377 @@
+//! A read-only database implemented in Rust
+//!
+//! With the `pure` feature, databases can be read without the leveldb C
+//! library; build with `default-features = false` to not link it at all.
+//! `PureDatabase` is built on the readers in `format`:
+//!
+//! * the MANIFEST named by CURRENT gives the tables of every level,
+//! * the logs not yet compacted into tables are replayed into an in-memory
+//!   table,
+//! * lookups and iteration merge the in-memory table and all levels,
+//!   where the version of a key with the highest sequence number wins and
+//!   deleted keys are left out.
+//!
+//! The database directory is never modified. Table checksums are always
+//! verified; damaged log records are skipped, like leveldb does by
+//! default.
+//!
+//! Values are returned as stored. Databases written with TTLs or merge
+//! operators (see the `ttl` and `merge` modules) store bookkeeping
+//! records and value headers that this reader doesn't interpret.
+use crate::format::log::{LogReader, WriteBatch};
+use crate::format::manifest::{current_manifest, FileMetaData, Manifest, Version};
+use crate::format::table::{Table, TableIter};
+use crate::format::{FormatError, InternalKey, ValueType, MAX_SEQUENCE};
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// The name of the only comparator this reader supports.
+pub use crate::format::BYTEWISE_COMPARATOR;
+
+type Entry = Result<(InternalKey, Vec<u8>), FormatError>;
+type Source<'a> = Box<dyn Iterator<Item = Entry> + 'a>;
+// writes by user key, newest first
+type MemTable = BTreeMap<(Vec<u8>, Reverse<u64>), (ValueType, Vec<u8>)>;
+
+/// the path of the table with the given number, `.ldb` or `.sst`.
+fn table_path(dir: &Path, number: u64) -> PathBuf {
+    let path = dir.join(format!("{:06}.ldb", number));
+    if path.exists() {
+        path
+    } else {
+        dir.join(format!("{:06}.sst", number))
+    }
+}
+
+/// A table of a level, with its metadata.
+struct LevelFile {
+    meta: FileMetaData,
+    table: Table,
+}
+
+/// A database opened for reading, without the C library.
+pub struct PureDatabase {
+    path: PathBuf,
+    version: Version,
+    // the files of every level, sorted by their smallest key
+    levels: Vec<Vec<LevelFile>>,
+    // the writes in the logs
+    memtable: MemTable,
+    last_sequence: u64,
+}
+
+impl PureDatabase {
+    /// open the database at `path`.
+    ///
+    /// All tables of the database are opened, so this takes a file handle
+    /// per table.
+    pub fn open(path: &Path) -> Result<PureDatabase, FormatError> {
+        let manifest = Manifest::open(&current_manifest(path)?)?;
+        if let Some(e) = manifest.corruptions.into_iter().next() {
+            return Err(e);
+        }
+        let version = manifest.version;
+        match version.comparator {
+            Some(ref comparator) if comparator != BYTEWISE_COMPARATOR => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported comparator {}", comparator),
+                )
+                .into())
+            }
+            _ => {}
+        }
+
+        let mut levels = Vec::new();
+        for files in &version.levels {
+            let mut opened = Vec::new();
+            for meta in files {
+                opened.push(LevelFile {
+                    table: Table::open(&table_path(path, meta.number))?,
+                    meta: meta.clone(),
+                });
+            }
+            levels.push(opened);
+        }
+
+        let mut database = PureDatabase {
+            path: path.to_path_buf(),
+            last_sequence: version.last_sequence,
+            version,
+            levels,
+            memtable: BTreeMap::new(),
+        };
+        database.replay_logs()?;
+        Ok(database)
+    }
+
+    /// read the writes in the logs that weren't compacted yet.
+    fn replay_logs(&mut self) -> Result<(), FormatError> {
+        let mut logs = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|number| number.parse::<u64>().ok());
+            match number {
+                Some(number) if number >= self.version.min_log_number() => logs.push(number),
+                _ => {}
+            }
+        }
+        logs.sort_unstable();
+        for number in logs {
+            let log = File::open(self.path.join(format!("{:06}.log", number)))?;
+            for record in LogReader::new(BufReader::new(log)) {
+                let batch = match record.and_then(|record| WriteBatch::decode(&record)) {
+                    Ok(batch) => batch,
+                    Err(FormatError::Io(e)) => return Err(FormatError::Io(e)),
+                    // damaged records are dropped
+                    Err(_) => continue,
+                };
+                for (i, entry) in batch.entries.into_iter().enumerate() {
+                    let sequence = batch.sequence + i as u64;
+                    self.last_sequence = self.last_sequence.max(sequence);
+                    self.memtable.insert(
+                        (entry.key, Reverse(sequence)),
+                        (entry.value_type, entry.value),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory of the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The sequence number of the latest write.
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// The layout of the database, as recorded in its MANIFEST.
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// the newest version of `key` in a table, if any.
+    fn table_get(table: &Table, key: &[u8]) -> Result<Option<(InternalKey, Vec<u8>)>, FormatError> {
+        if !table.may_contain(key)? {
+            return Ok(None);
+        }
+        let mut iter = table.iter();
+        iter.seek(&InternalKey::new(key, MAX_SEQUENCE, ValueType::Value))?;
+        match iter.next() {
+            Some(Ok((found, value))) if found.user_key == key => Ok(Some((found, value))),
+            Some(Err(e)) => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    /// get a value from the database.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, FormatError> {
+        let start = (key.to_vec(), Reverse(MAX_SEQUENCE));
+        if let Some(((found, _), (value_type, value))) = self.memtable.range(start..).next() {
+            if found.as_slice() == key {
+                return Ok(visible(*value_type, value.clone()));
+            }
+        }
+        for (level, files) in self.levels.iter().enumerate() {
+            let candidates: &[LevelFile] = if level == 0 {
+                files
+            } else {
+                // the files of other levels don't overlap
+                let i = files.partition_point(|file| file.meta.largest.user_key.as_slice() < key);
+                &files[i..files.len().min(i + 1)]
+            };
+            // level 0 files overlap, so the version with the highest
+            // sequence number wins; deeper levels hold older versions
+            let mut newest: Option<(InternalKey, Vec<u8>)> = None;
+            for file in candidates {
+                if key < file.meta.smallest.user_key.as_slice()
+                    || key > file.meta.largest.user_key.as_slice()
+                {
+                    continue;
+                }
+                if let Some((found, value)) = Self::table_get(&file.table, key)? {
+                    match newest {
+                        Some((ref other, _)) if other.sequence > found.sequence => {}
+                        _ => newest = Some((found, value)),
+                    }
+                }
+            }
+            if let Some((found, value)) = newest {
+                return Ok(visible(found.value_type, value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// iterate the keys and values of the database, in order.
+    pub fn iter(&self) -> Iter<'_> {
+        self.iter_from(b"")
+    }
+
+    /// iterate the keys and values of the database, starting at the first
+    /// key at or after `start`.
+    pub fn iter_from(&self, start: &[u8]) -> Iter<'_> {
+        let target = InternalKey::new(start, MAX_SEQUENCE, ValueType::Value);
+        let memtable: Source<'_> = Box::new(
+            self.memtable
+                .range((start.to_vec(), Reverse(MAX_SEQUENCE))..)
+                .map(|((key, sequence), (value_type, value))| {
+                    Ok((
+                        InternalKey::new(key, sequence.0, *value_type),
+                        value.clone(),
+                    ))
+                }),
+        );
+        let mut sources = vec![memtable];
+        for (level, files) in self.levels.iter().enumerate() {
+            if level == 0 {
+                for file in files {
+                    sources.push(seek(file.table.iter(), &target));
+                }
+            } else {
+                // a level is one sorted run, so its tables are read in turn
+                let first =
+                    files.partition_point(|file| file.meta.largest.user_key.as_slice() < start);
+                let target = target.clone();
+                let run = files[first..]
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(i, file)| {
+                        if i == 0 {
+                            seek(file.table.iter(), &target)
+                        } else {
+                            Box::new(file.table.iter())
+                        }
+                    });
+                sources.push(Box::new(run));
+            }
+        }
+        Iter {
+            heads: sources.iter().map(|_| None).collect(),
+            sources,
+            last: None,
+        }
+    }
+
+    /// a property of the database, for the subset of leveldb's properties
+    /// that is available without the C library: `leveldb.sstables` and
+    /// `leveldb.num-files-at-level<N>`.
+    pub fn property(&self, name: &str) -> Option<String> {
+        if let Some(level) = name.strip_prefix("leveldb.num-files-at-level") {
+            let level: usize = level.parse().ok()?;
+            return self
+                .version
+                .levels
+                .get(level)
+                .map(|files| files.len().to_string());
+        }
+        if name == "leveldb.sstables" {
+            let mut out = String::new();
+            for (level, files) in self.version.levels.iter().enumerate() {
+                out.push_str(&format!("--- level {} ---\n", level));
+                for file in files {
+                    out.push_str(&format!(
+                        " {}:{}['{}' @ {} : {} .. '{}' @ {} : {}]\n",
+                        file.number,
+                        file.size,
+                        String::from_utf8_lossy(&file.smallest.user_key),
+                        file.smallest.sequence,
+                        file.smallest.value_type as u8,
+                        String::from_utf8_lossy(&file.largest.user_key),
+                        file.largest.sequence,
+                        file.largest.value_type as u8
+                    ));
+                }
+            }
+            return Some(out);
+        }
+        None
+    }
+}
+
+/// the value a lookup returns for the newest version of a key.
+fn visible(value_type: ValueType, value: Vec<u8>) -> Option<Vec<u8>> {
+    match value_type {
+        ValueType::Value => Some(value),
+        ValueType::Deletion => None,
+    }
+}
+
+/// a table iterator positioned at `target`.
+fn seek<'a>(mut iter: TableIter<'a>, target: &InternalKey) -> Source<'a> {
+    match iter.seek(target) {
+        Ok(()) => Box::new(iter),
+        Err(e) => Box::new(iter::once(Err(e))),
+    }
+}
+
+/// Iterates the keys and values of a `PureDatabase`.
+///
+/// Damaged table blocks are reported as errors, after which iteration
+/// continues.
+pub struct Iter<'a> {
+    sources: Vec<Source<'a>>,
+    // the next entry of every source
+    heads: Vec<Option<(InternalKey, Vec<u8>)>>,
+    // the user key returned last, to skip its older versions
+    last: Option<Vec<u8>>,
+}
+
+impl<'a> Iter<'a> {
+    /// the next entry of all sources, in internal key order.
+    fn next_entry(&mut self) -> Option<Entry> {
+        for (source, head) in self.sources.iter_mut().zip(&mut self.heads) {
+            if head.is_none() {
+                match source.next() {
+                    Some(Ok(entry)) => *head = Some(entry),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {}
+                }
+            }
+        }
+        let (best, _) = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|(key, _)| (i, key)))
+            .min_by(|(_, a), (_, b)| {
+                a.user_key
+                    .cmp(&b.user_key)
+                    .then(b.sequence.cmp(&a.sequence))
+            })?;
+        self.heads[best].take().map(Ok)
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>), FormatError>;
+
+    fn next(&mut self) -> Option<Result<(Vec<u8>, Vec<u8>), FormatError>> {
+        loop {
+            let (key, value) = match self.next_entry()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            if self.last.as_ref() == Some(&key.user_key) {
+                // an older version
+                continue;
+            }
+            self.last = Some(key.user_key.clone());
+            if key.value_type == ValueType::Value {
+                return Some(Ok((key.user_key, value)));
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::utils::{open_database, tmpdir};
+use futures_core::Stream;
+use leveldb::async_database::{AsyncDatabase, Scan};
+use leveldb::batch::Writebatch;
+use leveldb::options::{ReadOptions, WriteOptions};
+use std::future::{self, Future};
+use std::pin::Pin;
+use tokio::runtime::Builder;
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+async fn collect(mut scan: Scan) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = Vec::new();
+    while let Some(entry) = future::poll_fn(|cx| Pin::new(&mut scan).poll_next(cx)).await {
+        entries.push(entry);
+    }
+    entries
+}
+
+#[test]
+fn test_get_put_delete() {
+    let tmp = tmpdir("async_get_put");
+    let database = AsyncDatabase::new(open_database(tmp.path(), true));
+
+    block_on(async {
+        database
+            .put(WriteOptions::default(), b"key", b"value")
+            .await
+            .unwrap();
+        assert_eq!(
+            database.get(ReadOptions::default(), b"key").await.unwrap(),
+            Some(b"value".to_vec())
+        );
+        database
+            .delete(WriteOptions::default(), b"key")
+            .await
+            .unwrap();
+        assert_eq!(
+            database.get(ReadOptions::default(), b"key").await.unwrap(),
+            None
+        );
+    });
+}
+
+#[test]
+fn test_scan_range() {
+    let tmp = tmpdir("async_scan");
+    let database = AsyncDatabase::new(open_database(tmp.path(), true));
+
+    block_on(async {
+        let mut batch = Writebatch::new();
+        for i in 0..200u32 {
+            batch.put(&i.to_be_bytes(), b"value");
+        }
+        database
+            .write(WriteOptions::default(), batch)
+            .await
+            .unwrap();
+
+        let all = collect(database.scan(ReadOptions::default(), None, None)).await;
+        assert_eq!(all.len(), 200);
+        let range = collect(database.scan(
+            ReadOptions::default(),
+            Some(&10u32.to_be_bytes()),
+            Some(&20u32.to_be_bytes()),
+        ))
+        .await;
+        let keys: Vec<_> = range.into_iter().map(|(key, _)| key).collect();
+        let expected: Vec<_> = (10..20u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        assert_eq!(keys, expected);
+    });
+}
+
+#[test]
+fn test_dropped_scan_stops() {
+    let tmp = tmpdir("async_scan_drop");
+    let database = AsyncDatabase::new(open_database(tmp.path(), true));
+
+    block_on(async {
+        for i in 0..500u32 {
+            database
+                .put(WriteOptions::default(), &i.to_be_bytes(), b"value")
+                .await
+                .unwrap();
+        }
+        drop(database.scan(ReadOptions::default(), None, None));
+        // the database is still usable while the scan winds down
+        database
+            .put(WriteOptions::default(), b"after", b"value")
+            .await
+            .unwrap();
+    });
+}
@@ -0,0 +1,92 @@
+use crate::utils::{db_get, db_put_simple, open_database, tmpdir};
+use leveldb::options::WriteOptions;
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_put_if_absent() {
+    let tmp = tmpdir("put_if_absent");
+    let database = open_database(tmp.path(), true);
+    let write_opts = WriteOptions::default();
+
+    assert!(database
+        .put_if_absent(&write_opts, b"key", b"first")
+        .unwrap());
+    assert!(!database
+        .put_if_absent(&write_opts, b"key", b"second")
+        .unwrap());
+    assert_eq!(db_get(&database, b"key"), Some(b"first".to_vec()));
+}
+
+#[test]
+fn test_compare_and_swap() {
+    let tmp = tmpdir("compare_and_swap");
+    let database = open_database(tmp.path(), true);
+    let write_opts = WriteOptions::default();
+    db_put_simple(&database, b"key", b"old");
+
+    assert!(!database
+        .compare_and_swap(&write_opts, b"key", Some(b"other"), Some(b"new"))
+        .unwrap());
+    assert_eq!(db_get(&database, b"key"), Some(b"old".to_vec()));
+
+    assert!(database
+        .compare_and_swap(&write_opts, b"key", Some(b"old"), Some(b"new"))
+        .unwrap());
+    assert_eq!(db_get(&database, b"key"), Some(b"new".to_vec()));
+
+    assert!(database
+        .compare_and_swap(&write_opts, b"key", Some(b"new"), None)
+        .unwrap());
+    assert_eq!(db_get(&database, b"key"), None);
+}
+
+#[test]
+fn test_update() {
+    let tmp = tmpdir("update");
+    let database = open_database(tmp.path(), true);
+    let write_opts = WriteOptions::default();
+
+    let new = database
+        .update(&write_opts, b"key", |current| {
+            assert_eq!(current, None);
+            Some(b"value".to_vec())
+        })
+        .unwrap();
+    assert_eq!(new, Some(b"value".to_vec()));
+
+    database.update(&write_opts, b"key", |_| None).unwrap();
+    assert_eq!(db_get(&database, b"key"), None);
+}
+
+#[test]
+fn test_concurrent_updates_are_not_lost() {
+    let tmp = tmpdir("concurrent_update");
+    let database = Arc::new(open_database(tmp.path(), true));
+
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let database = database.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    database
+                        .update(&WriteOptions::default(), b"counter", |current| {
+                            let mut count = [0; 4];
+                            if let Some(current) = current {
+                                count.copy_from_slice(current);
+                            }
+                            Some((u32::from_be_bytes(count) + 1).to_be_bytes().to_vec())
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(800u32.to_be_bytes().to_vec())
+    );
+}
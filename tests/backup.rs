@@ -0,0 +1,139 @@
+use crate::utils::{db_get, db_put_simple, open_database, tmpdir};
+use leveldb::backup::BackupEngine;
+use std::collections::BTreeSet;
+use std::fs;
+use std::thread;
+
+#[test]
+fn test_backup_and_restore() {
+    let tmp = tmpdir("backup");
+    let database = open_database(&tmp.path().join("db"), true);
+    let engine = BackupEngine::open(&tmp.path().join("backups")).unwrap();
+
+    for i in 0..1000u32 {
+        db_put_simple(&database, &i.to_be_bytes(), &[1; 100]);
+    }
+    database.compact_range(None, None);
+    let first = engine.create_backup(&database).unwrap();
+    db_put_simple(&database, b"later", b"value");
+    let second = engine.create_backup(&database).unwrap();
+    assert!(second > first);
+
+    let backups = engine.backups().unwrap();
+    assert_eq!(backups.len(), 2);
+    // the compacted tables are stored once
+    let shared = |id: usize| -> BTreeSet<String> {
+        backups[id]
+            .files
+            .iter()
+            .filter(|file| file.stored.starts_with("shared"))
+            .map(|file| file.stored.clone())
+            .collect()
+    };
+    assert!(!shared(0).is_empty());
+    assert!(shared(0).is_subset(&shared(1)));
+    // shared tables are copied under a temporary name and renamed
+    for entry in fs::read_dir(tmp.path().join("backups/shared")).unwrap() {
+        let name = entry.unwrap().file_name();
+        assert!(!name.to_string_lossy().ends_with(".tmp"), "{:?}", name);
+    }
+    engine.verify(first).unwrap();
+
+    let restored = tmp.path().join("restored");
+    engine.restore(first, &restored).unwrap();
+    let restored = open_database(&restored, false);
+    assert_eq!(db_get(&restored, &7u32.to_be_bytes()), Some(vec![1; 100]));
+    assert_eq!(db_get(&restored, b"later"), None);
+    assert!(engine.restore(first, tmp.path()).is_err());
+}
+
+#[test]
+fn test_damaged_backup_is_not_restored() {
+    let tmp = tmpdir("backup_damaged");
+    let database = open_database(&tmp.path().join("db"), true);
+    let backups = tmp.path().join("backups");
+    let engine = BackupEngine::open(&backups).unwrap();
+    db_put_simple(&database, b"key", b"value");
+    database.compact_range(None, None);
+    let id = engine.create_backup(&database).unwrap();
+
+    let info = engine.backup_info(id).unwrap();
+    let table = info
+        .files
+        .iter()
+        .find(|file| file.stored.starts_with("shared"))
+        .unwrap();
+    let path = backups.join(&table.stored);
+    let mut contents = fs::read(&path).unwrap();
+    contents[0] ^= 0xff;
+    fs::write(&path, contents).unwrap();
+
+    assert!(engine.verify(id).is_err());
+    let target = tmp.path().join("restored");
+    assert!(engine.restore(id, &target).is_err());
+    assert!(!target.exists());
+}
+
+#[test]
+fn test_purge_old() {
+    let tmp = tmpdir("backup_purge");
+    let database = open_database(&tmp.path().join("db"), true);
+    let backups = tmp.path().join("backups");
+    let engine = BackupEngine::open(&backups).unwrap();
+
+    let mut ids = Vec::new();
+    for round in 0..3u8 {
+        for i in 0..100u32 {
+            db_put_simple(&database, &i.to_be_bytes(), &[round; 100]);
+        }
+        database.compact_range(None, None);
+        ids.push(engine.create_backup(&database).unwrap());
+    }
+
+    assert_eq!(engine.purge_old(1).unwrap(), 2);
+    let left: Vec<_> = engine.backups().unwrap().iter().map(|b| b.id).collect();
+    assert_eq!(left, vec![ids[2]]);
+    engine.verify(ids[2]).unwrap();
+    // only the files of the remaining backup are kept
+    let stored: BTreeSet<_> = engine.backups().unwrap()[0]
+        .files
+        .iter()
+        .filter(|file| file.stored.starts_with("shared"))
+        .map(|file| file.stored.clone())
+        .collect();
+    let shared: BTreeSet<_> = fs::read_dir(backups.join("shared"))
+        .unwrap()
+        .map(|entry| format!("shared/{}", entry.unwrap().file_name().to_string_lossy()))
+        .collect();
+    assert_eq!(shared, stored);
+    assert!(engine.backup_info(ids[0]).is_err());
+}
+
+#[test]
+fn test_concurrent_backups_get_distinct_ids() {
+    let tmp = tmpdir("backup_concurrent");
+    let database = open_database(&tmp.path().join("db"), true);
+    let backups = tmp.path().join("backups");
+    db_put_simple(&database, b"key", b"value");
+
+    let ids: BTreeSet<u64> = thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                scope.spawn(|| {
+                    // separate engines, like separate processes would have
+                    let engine = BackupEngine::open(&backups).unwrap();
+                    let id = engine.create_backup(&database).unwrap();
+                    engine.purge_old(2).unwrap();
+                    id
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+    assert_eq!(ids.len(), 4);
+
+    let engine = BackupEngine::open(&backups).unwrap();
+    for info in engine.backups().unwrap() {
+        engine.verify(info.id).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use crate::utils::{db_put_simple, open_database, open_with, tmpdir};
+use leveldb::batch::Writebatch;
+use leveldb::change_log::{ChangeRecord, Mutation, MAX_RECORD_SIZE};
+use leveldb::database::Database;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use std::io::{Cursor, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+
+fn open_logged(path: &Path) -> Database {
+    let opts = Options {
+        change_log: true,
+        ..Options::default()
+    };
+    open_with(path, opts)
+}
+
+fn put(key: &[u8], value: &[u8]) -> Mutation {
+    Mutation::Put {
+        key: key.to_vec(),
+        value: value.to_vec(),
+    }
+}
+
+#[test]
+fn test_changes_are_numbered() {
+    let tmp = tmpdir("cdc_numbered");
+    let database = open_logged(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"a", b"1");
+    let mut batch = Writebatch::new();
+    batch.put(b"b", b"2");
+    batch.delete(b"a");
+    database.write(&write_opts, &batch).unwrap();
+    assert_eq!(database.last_change_seq().unwrap(), 2);
+
+    let records: Vec<_> = database
+        .changes_since(0)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        records,
+        vec![
+            ChangeRecord {
+                seq: 1,
+                mutations: vec![put(b"a", b"1")],
+            },
+            ChangeRecord {
+                seq: 2,
+                mutations: vec![put(b"b", b"2"), Mutation::Delete { key: b"a".to_vec() },],
+            },
+        ]
+    );
+    assert_eq!(database.changes_since(1).unwrap().count(), 1);
+    assert_eq!(database.changes_since(2).unwrap().count(), 0);
+}
+
+#[test]
+fn test_truncation_and_recovery() {
+    let tmp = tmpdir("cdc_truncate");
+    {
+        let database = open_logged(tmp.path());
+        for i in 0..10u8 {
+            db_put_simple(&database, &[i], b"value");
+        }
+        let write_opts = WriteOptions::default();
+        assert_eq!(database.truncate_changes_before(&write_opts, 6).unwrap(), 5);
+        assert_eq!(
+            database
+                .changes_since(0)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+                .seq,
+            6
+        );
+        assert_eq!(
+            database.truncate_changes_before(&write_opts, 100).unwrap(),
+            5
+        );
+    }
+
+    // numbering continues after reopening, even with an empty log
+    let database = open_logged(tmp.path());
+    assert_eq!(database.last_change_seq().unwrap(), 10);
+    db_put_simple(&database, b"next", b"value");
+    let records: Vec<_> = database.changes_since(0).unwrap().collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].as_ref().unwrap().seq, 11);
+}
+
+#[test]
+fn test_change_log_must_be_enabled() {
+    let tmp = tmpdir("cdc_disabled");
+    let database = open_database(tmp.path(), true);
+
+    assert!(database.last_change_seq().is_err());
+    assert!(database.changes_since(0).is_err());
+}
+
+#[test]
+fn test_record_framing_round_trip() {
+    let records = vec![
+        ChangeRecord {
+            seq: 1,
+            mutations: vec![put(b"key", b"value")],
+        },
+        ChangeRecord {
+            seq: 2,
+            mutations: vec![Mutation::Delete {
+                key: b"key".to_vec(),
+            }],
+        },
+    ];
+    let mut stream = Vec::new();
+    for record in &records {
+        record.write_to(&mut stream).unwrap();
+    }
+
+    let mut reader = Cursor::new(stream);
+    let mut read = Vec::new();
+    while let Some(record) = ChangeRecord::read_from(&mut reader).unwrap() {
+        read.push(record);
+    }
+    assert_eq!(read, records);
+}
+
+#[test]
+fn test_corrupt_frames() {
+    let record = ChangeRecord {
+        seq: 1,
+        mutations: vec![put(b"key", b"value")],
+    };
+    let mut stream = Vec::new();
+    record.write_to(&mut stream).unwrap();
+
+    // cut off in the header and in the body
+    for len in &[5, stream.len() - 1] {
+        let error = ChangeRecord::read_from(&mut &stream[..*len]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    // a length above the limit is rejected before reading the body
+    let mut oversized = stream[..8].to_vec();
+    oversized.extend_from_slice(&(MAX_RECORD_SIZE as u32 + 1).to_be_bytes());
+    let error = ChangeRecord::read_from(&mut &oversized[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // an unknown operation tag
+    let mut garbled = stream.clone();
+    garbled[12] = 7;
+    let error = ChangeRecord::read_from(&mut &garbled[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_iterators_hide_log_entries() {
+    let tmp = tmpdir("cdc_hidden");
+    let database = open_logged(tmp.path());
+    let read_opts = ReadOptions::default();
+    db_put_simple(&database, b"a", b"1");
+    db_put_simple(&database, b"b", b"2");
+
+    let mut iter = database.iter(&read_opts);
+    iter.seek_to_first();
+    let mut keys = Vec::new();
+    while iter.valid() {
+        keys.push(iter.key().to_vec());
+        iter.next();
+    }
+    assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec()]);
+    iter.seek_to_last();
+    assert_eq!(iter.key(), b"b");
+    iter.seek(b"c");
+    assert!(!iter.valid());
+
+    let snapshot = database.snapshot();
+    let mut iter = snapshot.iter(&read_opts);
+    iter.seek(b"b");
+    assert_eq!(iter.key(), b"b");
+    iter.next();
+    assert!(!iter.valid());
+
+    let mut txn = database.locking_transaction(Duration::from_secs(5));
+    let scanned = txn.scan(&read_opts, b"", None).unwrap();
+    assert_eq!(scanned.keys().collect::<Vec<_>>(), vec![b"a", b"b"]);
+}
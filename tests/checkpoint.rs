@@ -0,0 +1,91 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::management::checkpoint;
+use leveldb::merge::AppendOperator;
+use leveldb::options::{Options, WriteOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn test_checkpoint_copies_data() {
+    let tmp = tmpdir("checkpoint");
+    let database = open_database(&tmp.path().join("db"), true);
+    for i in 0..1000u32 {
+        db_put_simple(&database, &i.to_be_bytes(), &[7; 100]);
+    }
+    // some data in tables, some only in the log
+    database.compact_range(None, None);
+    db_put_simple(&database, b"unflushed", b"value");
+
+    let copy = tmp.path().join("copy");
+    checkpoint(&database, &copy).unwrap();
+    db_put_simple(&database, b"later", b"value");
+
+    let copy = open_database(&copy, false);
+    assert_eq!(db_get(&copy, &999u32.to_be_bytes()), Some(vec![7; 100]));
+    assert_eq!(db_get(&copy, b"unflushed"), Some(b"value".to_vec()));
+    assert_eq!(db_get(&copy, b"later"), None);
+}
+
+#[test]
+fn test_checkpoint_keeps_features() {
+    let tmp = tmpdir("checkpoint_features");
+    let options = || Options {
+        merge_operator: Some(Arc::new(AppendOperator)),
+        change_log: true,
+        ..Options::default()
+    };
+    let database = open_with(&tmp.path().join("db"), options());
+    db_put_simple(&database, b"key", b"a");
+    database
+        .merge(&WriteOptions::default(), b"key", b"b")
+        .unwrap();
+
+    let copy = tmp.path().join("copy");
+    checkpoint(&database, &copy).unwrap();
+
+    let copy = open_with(&copy, options());
+    assert_eq!(db_get(&copy, b"key"), Some(b"ab".to_vec()));
+    assert_eq!(
+        copy.last_change_seq().unwrap(),
+        database.last_change_seq().unwrap()
+    );
+}
+
+#[test]
+fn test_checkpoint_during_writes() {
+    let tmp = tmpdir("checkpoint_live");
+    let database = open_database(&tmp.path().join("db"), true);
+    let done = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut i = 0u32;
+            while !done.load(Ordering::SeqCst) {
+                db_put_simple(&database, &i.to_be_bytes(), &[i as u8; 1000]);
+                i += 1;
+            }
+        });
+        for n in 0..3 {
+            let copy = tmp.path().join(format!("copy{}", n));
+            checkpoint(&database, &copy).unwrap();
+            // every key in the copy holds the value written for it
+            let copy = open_database(&copy, false);
+            let mut iter = copy.iter(&Default::default());
+            iter.seek_to_first();
+            while iter.valid() {
+                assert_eq!(iter.value(), &[iter.key()[3]; 1000][..]);
+                iter.next();
+            }
+        }
+        done.store(true, Ordering::SeqCst);
+    });
+}
+
+#[test]
+fn test_checkpoint_needs_new_directory() {
+    let tmp = tmpdir("checkpoint_exists");
+    let database = open_database(&tmp.path().join("db"), true);
+
+    assert!(checkpoint(&database, tmp.path()).is_err());
+}
@@ -0,0 +1,128 @@
+use crate::format::Format;
+use crate::utils::{db_put_simple, open_with, tmpdir};
+use leveldb::merge::AppendOperator;
+use leveldb::options::{Options, WriteOptions};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn cli(path: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_leveldb-cli"))
+        .arg(path)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_utf8_escapes_round_trip() {
+    let bytes = b"a\x00\xff\\\n\"\x7f";
+    let shown = Format::Utf8.display(bytes);
+    assert_eq!(shown, "a\\x00\\xff\\\\\\n\\\"\\x7f");
+    assert_eq!(Format::Utf8.parse(&shown).unwrap(), bytes.to_vec());
+
+    // valid text is only escaped where needed
+    assert_eq!(Format::Utf8.display("é\tb\\".as_bytes()), "é\\tb\\\\");
+    assert_eq!(
+        Format::Utf8.parse("é\\tb\\\\").unwrap(),
+        "é\tb\\".as_bytes().to_vec()
+    );
+    assert_eq!(Format::Utf8.parse("\\x4A\\x4b").unwrap(), b"JK".to_vec());
+}
+
+#[test]
+fn test_invalid_utf8_escapes() {
+    for input in &["\\", "\\q", "\\x4", "\\x4g", "a\\x"] {
+        assert!(Format::Utf8.parse(input).is_err(), "{:?}", input);
+    }
+}
+
+#[test]
+fn test_hex_and_base64() {
+    let bytes = b"\x00key\xff";
+    assert_eq!(Format::Hex.display(bytes), "006b6579ff");
+    assert_eq!(Format::Hex.parse("006b6579ff").unwrap(), bytes.to_vec());
+    assert_eq!(Format::Base64.display(bytes), "AGtlef8=");
+    assert_eq!(Format::Base64.parse("AGtlef8=").unwrap(), bytes.to_vec());
+    assert!(Format::Hex.parse("0g").is_err());
+    assert!(Format::Base64.parse("!").is_err());
+}
+
+#[test]
+fn test_put_get_delete() {
+    let tmp = tmpdir("cli_put_get");
+    let path = tmp.path().join("db");
+
+    stdout(&cli(
+        &path,
+        &["--create-if-missing", "put", "key", "a\\x00b"],
+    ));
+    assert_eq!(stdout(&cli(&path, &["get", "key"])), "a\\x00b\n");
+    assert_eq!(
+        stdout(&cli(&path, &["-v", "hex", "get", "key"])),
+        "610062\n"
+    );
+
+    stdout(&cli(&path, &["delete", "key"]));
+    let output = cli(&path, &["get", "key"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("key not found"));
+}
+
+#[test]
+fn test_scan_and_count_hide_reserved_keys() {
+    let tmp = tmpdir("cli_scan");
+    let path = tmp.path().join("db");
+    {
+        let options = Options {
+            merge_operator: Some(Arc::new(AppendOperator)),
+            ..Options::default()
+        };
+        let database = open_with(&path, options);
+        db_put_simple(&database, b"a", b"1");
+        db_put_simple(&database, b"b", b"2");
+        database
+            .merge(&WriteOptions::default(), b"a", b"3")
+            .unwrap();
+    }
+
+    assert_eq!(stdout(&cli(&path, &["scan"])), "a\t1\nb\t2\n");
+    assert_eq!(stdout(&cli(&path, &["scan", "--from", "b"])), "b\t2\n");
+    assert_eq!(stdout(&cli(&path, &["count"])), "2\n");
+}
+
+#[test]
+fn test_ttl_flag() {
+    let tmp = tmpdir("cli_ttl");
+    let path = tmp.path().join("db");
+    {
+        let options = Options {
+            ttl: true,
+            ..Options::default()
+        };
+        let database = open_with(&path, options);
+        db_put_simple(&database, b"key", b"value");
+        database
+            .put_with_ttl(
+                &WriteOptions::default(),
+                b"gone",
+                b"value",
+                Duration::from_millis(1),
+            )
+            .unwrap();
+    }
+
+    assert!(!cli(&path, &["get", "key"]).status.success());
+    assert_eq!(stdout(&cli(&path, &["--ttl", "get", "key"])), "value\n");
+    assert_eq!(stdout(&cli(&path, &["--ttl", "scan"])), "key\tvalue\n");
+}
@@ -0,0 +1,102 @@
+use crate::utils::{db_get, db_put_simple, open_database, tmpdir};
+use leveldb::database::export::{export, export_json_lines, import, import_json_lines};
+
+#[test]
+fn test_export_import_round_trip() {
+    let tmp = tmpdir("export_round_trip");
+    let source = open_database(&tmp.path().join("source"), true);
+    for i in 0..2500u32 {
+        db_put_simple(&source, &i.to_be_bytes(), &[i as u8; 3]);
+    }
+    db_put_simple(&source, b"", b"empty key");
+
+    let mut stream = Vec::new();
+    assert_eq!(export(&source, &mut stream, ..).unwrap(), 2501);
+    let target = open_database(&tmp.path().join("target"), true);
+    assert_eq!(import(&target, &stream[..]).unwrap(), 2501);
+    assert_eq!(db_get(&target, b""), Some(b"empty key".to_vec()));
+    assert_eq!(
+        db_get(&target, &2499u32.to_be_bytes()),
+        Some(vec![2499u32 as u8; 3])
+    );
+}
+
+#[test]
+fn test_export_range() {
+    let tmp = tmpdir("export_range");
+    let source = open_database(&tmp.path().join("source"), true);
+    for key in &[b"a", b"b", b"c", b"d"] {
+        db_put_simple(&source, *key, b"value");
+    }
+
+    let mut stream = Vec::new();
+    assert_eq!(
+        export(&source, &mut stream, &b"b"[..]..&b"d"[..]).unwrap(),
+        2
+    );
+    let target = open_database(&tmp.path().join("target"), true);
+    assert_eq!(import(&target, &stream[..]).unwrap(), 2);
+    assert_eq!(db_get(&target, b"a"), None);
+    assert_eq!(db_get(&target, b"c"), Some(b"value".to_vec()));
+    assert_eq!(db_get(&target, b"d"), None);
+}
+
+#[test]
+fn test_corrupt_export_imports_nothing() {
+    let tmp = tmpdir("export_corrupt");
+    let source = open_database(&tmp.path().join("source"), true);
+    for i in 0..2500u32 {
+        db_put_simple(&source, &i.to_be_bytes(), b"value");
+    }
+    let mut stream = Vec::new();
+    export(&source, &mut stream, ..).unwrap();
+    let target = open_database(&tmp.path().join("target"), true);
+
+    // flip a byte in the last value
+    let mut garbled = stream.clone();
+    *garbled.last_mut().unwrap() ^= 1;
+    assert!(import(&target, &garbled[..]).is_err());
+    assert_eq!(db_get(&target, &0u32.to_be_bytes()), None);
+
+    let truncated = &stream[..stream.len() - 1];
+    assert!(import(&target, truncated).is_err());
+    assert_eq!(db_get(&target, &0u32.to_be_bytes()), None);
+}
+
+#[test]
+fn test_oversized_length_is_rejected() {
+    let tmp = tmpdir("export_oversized");
+    let source = open_database(&tmp.path().join("source"), true);
+    db_put_simple(&source, b"key", b"value");
+    let mut stream = Vec::new();
+    export(&source, &mut stream, ..).unwrap();
+
+    // the entry starts right after the header; claim a huge key
+    let entry = stream.len() - (4 + 3 + 4 + 5);
+    stream[entry..entry + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let target = open_database(&tmp.path().join("target"), true);
+    assert!(import(&target, &stream[..]).is_err());
+    assert_eq!(db_get(&target, b"key"), None);
+}
+
+#[test]
+fn test_json_lines_round_trip() {
+    let tmp = tmpdir("export_json");
+    let source = open_database(&tmp.path().join("source"), true);
+    db_put_simple(&source, &[0, 1, 0xff], b"binary");
+    db_put_simple(&source, b"text", b"hello");
+
+    let mut lines = Vec::new();
+    assert_eq!(export_json_lines(&source, &mut lines, ..).unwrap(), 2);
+    let target = open_database(&tmp.path().join("target"), true);
+    assert_eq!(import_json_lines(&target, &lines[..]).unwrap(), 2);
+    assert_eq!(db_get(&target, &[0, 1, 0xff]), Some(b"binary".to_vec()));
+    assert_eq!(db_get(&target, b"text"), Some(b"hello".to_vec()));
+
+    let mut invalid = lines.clone();
+    invalid.extend_from_slice(b"\n{\"key\":\"!\"}\n");
+    let other = open_database(&tmp.path().join("other"), true);
+    let error = import_json_lines(&other, &invalid[..]).unwrap_err();
+    assert!(error.to_string().contains("line 4"), "{}", error);
+    assert_eq!(db_get(&other, b"text"), None);
+}
@@ -0,0 +1,82 @@
+use crate::utils::{db_put_simple, open_database, tmpdir};
+use leveldb::options::ReadOptions;
+
+#[test]
+fn test_get_returns_owned_value() {
+    let tmp = tmpdir("get_owned");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"key", b"value");
+
+    let read_opts = ReadOptions::default();
+    assert_eq!(
+        database.get(&read_opts, b"key").unwrap(),
+        Some(b"value".to_vec())
+    );
+    assert_eq!(database.get(&read_opts, b"missing").unwrap(), None);
+}
+
+#[test]
+fn test_contains_key() {
+    let tmp = tmpdir("contains_key");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"key", b"");
+
+    let read_opts = ReadOptions::default();
+    assert!(database.contains_key(&read_opts, b"key").unwrap());
+    assert!(!database.contains_key(&read_opts, b"missing").unwrap());
+}
+
+#[test]
+fn test_get_or_defaults() {
+    let tmp = tmpdir("get_or");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"key", b"value");
+
+    let read_opts = ReadOptions::default();
+    assert_eq!(
+        database.get_or_default(&read_opts, b"missing").unwrap(),
+        Vec::<u8>::new()
+    );
+    assert_eq!(
+        database
+            .get_or(&read_opts, b"missing", b"fallback")
+            .unwrap(),
+        b"fallback".to_vec()
+    );
+    assert_eq!(
+        database.get_or(&read_opts, b"key", b"fallback").unwrap(),
+        b"value".to_vec()
+    );
+    assert_eq!(
+        database
+            .get_or_else(&read_opts, b"missing", || b"computed".to_vec())
+            .unwrap(),
+        b"computed".to_vec()
+    );
+}
+
+#[test]
+fn test_get_as_or_default() {
+    let tmp = tmpdir("get_as");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"count", &7u32.to_be_bytes());
+
+    let read_opts = ReadOptions::default();
+    let decode = |bytes: &[u8]| {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(bytes);
+        u32::from_be_bytes(buf)
+    };
+    assert_eq!(
+        database
+            .get_as_or_default(&read_opts, b"count", decode)
+            .unwrap(),
+        7
+    );
+    assert_eq!(
+        database
+            .get_as_or_default(&read_opts, b"missing", decode)
+            .unwrap(),
+        0
+    );
+}
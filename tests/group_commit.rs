@@ -0,0 +1,64 @@
+use crate::utils::{db_get, open_database, tmpdir};
+use leveldb::batch::Writebatch;
+use leveldb::group_commit::GroupCommitWriter;
+use leveldb::options::WriteOptions;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_concurrent_sync_writes() {
+    let tmp = tmpdir("group_commit");
+    let database = Arc::new(open_database(tmp.path(), true));
+    let writer = Arc::new(GroupCommitWriter::new(
+        database.clone(),
+        Duration::from_millis(1),
+    ));
+    let sync = WriteOptions { sync: true };
+
+    let threads: Vec<_> = (0..8u32)
+        .map(|t| {
+            let writer = writer.clone();
+            thread::spawn(move || {
+                for i in 0..50u32 {
+                    let key = [t.to_be_bytes(), i.to_be_bytes()].concat();
+                    writer.put(&sync, &key, b"value").unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    for t in 0..8u32 {
+        for i in 0..50u32 {
+            let key = [t.to_be_bytes(), i.to_be_bytes()].concat();
+            assert_eq!(db_get(&database, &key), Some(b"value".to_vec()));
+        }
+    }
+}
+
+#[test]
+fn test_writes_keep_their_order() {
+    let tmp = tmpdir("group_commit_order");
+    let database = Arc::new(open_database(tmp.path(), true));
+    let writer = GroupCommitWriter::new(database.clone(), Duration::from_secs(0));
+    let sync = WriteOptions { sync: true };
+
+    writer.put(&sync, b"key", b"first").unwrap();
+    let mut batch = Writebatch::new();
+    batch.put(b"key", b"second");
+    batch.put(b"other", b"value");
+    writer.write(&sync, batch).unwrap();
+    writer.delete(&sync, b"other").unwrap();
+    // writes that need no sync bypass the groups
+    writer
+        .put(&WriteOptions::default(), b"unsynced", b"value")
+        .unwrap();
+
+    assert_eq!(db_get(&database, b"key"), Some(b"second".to_vec()));
+    assert_eq!(db_get(&database, b"other"), None);
+    assert_eq!(db_get(&database, b"unsynced"), Some(b"value".to_vec()));
+    assert!(Arc::ptr_eq(writer.database(), &database));
+}
@@ -0,0 +1,95 @@
+use crate::utils::{open_database, tmpdir};
+use leveldb::batch::Writebatch;
+use leveldb::index::IndexedDatabase;
+use leveldb::options::{ReadOptions, WriteOptions};
+
+/// index values by their first byte.
+fn by_first_byte(_key: &[u8], value: &[u8]) -> Vec<Vec<u8>> {
+    value.iter().take(1).map(|&b| vec![b]).collect()
+}
+
+#[test]
+fn test_lookup_follows_writes() {
+    let tmp = tmpdir("index_lookup");
+    let mut database = IndexedDatabase::new(open_database(tmp.path(), true));
+    database.add_index("first", by_first_byte);
+    let write_opts = WriteOptions::default();
+    let read_opts = ReadOptions::default();
+
+    database.put(&write_opts, b"a", b"apple").unwrap();
+    database.put(&write_opts, b"b", b"avocado").unwrap();
+    database.put(&write_opts, b"c", b"banana").unwrap();
+    assert_eq!(
+        database
+            .lookup_keys_by_index(&read_opts, "first", b"a")
+            .unwrap(),
+        vec![b"a".to_vec(), b"b".to_vec()]
+    );
+
+    // changing a value moves its entry, deleting it removes the entry
+    database.put(&write_opts, b"a", b"blueberry").unwrap();
+    database.delete(&write_opts, b"c").unwrap();
+    assert_eq!(
+        database
+            .lookup_keys_by_index(&read_opts, "first", b"a")
+            .unwrap(),
+        vec![b"b".to_vec()]
+    );
+    let entries = database.lookup_by_index(&read_opts, "first", b"b").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[&b"a".to_vec()], b"blueberry".to_vec());
+}
+
+#[test]
+fn test_batch_updates_indexes() {
+    let tmp = tmpdir("index_batch");
+    let mut database = IndexedDatabase::new(open_database(tmp.path(), true));
+    database.add_index("first", by_first_byte);
+
+    let mut batch = Writebatch::new();
+    batch.put(b"a", b"xylophone");
+    batch.put(b"b", b"xenon");
+    batch.put(b"a", b"yak");
+    database.write(&WriteOptions::default(), &batch).unwrap();
+
+    let read_opts = ReadOptions::default();
+    assert_eq!(
+        database
+            .lookup_keys_by_index(&read_opts, "first", b"x")
+            .unwrap(),
+        vec![b"b".to_vec()]
+    );
+    assert_eq!(
+        database
+            .lookup_keys_by_index(&read_opts, "first", b"y")
+            .unwrap(),
+        vec![b"a".to_vec()]
+    );
+}
+
+#[test]
+fn test_rebuild_index() {
+    let tmp = tmpdir("index_rebuild");
+    let database = open_database(tmp.path(), true);
+    let write_opts = WriteOptions::default();
+    // written before the index existed
+    database.put(&write_opts, b"a", b"apple").unwrap();
+    database.put(&write_opts, b"b", b"banana").unwrap();
+
+    let mut database = IndexedDatabase::new(database);
+    database.add_index("first", by_first_byte);
+    let read_opts = ReadOptions::default();
+    assert!(database
+        .lookup_keys_by_index(&read_opts, "first", b"a")
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(database.rebuild_index(&write_opts, "first").unwrap(), 2);
+    assert_eq!(
+        database
+            .lookup_keys_by_index(&read_opts, "first", b"a")
+            .unwrap(),
+        vec![b"a".to_vec()]
+    );
+    assert!(database.rebuild_index(&write_opts, "missing").is_err());
+}
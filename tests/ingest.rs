@@ -0,0 +1,144 @@
+use crate::utils::{db_get, db_put_simple, open_database, tmpdir};
+use leveldb::format::sst_writer::{SstOptions, SstWriter};
+use leveldb::format::table::Table;
+use leveldb::format::{ValueType, MAX_SEQUENCE};
+use leveldb::management::{ingest, last_sequence};
+use std::path::{Path, PathBuf};
+
+/// write a table of `keys`, all set to `value`, at `sequence`.
+fn write_table(path: &Path, sequence: u64, keys: &[u32], value: &[u8]) -> PathBuf {
+    let mut writer = SstWriter::create(path, sequence, SstOptions::default()).unwrap();
+    for key in keys {
+        writer.put(&key.to_be_bytes(), value).unwrap();
+    }
+    writer.finish().unwrap();
+    path.to_path_buf()
+}
+
+#[test]
+fn test_sst_writer() {
+    let tmp = tmpdir("sst_writer");
+    let path = tmp.path().join("table.ldb");
+    let options = SstOptions {
+        block_size: 512,
+        compression: true,
+        bloom_filter_bits_per_key: Some(10),
+        ..SstOptions::default()
+    };
+    let mut writer = SstWriter::create(&path, 9, options).unwrap();
+    for i in 0..1000u32 {
+        writer.put(&i.to_be_bytes(), &[0; 100]).unwrap();
+    }
+    writer.delete(&1000u32.to_be_bytes()).unwrap();
+    // keys have to increase
+    assert!(writer.put(&5u32.to_be_bytes(), b"value").is_err());
+    assert!(writer.delete(&1000u32.to_be_bytes()).is_err());
+    assert_eq!(writer.entries(), 1001);
+    let size = writer.finish().unwrap();
+
+    let table = Table::open(&path).unwrap();
+    assert_eq!(table.file_size(), size);
+    assert!(table.has_filter());
+    // the zeroes compress well
+    assert!(size < 1000 * 100 / 2);
+    let entries: Vec<_> = table.iter().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 1001);
+    assert!(entries.iter().all(|(key, _)| key.sequence == 9));
+    assert_eq!(entries[999].1, vec![0; 100]);
+    assert_eq!(entries[1000].0.value_type, ValueType::Deletion);
+
+    let too_large = tmp.path().join("too_large.ldb");
+    assert!(SstWriter::create(&too_large, MAX_SEQUENCE + 1, SstOptions::default()).is_err());
+}
+
+#[test]
+fn test_ingest_tables() {
+    let tmp = tmpdir("ingest");
+    let path = tmp.path().join("db");
+    {
+        let database = open_database(&path, true);
+        for i in 0..100u32 {
+            db_put_simple(&database, &i.to_be_bytes(), b"old");
+        }
+        database.compact_range(None, None);
+    }
+
+    let sequence = last_sequence(&path).unwrap() + 1;
+    assert_eq!(sequence, 101);
+    let low = write_table(&tmp.path().join("low.ldb"), sequence, &[10, 11, 12], b"new");
+    let mut writer = SstWriter::create(
+        &tmp.path().join("high.ldb"),
+        sequence,
+        SstOptions::default(),
+    )
+    .unwrap();
+    writer.delete(&50u32.to_be_bytes()).unwrap();
+    writer.put(&500u32.to_be_bytes(), b"new").unwrap();
+    writer.finish().unwrap();
+    let high = tmp.path().join("high.ldb");
+
+    let added = ingest(&path, &[high.as_path(), low.as_path()]).unwrap();
+    assert_eq!(added.len(), 2);
+    assert_eq!(added[0].1.smallest.user_key, 10u32.to_be_bytes());
+    assert_eq!(added[1].1.largest.user_key, 500u32.to_be_bytes());
+    // the existing table holds the same keys, so they go above it
+    assert!(added.iter().all(|(level, _)| *level < 6));
+    assert!(!low.exists() && !high.exists());
+    assert_eq!(last_sequence(&path).unwrap(), sequence);
+
+    let database = open_database(&path, false);
+    assert_eq!(
+        db_get(&database, &9u32.to_be_bytes()),
+        Some(b"old".to_vec())
+    );
+    assert_eq!(
+        db_get(&database, &10u32.to_be_bytes()),
+        Some(b"new".to_vec())
+    );
+    assert_eq!(db_get(&database, &50u32.to_be_bytes()), None);
+    assert_eq!(
+        db_get(&database, &500u32.to_be_bytes()),
+        Some(b"new".to_vec())
+    );
+}
+
+#[test]
+fn test_ingest_rejects_invalid_tables() {
+    let tmp = tmpdir("ingest_invalid");
+    let path = tmp.path().join("db");
+    {
+        let database = open_database(&path, true);
+        for i in 0..10u32 {
+            db_put_simple(&database, &i.to_be_bytes(), b"old");
+        }
+    }
+    let last = last_sequence(&path).unwrap();
+    assert_eq!(last, 10);
+
+    // not newer than the database
+    let stale = write_table(&tmp.path().join("stale.ldb"), last, &[100], b"new");
+    assert!(ingest(&path, &[stale.as_path()]).is_err());
+    assert!(stale.exists());
+
+    // overlapping each other
+    let a = write_table(&tmp.path().join("a.ldb"), last + 1, &[100, 200], b"new");
+    let b = write_table(&tmp.path().join("b.ldb"), last + 1, &[150], b"new");
+    assert!(ingest(&path, &[a.as_path(), b.as_path()]).is_err());
+    assert!(a.exists() && b.exists());
+
+    // overlapping the writes still in the log
+    let logged = write_table(&tmp.path().join("logged.ldb"), last + 1, &[5], b"new");
+    assert!(ingest(&path, &[logged.as_path()]).is_err());
+
+    // empty
+    let empty = write_table(&tmp.path().join("empty.ldb"), last + 1, &[], b"new");
+    assert!(ingest(&path, &[empty.as_path()]).is_err());
+
+    // the database is unchanged
+    let database = open_database(&path, false);
+    assert_eq!(
+        db_get(&database, &5u32.to_be_bytes()),
+        Some(b"old".to_vec())
+    );
+    assert_eq!(db_get(&database, &100u32.to_be_bytes()), None);
+}
@@ -0,0 +1,149 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::locking::LockMode;
+use leveldb::merge::AppendOperator;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use leveldb::transaction::TransactionError;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn test_commit_applies_writes() {
+    let tmp = tmpdir("locking_commit");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"a", b"1");
+    let read_opts = ReadOptions::default();
+
+    let mut txn = database.locking_transaction(TIMEOUT);
+    assert_eq!(
+        txn.get_for_update(&read_opts, b"a").unwrap(),
+        Some(b"1".to_vec())
+    );
+    txn.put(b"a", b"2").unwrap();
+    txn.put(b"b", b"3").unwrap();
+    assert_eq!(txn.get(&read_opts, b"a").unwrap(), Some(b"2".to_vec()));
+    let scanned = txn.scan(&read_opts, b"a", Some(b"c")).unwrap();
+    assert_eq!(scanned.len(), 2);
+    txn.commit(&WriteOptions::default()).unwrap();
+
+    assert_eq!(db_get(&database, b"a"), Some(b"2".to_vec()));
+    assert_eq!(db_get(&database, b"b"), Some(b"3".to_vec()));
+}
+
+#[test]
+fn test_reads_fold_merge_operands() {
+    let tmp = tmpdir("locking_merge");
+    let opts = Options {
+        merge_operator: Some(Arc::new(AppendOperator)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let read_opts = ReadOptions::default();
+    let write_opts = WriteOptions::default();
+    db_put_simple(&database, b"a", b"1");
+    database.merge(&write_opts, b"a", b"2").unwrap();
+    database.merge(&write_opts, b"b", b"3").unwrap();
+    database.merge(&write_opts, b"d", b"4").unwrap();
+
+    let mut txn = database.locking_transaction(TIMEOUT);
+    assert_eq!(
+        txn.get_for_update(&read_opts, b"a").unwrap(),
+        Some(b"12".to_vec())
+    );
+    txn.put(b"c", b"5").unwrap();
+    let scanned: Vec<_> = txn
+        .scan(&read_opts, b"a", Some(b"d"))
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(
+        scanned,
+        vec![
+            (b"a".to_vec(), b"12".to_vec()),
+            (b"b".to_vec(), b"3".to_vec()),
+            (b"c".to_vec(), b"5".to_vec()),
+        ]
+    );
+    txn.commit(&write_opts).unwrap();
+    assert_eq!(db_get(&database, b"a"), Some(b"12".to_vec()));
+}
+
+#[test]
+fn test_shared_locks_are_compatible() {
+    let tmp = tmpdir("locking_shared");
+    let database = open_database(tmp.path(), true);
+
+    let mut first = database.locking_transaction(TIMEOUT);
+    let mut second = database.locking_transaction(Duration::from_millis(50));
+    first.lock_key(b"key", LockMode::Shared).unwrap();
+    second.lock_key(b"key", LockMode::Shared).unwrap();
+}
+
+#[test]
+fn test_exclusive_lock_times_out() {
+    let tmp = tmpdir("locking_timeout");
+    let database = open_database(tmp.path(), true);
+
+    let mut holder = database.locking_transaction(TIMEOUT);
+    holder.lock_key(b"key", LockMode::Exclusive).unwrap();
+    let mut waiter = database.locking_transaction(Duration::from_millis(50));
+    match waiter.lock_key(b"key", LockMode::Shared) {
+        Err(TransactionError::Timeout) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+
+    drop(holder);
+    waiter.lock_key(b"key", LockMode::Shared).unwrap();
+}
+
+#[test]
+fn test_range_lock_covers_keys() {
+    let tmp = tmpdir("locking_range");
+    let database = open_database(tmp.path(), true);
+
+    let mut holder = database.locking_transaction(TIMEOUT);
+    holder
+        .lock_range(b"b", Some(b"d"), LockMode::Exclusive)
+        .unwrap();
+    let mut waiter = database.locking_transaction(Duration::from_millis(50));
+    assert!(waiter.lock_key(b"a", LockMode::Exclusive).is_ok());
+    assert!(waiter.lock_key(b"d", LockMode::Exclusive).is_ok());
+    match waiter.put(b"c", b"value") {
+        Err(TransactionError::Timeout) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_deadlock_is_detected() {
+    let tmp = tmpdir("locking_deadlock");
+    let database = Arc::new(open_database(tmp.path(), true));
+    let barrier = Arc::new(Barrier::new(2));
+
+    let threads: Vec<_> = [(b"a", b"b"), (b"b", b"a")]
+        .iter()
+        .map(|&(first, second)| {
+            let database = database.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut txn = database.locking_transaction(TIMEOUT);
+                txn.lock_key(first, LockMode::Exclusive).unwrap();
+                barrier.wait();
+                let result = txn.lock_key(second, LockMode::Exclusive);
+                // release the locks, so the other transaction can go on
+                drop(txn);
+                result
+            })
+        })
+        .collect();
+    let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+    let deadlocks = results
+        .iter()
+        .filter(|result| matches!(result, Err(TransactionError::Deadlock)))
+        .count();
+    assert_eq!(deadlocks, 1, "{:?}", results);
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+}
@@ -0,0 +1,128 @@
+use leveldb::format::log::{LogReader, LogWriter, Record, WriteBatch, BLOCK_SIZE, HEADER_SIZE};
+use leveldb::format::{FormatError, ValueType};
+
+fn write_log(records: &[Vec<u8>]) -> Vec<u8> {
+    let mut writer = LogWriter::new(Vec::new(), 0);
+    for record in records {
+        writer.add_record(record).unwrap();
+    }
+    writer.into_inner()
+}
+
+fn read_log(log: &[u8]) -> Vec<Result<Record, FormatError>> {
+    LogReader::new(log).collect()
+}
+
+fn corruption_offset(result: &Result<Record, FormatError>) -> u64 {
+    match *result {
+        Err(FormatError::Corruption { offset, .. }) => offset,
+        ref other => panic!("expected a corruption, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let records = vec![
+        b"first".to_vec(),
+        Vec::new(),
+        // spans three blocks
+        (0..3 * BLOCK_SIZE).map(|i| i as u8).collect(),
+        // leaves less than a header at the end of the block
+        vec![7; BLOCK_SIZE - 2 * HEADER_SIZE - 3],
+        b"last".to_vec(),
+    ];
+    let log = write_log(&records);
+
+    let read: Vec<Record> = read_log(&log).into_iter().map(Result::unwrap).collect();
+    let data: Vec<Vec<u8>> = read.iter().map(|record| record.data.clone()).collect();
+    assert_eq!(data, records);
+    assert_eq!(read[0].offset, 0);
+    assert_eq!(read[1].offset, (HEADER_SIZE + 5) as u64);
+}
+
+#[test]
+fn test_append_to_existing_log() {
+    let mut log = write_log(&[b"first".to_vec()]);
+    let length = log.len() as u64;
+    let mut writer = LogWriter::new(&mut log, length);
+    writer.add_record(&vec![1; BLOCK_SIZE]).unwrap();
+    writer.add_record(b"third").unwrap();
+
+    let data: Vec<Vec<u8>> = read_log(&log)
+        .into_iter()
+        .map(|record| record.unwrap().data)
+        .collect();
+    assert_eq!(
+        data,
+        vec![b"first".to_vec(), vec![1; BLOCK_SIZE], b"third".to_vec()]
+    );
+}
+
+#[test]
+fn test_damaged_fragment_is_skipped() {
+    let records = vec![
+        b"first".to_vec(),
+        vec![1; BLOCK_SIZE],
+        vec![2; BLOCK_SIZE],
+        b"last".to_vec(),
+    ];
+    let mut log = write_log(&records);
+    // garble the data of the first record
+    log[HEADER_SIZE] ^= 0xff;
+
+    let read = read_log(&log);
+    assert_eq!(corruption_offset(&read[0]), 0);
+    let intact: Vec<Vec<u8>> = read
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|record| record.data)
+        .collect();
+    // the rest of the first block, and the record starting in it, are lost
+    assert_eq!(intact, records[2..].to_vec());
+}
+
+#[test]
+fn test_truncated_log() {
+    let log = write_log(&[b"first".to_vec(), vec![3; 2 * BLOCK_SIZE]]);
+
+    // cut in the middle fragment of the second record
+    let read = read_log(&log[..BLOCK_SIZE + 100]);
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[0].as_ref().unwrap().data, b"first");
+    assert_eq!(corruption_offset(&read[1]), (HEADER_SIZE + 5) as u64);
+    assert_eq!(corruption_offset(&read[2]), BLOCK_SIZE as u64);
+
+    // a partial header at the end is reported as well
+    let read = read_log(&log[..HEADER_SIZE + 5 + 3]);
+    assert_eq!(read.len(), 2);
+    assert_eq!(corruption_offset(&read[1]), (HEADER_SIZE + 5) as u64);
+}
+
+#[test]
+fn test_decode_write_batch() {
+    let mut data = Vec::new();
+    data.extend_from_slice(&42u64.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&[ValueType::Value as u8, 3]);
+    data.extend_from_slice(b"key");
+    data.extend_from_slice(&[5]);
+    data.extend_from_slice(b"value");
+    data.extend_from_slice(&[ValueType::Deletion as u8, 4]);
+    data.extend_from_slice(b"gone");
+    let log = write_log(&[data.clone()]);
+
+    let record = read_log(&log).pop().unwrap().unwrap();
+    let batch = WriteBatch::decode(&record).unwrap();
+    assert_eq!(batch.sequence, 42);
+    assert_eq!(batch.entries.len(), 2);
+    assert_eq!(batch.entries[0].value_type, ValueType::Value);
+    assert_eq!(batch.entries[0].key, b"key");
+    assert_eq!(batch.entries[0].value, b"value");
+    assert_eq!(batch.entries[1].value_type, ValueType::Deletion);
+    assert_eq!(batch.entries[1].key, b"gone");
+
+    // a count that doesn't match the entries
+    data[8] = 3;
+    let record = Record { offset: 0, data };
+    assert!(WriteBatch::decode(&record).is_err());
+}
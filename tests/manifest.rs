@@ -0,0 +1,162 @@
+use leveldb::format::log::LogWriter;
+use leveldb::format::manifest::{append_edit, FileMetaData, Manifest, Version, VersionEdit};
+use leveldb::format::{InternalKey, ValueType};
+use std::fs;
+use tempdir::TempDir;
+
+fn file(number: u64, smallest: &[u8], sequence: u64, largest: &[u8]) -> FileMetaData {
+    FileMetaData {
+        number,
+        size: 1000 + number,
+        smallest: InternalKey::new(smallest, sequence, ValueType::Value),
+        largest: InternalKey::new(largest, 1, ValueType::Deletion),
+    }
+}
+
+fn edits() -> Vec<VersionEdit> {
+    vec![
+        VersionEdit {
+            comparator: Some("leveldb.BytewiseComparator".to_string()),
+            log_number: Some(3),
+            next_file_number: Some(4),
+            last_sequence: Some(0),
+            ..VersionEdit::default()
+        },
+        VersionEdit {
+            log_number: Some(9),
+            prev_log_number: Some(0),
+            next_file_number: Some(10),
+            last_sequence: Some(500),
+            new_files: vec![
+                (1, file(7, b"m", 5, b"p")),
+                (1, file(5, b"c", 5, b"f")),
+                (0, file(6, b"a", 5, b"z")),
+            ],
+            ..VersionEdit::default()
+        },
+        VersionEdit {
+            last_sequence: Some(800),
+            compact_pointers: vec![(1, InternalKey::new(b"f", 5, ValueType::Value))],
+            deleted_files: vec![(0, 6)],
+            // the same user key as file 5, but newer, so it sorts first
+            new_files: vec![(1, file(8, b"c", 9, b"d")), (2, file(4, b"a", 1, b"b"))],
+            ..VersionEdit::default()
+        },
+    ]
+}
+
+fn write_manifest(edits: &[VersionEdit]) -> Vec<u8> {
+    let mut writer = LogWriter::new(Vec::new(), 0);
+    for edit in edits {
+        writer.add_record(&edit.encode()).unwrap();
+    }
+    writer.into_inner()
+}
+
+fn numbers(files: &[FileMetaData]) -> Vec<u64> {
+    files.iter().map(|file| file.number).collect()
+}
+
+#[test]
+fn test_replay_edits() {
+    let edits = edits();
+    let manifest = Manifest::read(&write_manifest(&edits)[..]).unwrap();
+    assert!(manifest.corruptions.is_empty());
+    assert_eq!(manifest.edits, edits);
+
+    let version = manifest.version;
+    assert_eq!(
+        version.comparator.as_deref(),
+        Some("leveldb.BytewiseComparator")
+    );
+    assert_eq!(version.log_number, 9);
+    assert_eq!(version.min_log_number(), 9);
+    assert_eq!(version.next_file_number, 10);
+    assert_eq!(version.last_sequence, 800);
+    assert_eq!(
+        version.compact_pointers[1],
+        Some(InternalKey::new(b"f", 5, ValueType::Value))
+    );
+    assert!(version.levels[0].is_empty());
+    assert_eq!(numbers(&version.levels[1]), vec![8, 5, 7]);
+    assert_eq!(numbers(&version.levels[2]), vec![4]);
+    assert_eq!(version.live_files(), vec![4, 5, 7, 8]);
+}
+
+#[test]
+fn test_apply_matches_apply_all() {
+    let edits = edits();
+    let mut one_by_one = Version::new();
+    for edit in &edits {
+        one_by_one.apply(edit);
+        for files in &one_by_one.levels {
+            assert!(files
+                .windows(2)
+                .all(|pair| pair[0].smallest.user_key <= pair[1].smallest.user_key));
+        }
+    }
+    let mut all = Version::new();
+    all.apply_all(&edits);
+    assert_eq!(one_by_one, all);
+
+    // a table added again replaces its earlier entry
+    let mut moved = VersionEdit::default();
+    moved.new_files.push((1, file(5, b"e", 5, b"f")));
+    all.apply(&moved);
+    assert_eq!(numbers(&all.levels[1]), vec![8, 5, 7]);
+    assert_eq!(all.levels[1][1].smallest.user_key, b"e");
+}
+
+#[test]
+fn test_damaged_edit_is_skipped() {
+    let edits = edits();
+    let mut manifest = write_manifest(&edits[..1]);
+    let damaged = manifest.len();
+    manifest.extend_from_slice(&write_manifest(&edits[1..2])[..]);
+    // break the checksum of the second edit
+    manifest[damaged] ^= 0xff;
+
+    let read = Manifest::read(&manifest[..]).unwrap();
+    assert_eq!(read.corruptions.len(), 1);
+    assert_eq!(read.trailing_corruptions, 1);
+    assert_eq!(read.edits, edits[..1].to_vec());
+    assert!(read.version.live_files().is_empty());
+}
+
+#[test]
+fn test_damage_before_intact_edits() {
+    let edits = edits();
+    // a large edit spanning two blocks, so that an edit after it survives
+    // the damaged first block being dropped
+    let large = VersionEdit {
+        comparator: Some("x".repeat(40_000)),
+        ..VersionEdit::default()
+    };
+    let mut manifest = write_manifest(&[edits[0].clone(), large, edits[1].clone()]);
+    manifest[0] ^= 0xff;
+
+    let read = Manifest::read(&manifest[..]).unwrap();
+    assert!(!read.corruptions.is_empty());
+    assert_eq!(read.trailing_corruptions, 0);
+    assert_eq!(read.edits, edits[1..2].to_vec());
+}
+
+#[test]
+fn test_append_to_current_manifest() {
+    let tmp = TempDir::new("manifest_append").unwrap();
+    let edits = edits();
+    fs::write(
+        tmp.path().join("MANIFEST-000002"),
+        write_manifest(&edits[..2]),
+    )
+    .unwrap();
+    fs::write(tmp.path().join("CURRENT"), "MANIFEST-000002\n").unwrap();
+
+    append_edit(&tmp.path().join("MANIFEST-000002"), &edits[2]).unwrap();
+    let manifest = Manifest::open_current(tmp.path()).unwrap();
+    assert_eq!(manifest.edits, edits);
+    assert_eq!(manifest.version.live_files(), vec![4, 5, 7, 8]);
+
+    fs::write(tmp.path().join("CURRENT"), "../MANIFEST-000002\n").unwrap();
+    assert!(Manifest::open_current(tmp.path()).is_err());
+}
@@ -0,0 +1,294 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::batch::Writebatch;
+use leveldb::database::Database;
+use leveldb::merge::{AppendOperator, U64AddOperator};
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use leveldb::watch::ChangeEvent;
+use std::path::Path;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+fn open_merging(path: &Path) -> Database {
+    let opts = Options {
+        merge_operator: Some(Arc::new(AppendOperator)),
+        ..Options::default()
+    };
+    open_with(path, opts)
+}
+
+#[test]
+fn test_get_folds_operands() {
+    let tmp = tmpdir("merge_get");
+    let database = open_merging(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    database.merge(&write_opts, b"key", b"a").unwrap();
+    assert_eq!(db_get(&database, b"key"), Some(b"a".to_vec()));
+    db_put_simple(&database, b"key", b"base-");
+    database.merge(&write_opts, b"key", b"b").unwrap();
+    database.merge(&write_opts, b"key", b"c").unwrap();
+    assert_eq!(db_get(&database, b"key"), Some(b"base-bc".to_vec()));
+    assert!(database
+        .contains_key(&ReadOptions::default(), b"key")
+        .unwrap());
+}
+
+#[test]
+fn test_put_and_delete_replace_operands() {
+    let tmp = tmpdir("merge_replace");
+    let database = open_merging(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    database.merge(&write_opts, b"key", b"a").unwrap();
+    db_put_simple(&database, b"key", b"new");
+    assert_eq!(db_get(&database, b"key"), Some(b"new".to_vec()));
+
+    database.merge(&write_opts, b"key", b"b").unwrap();
+    database.delete(&write_opts, b"key").unwrap();
+    assert_eq!(db_get(&database, b"key"), None);
+}
+
+#[test]
+fn test_snapshot_reads_fold_operands() {
+    let tmp = tmpdir("merge_snapshot");
+    let database = open_merging(tmp.path());
+    let read_opts = ReadOptions::default();
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"a", b"1");
+    database.merge(&write_opts, b"a", b"2").unwrap();
+    database.merge(&write_opts, b"b", b"3").unwrap();
+    let snapshot = database.snapshot();
+    database.merge(&write_opts, b"a", b"4").unwrap();
+
+    assert_eq!(
+        snapshot.get(&read_opts, b"a").unwrap(),
+        Some(b"12".to_vec())
+    );
+    assert_eq!(
+        snapshot.get_bytes(&read_opts, b"b").unwrap().map(Vec::from),
+        Some(b"3".to_vec())
+    );
+    assert_eq!(
+        database.get_bytes(&read_opts, b"a").unwrap().map(Vec::from),
+        Some(b"124".to_vec())
+    );
+    let entries: Vec<_> = snapshot.merged_iter(&read_opts).unwrap().collect();
+    assert_eq!(
+        entries,
+        vec![
+            (b"a".to_vec(), b"12".to_vec()),
+            (b"b".to_vec(), b"3".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn test_merged_iter() {
+    let tmp = tmpdir("merge_iter");
+    let database = open_merging(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"a", b"1");
+    database.merge(&write_opts, b"a", b"2").unwrap();
+    database.merge(&write_opts, b"b", b"3").unwrap();
+    db_put_simple(&database, b"c", b"4");
+
+    let entries: Vec<_> = database
+        .merged_iter(&ReadOptions::default())
+        .unwrap()
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (b"a".to_vec(), b"12".to_vec()),
+            (b"b".to_vec(), b"3".to_vec()),
+            (b"c".to_vec(), b"4".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn test_iter_hides_operands() {
+    let tmp = tmpdir("merge_iter_hidden");
+    let database = open_merging(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"a", b"1");
+    database.merge(&write_opts, b"a", b"2").unwrap();
+    database.merge(&write_opts, b"b", b"3").unwrap();
+
+    let mut iter = database.iter(&ReadOptions::default());
+    iter.seek_to_last();
+    assert_eq!(iter.key(), b"a");
+    iter.prev();
+    assert!(!iter.valid());
+    iter.seek_to_first();
+    assert_eq!((iter.key(), iter.value()), (&b"a"[..], &b"1"[..]));
+    iter.next();
+    assert!(!iter.valid());
+}
+
+#[test]
+fn test_fold_merges() {
+    let tmp = tmpdir("merge_fold");
+    let opts = Options {
+        merge_operator: Some(Arc::new(U64AddOperator)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let write_opts = WriteOptions::default();
+
+    for _ in 0..10 {
+        database
+            .merge(&write_opts, b"counter", &1u64.to_le_bytes())
+            .unwrap();
+    }
+    database
+        .merge(&write_opts, b"other", &5u64.to_le_bytes())
+        .unwrap();
+    assert_eq!(database.fold_merges(&write_opts).unwrap(), 2);
+    assert_eq!(database.fold_merges(&write_opts).unwrap(), 0);
+
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(10u64.to_le_bytes().to_vec())
+    );
+    assert_eq!(
+        database
+            .get_bytes(&ReadOptions::default(), b"counter")
+            .unwrap()
+            .map(Vec::from),
+        Some(10u64.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn test_background_folding() {
+    let tmp = tmpdir("merge_background");
+    let opts = Options {
+        merge_operator: Some(Arc::new(U64AddOperator)),
+        merge_fold_interval: Some(Duration::from_millis(10)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let events = database.watch(&b"counter"[..]);
+    let write_opts = WriteOptions::default();
+
+    for _ in 0..3 {
+        database
+            .merge(&write_opts, b"counter", &1u64.to_le_bytes())
+            .unwrap();
+    }
+    // the folding thread publishes folded values as puts, possibly
+    // before all operands were written
+    loop {
+        match events.recv_timeout(Duration::from_secs(10)).unwrap() {
+            ChangeEvent::Put { value, .. } if value == 3u64.to_le_bytes() => break,
+            ChangeEvent::Delete { .. } => panic!("unexpected delete"),
+            _ => {}
+        }
+    }
+    assert_eq!(database.fold_merges(&write_opts).unwrap(), 0);
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(3u64.to_le_bytes().to_vec())
+    );
+
+    // dropping the database stops the thread and closes the database
+    drop(events);
+    drop(database);
+    let opts = Options {
+        merge_operator: Some(Arc::new(U64AddOperator)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(3u64.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn test_merge_needs_operator() {
+    let tmp = tmpdir("merge_no_operator");
+    let database = open_database(tmp.path(), true);
+
+    assert!(database
+        .merge(&WriteOptions::default(), b"key", b"a")
+        .is_err());
+    assert!(database.merged_iter(&ReadOptions::default()).is_err());
+}
+
+#[test]
+fn test_merge_operator_conflicts_with_ttl() {
+    let tmp = tmpdir("merge_ttl");
+    let opts = Options {
+        create_if_missing: true,
+        merge_operator: Some(Arc::new(AppendOperator)),
+        ttl: true,
+        ..Options::default()
+    };
+    assert!(Database::open(tmp.path(), opts).is_err());
+}
+
+#[test]
+fn test_concurrent_put_and_merge() {
+    let tmp = tmpdir("merge_concurrent");
+    let database = Arc::new(open_merging(tmp.path()));
+    let events = database.watch_with_capacity(&b"key"[..], 1 << 16);
+    let write_opts = WriteOptions::default();
+
+    for round in 0..200 {
+        let barrier = Arc::new(Barrier::new(2));
+        let merger = {
+            let database = database.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                database.merge(&write_opts, b"key", b"m").unwrap();
+            })
+        };
+        // the operands of "key" are looked up first, the filler keys make
+        // a merge landing before the batch is written likely
+        let mut batch = Writebatch::new();
+        batch.put(b"key", b"p");
+        for i in 0..500u32 {
+            batch.put(&i.to_be_bytes(), b"filler");
+        }
+        barrier.wait();
+        database.write(&write_opts, &batch).unwrap();
+        merger.join().unwrap();
+
+        // events arrive in the order the writes were applied, so folding
+        // them has to give the stored value
+        let mut expected = Vec::new();
+        for event in events.try_iter() {
+            match event {
+                ChangeEvent::Put { value, .. } => expected = value,
+                ChangeEvent::Merge { operand, .. } => expected.extend_from_slice(&operand),
+                ChangeEvent::Delete { .. } => expected.clear(),
+            }
+        }
+        assert_eq!(db_get(&database, b"key"), Some(expected), "round {}", round);
+    }
+}
+
+#[test]
+fn test_compare_and_swap_sees_operands() {
+    let tmp = tmpdir("merge_cas");
+    let database = open_merging(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    database.merge(&write_opts, b"key", b"a").unwrap();
+    assert!(database
+        .compare_and_swap(&write_opts, b"key", Some(b"a"), Some(b"b"))
+        .unwrap());
+    database
+        .update(&write_opts, b"key", |current| {
+            current.map(|v| [v, b"c"].concat())
+        })
+        .unwrap();
+    assert_eq!(db_get(&database, b"key"), Some(b"bc".to_vec()));
+}
@@ -0,0 +1,151 @@
+use leveldb::format::log::LogWriter;
+use leveldb::format::manifest::{FileMetaData, VersionEdit};
+use leveldb::format::sst_writer::{SstOptions, SstWriter};
+use leveldb::format::{InternalKey, ValueType, BYTEWISE_COMPARATOR};
+use leveldb::pure::PureDatabase;
+use std::fs;
+use std::path::Path;
+use tempdir::TempDir;
+
+fn collect(database: &PureDatabase, start: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    database.iter_from(start).map(Result::unwrap).collect()
+}
+
+fn write_log_file(path: &Path, records: &[Vec<u8>]) {
+    let mut writer = LogWriter::new(Vec::new(), 0);
+    for record in records {
+        writer.add_record(record).unwrap();
+    }
+    fs::write(path, writer.into_inner()).unwrap();
+}
+
+/// build a database by hand: one table written at sequence 1 and a log
+/// with a later batch.
+fn build_database(path: &Path) {
+    let table = path.join("000005.ldb");
+    let mut writer = SstWriter::create(&table, 1, SstOptions::default()).unwrap();
+    for key in &[b"a", b"b", b"c"] {
+        writer.put(*key, b"table").unwrap();
+    }
+    writer.finish().unwrap();
+
+    let edit = VersionEdit {
+        comparator: Some(BYTEWISE_COMPARATOR.to_string()),
+        log_number: Some(6),
+        next_file_number: Some(7),
+        last_sequence: Some(3),
+        new_files: vec![(
+            0,
+            FileMetaData {
+                number: 5,
+                size: fs::metadata(&table).unwrap().len(),
+                smallest: InternalKey::new(b"a", 1, ValueType::Value),
+                largest: InternalKey::new(b"c", 1, ValueType::Value),
+            },
+        )],
+        ..VersionEdit::default()
+    };
+    write_log_file(&path.join("MANIFEST-000002"), &[edit.encode()]);
+    fs::write(path.join("CURRENT"), "MANIFEST-000002\n").unwrap();
+
+    // sequence 4: put b, delete c
+    let mut batch = Vec::new();
+    batch.extend_from_slice(&4u64.to_le_bytes());
+    batch.extend_from_slice(&2u32.to_le_bytes());
+    batch.extend_from_slice(&[ValueType::Value as u8, 1, b'b', 3]);
+    batch.extend_from_slice(b"log");
+    batch.extend_from_slice(&[ValueType::Deletion as u8, 1, b'c']);
+    write_log_file(&path.join("000006.log"), &[batch]);
+}
+
+#[test]
+fn test_read_hand_built_database() {
+    let tmp = TempDir::new("pure_built").unwrap();
+    build_database(tmp.path());
+
+    let database = PureDatabase::open(tmp.path()).unwrap();
+    assert_eq!(database.path(), tmp.path());
+    assert_eq!(database.last_sequence(), 5);
+    assert_eq!(database.get(b"a").unwrap(), Some(b"table".to_vec()));
+    assert_eq!(database.get(b"b").unwrap(), Some(b"log".to_vec()));
+    assert_eq!(database.get(b"c").unwrap(), None);
+    assert_eq!(database.get(b"d").unwrap(), None);
+    assert_eq!(
+        collect(&database, b""),
+        vec![
+            (b"a".to_vec(), b"table".to_vec()),
+            (b"b".to_vec(), b"log".to_vec()),
+        ]
+    );
+    assert_eq!(
+        collect(&database, b"b"),
+        vec![(b"b".to_vec(), b"log".to_vec())]
+    );
+    assert_eq!(
+        database.property("leveldb.num-files-at-level0").as_deref(),
+        Some("1")
+    );
+    assert!(database
+        .property("leveldb.sstables")
+        .unwrap()
+        .contains(" 5:"));
+}
+
+#[test]
+fn test_damaged_database() {
+    let tmp = TempDir::new("pure_damaged").unwrap();
+    build_database(tmp.path());
+
+    // a damaged log record is skipped
+    let log = tmp.path().join("000006.log");
+    let mut contents = fs::read(&log).unwrap();
+    contents[10] ^= 0xff;
+    fs::write(&log, contents).unwrap();
+    let database = PureDatabase::open(tmp.path()).unwrap();
+    assert_eq!(database.get(b"b").unwrap(), Some(b"table".to_vec()));
+    assert_eq!(database.get(b"c").unwrap(), Some(b"table".to_vec()));
+    drop(database);
+
+    // a missing table fails to open
+    fs::remove_file(tmp.path().join("000005.ldb")).unwrap();
+    assert!(PureDatabase::open(tmp.path()).is_err());
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn test_read_what_leveldb_wrote() {
+    use crate::utils::{db_put_simple, open_database};
+    use leveldb::options::{ReadOptions, WriteOptions};
+
+    let tmp = TempDir::new("pure_native").unwrap();
+    let database = open_database(tmp.path(), true);
+    for i in 0..2000u32 {
+        db_put_simple(&database, &i.to_be_bytes(), &[1; 100]);
+    }
+    database.compact_range(None, None);
+    for i in (0..2000u32).step_by(3) {
+        db_put_simple(&database, &i.to_be_bytes(), &[2; 50]);
+    }
+    database.compact_range(None, Some(&1000u32.to_be_bytes()));
+    for i in (0..2000u32).step_by(7) {
+        database
+            .delete(&WriteOptions::default(), &i.to_be_bytes())
+            .unwrap();
+    }
+
+    let mut expected = Vec::new();
+    let mut iter = database.iter(&ReadOptions::default());
+    iter.seek_to_first();
+    while iter.valid() {
+        expected.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next();
+    }
+    drop(iter);
+    drop(database);
+
+    let pure = PureDatabase::open(tmp.path()).unwrap();
+    assert_eq!(collect(&pure, b""), expected);
+    assert_eq!(pure.get(&3u32.to_be_bytes()).unwrap(), Some(vec![2; 50]));
+    assert_eq!(pure.get(&4u32.to_be_bytes()).unwrap(), Some(vec![1; 100]));
+    assert_eq!(pure.get(&7u32.to_be_bytes()).unwrap(), None);
+}
@@ -0,0 +1,80 @@
+use crate::utils::{db_get, db_put_simple, open_database, tmpdir};
+use leveldb::database::Database;
+use leveldb::options::{Options, ReadOptions};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn listing(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_read_only_copy_is_staged_in_the_temporary_directory() {
+    let tmp = tmpdir("read_only_staging");
+    let path = tmp.path().join("db");
+    let database = open_database(&path, true);
+    db_put_simple(&database, b"key", b"value");
+    let before = listing(&path);
+
+    let read_only = Database::open_read_only(&path, Options::default()).unwrap();
+    db_put_simple(&database, b"later", b"value");
+    let staging = read_only.staging_path().to_path_buf();
+    assert_eq!(staging.parent(), Some(env::temp_dir().as_path()));
+    let name = staging.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("db.read-only-"), "{}", name);
+    assert_eq!(read_only.path(), path.as_path());
+
+    let options = ReadOptions::default();
+    assert_eq!(
+        read_only.get(&options, b"key").unwrap(),
+        Some(b"value".to_vec())
+    );
+    // the copy is a view of the database when it was opened
+    assert_eq!(read_only.get(&options, b"later").unwrap(), None);
+    assert_eq!(db_get(&database, b"later"), Some(b"value".to_vec()));
+
+    drop(read_only);
+    assert!(!staging.exists());
+    drop(database);
+    let after = listing(&path);
+    assert!(before.iter().all(|name| after.contains(name)));
+    assert_eq!(listing(tmp.path()), vec!["db".to_string()]);
+}
+
+#[test]
+fn test_read_only_copy_in_a_staging_directory() {
+    let tmp = tmpdir("read_only_staging_in");
+    let path = tmp.path().join("db");
+    {
+        let database = open_database(&path, true);
+        db_put_simple(&database, b"key", b"value");
+    }
+    let staging = tmp.path().join("staging");
+    fs::create_dir(&staging).unwrap();
+    // not made by this process, so it is left alone
+    let other = staging.join("db.read-only-1-0");
+    fs::create_dir(&other).unwrap();
+
+    let first = Database::open_read_only_in(&path, &staging, Options::default()).unwrap();
+    let second = Database::open_read_only_in(&path, &staging, Options::default()).unwrap();
+    assert_eq!(first.staging_path().parent(), Some(staging.as_path()));
+    assert_ne!(first.staging_path(), second.staging_path());
+    assert_eq!(
+        second.get(&ReadOptions::default(), b"key").unwrap(),
+        Some(b"value".to_vec())
+    );
+
+    drop(first);
+    drop(second);
+    assert_eq!(listing(&staging), vec!["db.read-only-1-0".to_string()]);
+    assert!(
+        Database::open_read_only_in(&path, &tmp.path().join("missing"), Options::default())
+            .is_err()
+    );
+}
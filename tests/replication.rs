@@ -0,0 +1,153 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::change_log::ChangeRecord;
+use leveldb::database::Database;
+use leveldb::options::{Options, WriteOptions};
+use leveldb::replication::{send_changes, serve_replica, ReplicaApplier};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn open_primary(path: &Path) -> Database {
+    let opts = Options {
+        change_log: true,
+        ..Options::default()
+    };
+    open_with(path, opts)
+}
+
+fn wait_for(applier: &ReplicaApplier, seq: u64) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while applier.applied_seq().unwrap() < seq {
+        assert!(Instant::now() < deadline, "replica did not catch up");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_replica_follows_primary_over_tcp() {
+    let tmp = tmpdir("replication_tcp");
+    let primary = open_primary(&tmp.path().join("primary"));
+    let replica = open_database(&tmp.path().join("replica"), true);
+    let applier = ReplicaApplier::new(&replica);
+    let write_opts = WriteOptions::default();
+    for i in 0..50u32 {
+        db_put_simple(&primary, &i.to_be_bytes(), b"first");
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (connected, connection) = channel();
+    thread::scope(|scope| {
+        // first session: catch up, then follow new writes
+        let served = scope.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            connected.send(stream.try_clone().unwrap()).unwrap();
+            serve_replica(&primary, stream, Duration::from_millis(5))
+        });
+        let follower = scope.spawn(|| applier.follow(&write_opts, addr));
+        let connection = connection.recv().unwrap();
+
+        wait_for(&applier, 50);
+        primary.delete(&write_opts, &0u32.to_be_bytes()).unwrap();
+        db_put_simple(&primary, b"live", b"value");
+        wait_for(&applier, 52);
+
+        connection.shutdown(Shutdown::Both).unwrap();
+        assert_eq!(follower.join().unwrap().unwrap(), 52);
+        served.join().unwrap().unwrap();
+    });
+    assert_eq!(db_get(&replica, &0u32.to_be_bytes()), None);
+    assert_eq!(
+        db_get(&replica, &1u32.to_be_bytes()),
+        Some(b"first".to_vec())
+    );
+    assert_eq!(db_get(&replica, b"live"), Some(b"value".to_vec()));
+
+    for i in 0..10u32 {
+        db_put_simple(&primary, &i.to_be_bytes(), b"second");
+    }
+    thread::scope(|scope| {
+        // second session: the replica resumes after what it applied
+        let server = scope.spawn(|| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut applied = [0; 8];
+            stream.read_exact(&mut applied).unwrap();
+            let applied = u64::from_be_bytes(applied);
+            send_changes(&primary, &mut stream, applied).unwrap();
+            stream.shutdown(Shutdown::Both).unwrap();
+            applied
+        });
+        let follower = scope.spawn(|| applier.follow(&write_opts, addr));
+        assert_eq!(server.join().unwrap(), 52);
+        assert_eq!(follower.join().unwrap().unwrap(), 10);
+    });
+    assert_eq!(applier.applied_seq().unwrap(), 62);
+    assert_eq!(
+        db_get(&replica, &0u32.to_be_bytes()),
+        Some(b"second".to_vec())
+    );
+    assert_eq!(
+        db_get(&replica, &10u32.to_be_bytes()),
+        Some(b"first".to_vec())
+    );
+}
+
+#[test]
+fn test_apply_skips_old_records_and_rejects_gaps() {
+    let tmp = tmpdir("replication_gap");
+    let primary = open_primary(&tmp.path().join("primary"));
+    let replica = open_database(&tmp.path().join("replica"), true);
+    let applier = ReplicaApplier::new(&replica);
+    let write_opts = WriteOptions::default();
+    for i in 0..5u8 {
+        db_put_simple(&primary, &[i], b"value");
+    }
+
+    let mut stream = Vec::new();
+    assert_eq!(send_changes(&primary, &mut stream, 0).unwrap(), 5);
+    assert_eq!(applier.apply_from(&write_opts, &stream[..]).unwrap(), 5);
+    // replaying the same stream applies nothing
+    assert_eq!(applier.apply_from(&write_opts, &stream[..]).unwrap(), 0);
+
+    let (sender, receiver) = channel();
+    for record in primary.changes_since(3).unwrap() {
+        sender.send(record.unwrap()).unwrap();
+    }
+    drop(sender);
+    assert_eq!(
+        applier.apply_from_channel(&write_opts, &receiver).unwrap(),
+        0
+    );
+
+    let gap = ChangeRecord {
+        seq: 7,
+        mutations: Vec::new(),
+    };
+    assert!(applier.apply(&write_opts, &gap).is_err());
+    assert_eq!(applier.applied_seq().unwrap(), 5);
+}
+
+#[test]
+fn test_serving_stops_when_follower_disconnects() {
+    let tmp = tmpdir("replication_disconnect");
+    let primary = open_primary(tmp.path());
+    db_put_simple(&primary, b"key", b"value");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::scope(|scope| {
+        let served = scope.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            serve_replica(&primary, stream, Duration::from_millis(5))
+        });
+        let mut follower = TcpStream::connect(addr).unwrap();
+        follower.write_all(&0u64.to_be_bytes()).unwrap();
+        let record = ChangeRecord::read_from(&mut follower).unwrap().unwrap();
+        assert_eq!(record.seq, 1);
+        drop(follower);
+        served.join().unwrap().unwrap();
+    });
+}
@@ -0,0 +1,139 @@
+use crate::utils::{
+    db_get, db_put_simple, files_with_extension, garble, open_database, open_with, tmpdir,
+};
+use leveldb::management::salvage;
+use leveldb::options::{Options, WriteOptions};
+use std::fs;
+
+#[test]
+fn test_salvage_intact_database() {
+    let tmp = tmpdir("salvage_intact");
+    let src = tmp.path().join("src");
+    {
+        let database = open_database(&src, true);
+        for i in 0..1000u32 {
+            db_put_simple(&database, &i.to_be_bytes(), b"old");
+        }
+        database.compact_range(None, None);
+        db_put_simple(&database, &1u32.to_be_bytes(), b"new");
+        database
+            .delete(&WriteOptions::default(), &2u32.to_be_bytes())
+            .unwrap();
+    }
+    // salvage doesn't need the MANIFEST
+    fs::remove_file(src.join("CURRENT")).unwrap();
+
+    let dst = open_database(&tmp.path().join("dst"), true);
+    let report = salvage(&src, &dst).unwrap();
+    assert!(report.corruptions.is_empty(), "{:?}", report);
+    assert!(report.tables >= 1);
+    assert!(report.logs >= 1);
+    assert_eq!(report.entries, 1002);
+    assert_eq!(report.keys, 999);
+    assert_eq!(db_get(&dst, &0u32.to_be_bytes()), Some(b"old".to_vec()));
+    assert_eq!(db_get(&dst, &1u32.to_be_bytes()), Some(b"new".to_vec()));
+    assert_eq!(db_get(&dst, &2u32.to_be_bytes()), None);
+}
+
+#[test]
+fn test_salvage_skips_damaged_table_blocks() {
+    let tmp = tmpdir("salvage_table");
+    let src = tmp.path().join("src");
+    {
+        let database = open_database(&src, true);
+        for i in 0..1000u32 {
+            db_put_simple(&database, &i.to_be_bytes(), &[7; 100]);
+        }
+        database.compact_range(None, None);
+    }
+    let table = files_with_extension(&src, "ldb").remove(0);
+    garble(&table, 100);
+    let damaged = fs::read(&table).unwrap();
+
+    let dst = open_database(&tmp.path().join("dst"), true);
+    let report = salvage(&src, &dst).unwrap();
+    assert_eq!(report.corruptions.len(), 1);
+    assert!(report.corruptions[0].keys.is_some());
+    assert!(report.keys > 0 && report.keys < 1000);
+    // the damage is in the first block, the last keys survive
+    assert_eq!(db_get(&dst, &0u32.to_be_bytes()), None);
+    assert_eq!(db_get(&dst, &999u32.to_be_bytes()), Some(vec![7; 100]));
+    assert_eq!(fs::read(&table).unwrap(), damaged);
+}
+
+#[test]
+fn test_salvage_falls_back_to_older_versions() {
+    let tmp = tmpdir("salvage_log");
+    let src = tmp.path().join("src");
+    {
+        let database = open_database(&src, true);
+        db_put_simple(&database, b"key", b"old");
+        database.compact_range(None, None);
+        db_put_simple(&database, b"key", b"new");
+    }
+    for log in files_with_extension(&src, "log") {
+        if fs::metadata(&log).unwrap().len() > 0 {
+            garble(&log, 10);
+        }
+    }
+
+    let dst = open_database(&tmp.path().join("dst"), true);
+    let report = salvage(&src, &dst).unwrap();
+    assert_eq!(report.corruptions.len(), 1);
+    assert_eq!(report.corruptions[0].keys, None);
+    assert_eq!(report.keys, 1);
+    assert_eq!(db_get(&dst, b"key"), Some(b"old".to_vec()));
+}
+
+#[test]
+fn test_salvage_checks_ttl_mode() {
+    let tmp = tmpdir("salvage_ttl");
+    let src = tmp.path().join("src");
+    let ttl = || Options {
+        ttl: true,
+        ..Options::default()
+    };
+    {
+        let database = open_with(&src, ttl());
+        db_put_simple(&database, b"key", b"value");
+    }
+
+    let plain = open_database(&tmp.path().join("plain"), true);
+    assert!(salvage(&src, &plain).is_err());
+    assert_eq!(db_get(&plain, b"key"), None);
+
+    let dst = open_with(&tmp.path().join("dst"), ttl());
+    salvage(&src, &dst).unwrap();
+    assert_eq!(db_get(&dst, b"key"), Some(b"value".to_vec()));
+
+    db_put_simple(&plain, b"key", b"value");
+    drop(plain);
+    let dst = open_with(&tmp.path().join("ttl"), ttl());
+    assert!(salvage(&tmp.path().join("plain"), &dst).is_err());
+    assert_eq!(db_get(&dst, b"key"), None);
+}
+
+#[test]
+fn test_salvage_records_changes_in_destination() {
+    let tmp = tmpdir("salvage_change_log");
+    let src = tmp.path().join("src");
+    let change_log = || Options {
+        change_log: true,
+        ..Options::default()
+    };
+    {
+        let database = open_with(&src, change_log());
+        for key in &[b"a", b"b", b"c"] {
+            db_put_simple(&database, *key, b"value");
+        }
+    }
+
+    let dst = open_with(&tmp.path().join("dst"), change_log());
+    let report = salvage(&src, &dst).unwrap();
+    assert_eq!(report.keys, 3);
+    // one record for the salvaged keys, none copied from src
+    assert_eq!(dst.last_change_seq().unwrap(), 1);
+    let records: Vec<_> = dst.changes_since(0).unwrap().collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].as_ref().unwrap().mutations.len(), 3);
+}
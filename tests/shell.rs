@@ -0,0 +1,167 @@
+use crate::utils::{db_put_simple, open_database, open_with, tmpdir};
+use leveldb::database::Database;
+use leveldb::merge::AppendOperator;
+use leveldb::options::{Options, WriteOptions};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// the shell's commands find the formats as `super::format`
+use crate::format;
+#[path = "../src/bin/leveldb-shell/session.rs"]
+mod session;
+
+use self::session::{split_words, Shell};
+
+/// Output shared between a shell and the test reading it.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Session<'a> {
+    shell: Shell<'a, Output>,
+    output: Output,
+}
+
+impl<'a> Session<'a> {
+    fn new(database: &'a Database, merged: bool) -> Session<'a> {
+        let output = Output::default();
+        Session {
+            shell: Shell::new(database, WriteOptions::default(), merged, output.clone()),
+            output,
+        }
+    }
+
+    /// run a command line, returning what it printed.
+    fn run(&mut self, line: &str) -> String {
+        self.output.0.borrow_mut().clear();
+        let words = split_words(line).unwrap();
+        assert!(self.shell.run(&words).unwrap(), "{}", line);
+        String::from_utf8(self.output.0.borrow().clone()).unwrap()
+    }
+}
+
+#[test]
+fn test_split_words() {
+    assert_eq!(
+        split_words(r#" put  "a key" b\x00 "q\"uote" "#).unwrap(),
+        vec!["put", "a key", "b\\x00", "q\"uote"]
+    );
+    assert!(split_words("get \"open").is_err());
+    assert!(split_words("get \"\\").is_err());
+}
+
+#[test]
+fn test_cursor_follows_writes() {
+    let tmp = tmpdir("shell_writes");
+    let database = open_database(tmp.path(), true);
+    for key in &["a", "b", "c"] {
+        db_put_simple(&database, key.as_bytes(), b"1");
+    }
+    let mut session = Session::new(&database, false);
+
+    assert_eq!(session.run("first"), "a\t1\n");
+    assert_eq!(session.run("next"), "b\t1\n");
+    session.run("put bb 2");
+    assert_eq!(session.run("next"), "bb\t2\n");
+    session.run("delete bb");
+    assert_eq!(session.run("show"), "bb\t(deleted)\n");
+    assert_eq!(session.run("next"), "c\t1\n");
+    session.run("put ab 3");
+    assert_eq!(session.run("prev 2"), "ab\t3\n");
+    assert_eq!(session.run("prev"), "a\t1\n");
+}
+
+#[test]
+fn test_cursor_runs_off_both_ends() {
+    let tmp = tmpdir("shell_ends");
+    let database = open_database(tmp.path(), true);
+    assert_eq!(Session::new(&database, false).run("first"), "(empty)\n");
+    for key in &["a", "b", "c"] {
+        db_put_simple(&database, key.as_bytes(), b"1");
+    }
+    let mut session = Session::new(&database, false);
+
+    assert_eq!(session.run("first"), "a\t1\n");
+    assert_eq!(session.run("prev"), "(end)\n");
+    assert_eq!(session.run("next"), "b\t1\n");
+    assert_eq!(session.run("next 5"), "(end)\n");
+    assert_eq!(session.run("show"), "c\t1\n");
+    assert_eq!(session.run("prev"), "b\t1\n");
+    assert_eq!(session.run("seek d"), "(end)\n");
+    assert_eq!(session.run("scan"), "b\t1\nc\t1\n(end)\n");
+}
+
+#[test]
+fn test_cursor_skips_reserved_keys() {
+    let tmp = tmpdir("shell_reserved");
+    let options = Options {
+        merge_operator: Some(Arc::new(AppendOperator)),
+        change_log: true,
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), options);
+    let write_opts = WriteOptions::default();
+    db_put_simple(&database, b"a", b"1");
+    db_put_simple(&database, b"b", b"2");
+    database.merge(&write_opts, b"b", b"3").unwrap();
+    database.merge(&write_opts, b"c", b"4").unwrap();
+    let mut session = Session::new(&database, true);
+
+    assert_eq!(session.run("last"), "b\t23\n");
+    assert_eq!(session.run("next"), "(end)\n");
+    assert_eq!(session.run("prev"), "a\t1\n");
+    assert_eq!(session.run("scan"), "a\t1\nb\t23\n(end)\n");
+    assert_eq!(session.run("get c"), "4\n");
+}
+
+#[test]
+fn test_get_strips_ttl_headers() {
+    let tmp = tmpdir("shell_ttl");
+    let options = Options {
+        ttl: true,
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), options);
+    let mut session = Session::new(&database, false);
+
+    session.run("put key value");
+    assert_eq!(session.run("get key"), "value\n");
+    assert_eq!(session.run("first"), "key\tvalue\n");
+}
+
+#[test]
+fn test_batches() {
+    let tmp = tmpdir("shell_batch");
+    let database = open_database(tmp.path(), true);
+    let mut session = Session::new(&database, false);
+
+    assert_eq!(session.shell.prompt(), "leveldb> ");
+    session.run("begin");
+    session.run("put a 1");
+    session.run("put b 2");
+    assert!(session.shell.in_batch());
+    assert_eq!(session.shell.prompt(), "leveldb (batch: 2)> ");
+    assert_eq!(session.run("get a"), "(not found)\n");
+    assert_eq!(session.run("commit"), "committed 2 operations\n");
+    assert_eq!(session.run("get b"), "2\n");
+
+    session.run("begin");
+    session.run("delete a");
+    assert_eq!(session.run("rollback"), "discarded 1 operations\n");
+    assert!(!session.shell.in_batch());
+    session.run("format value hex");
+    assert_eq!(session.run("get a"), "31\n");
+    assert!(!session.shell.run(&["quit".to_string()]).unwrap());
+}
@@ -0,0 +1,119 @@
+use leveldb::format::sst_writer::{SstOptions, SstWriter};
+use leveldb::format::table::{Table, FOOTER_SIZE};
+use leveldb::format::{FormatError, InternalKey, ValueType, MAX_SEQUENCE};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+
+fn write_table(dir: &Path) -> PathBuf {
+    let path = dir.join("000005.ldb");
+    let options = SstOptions {
+        block_size: 256,
+        bloom_filter_bits_per_key: Some(10),
+        ..SstOptions::default()
+    };
+    let mut writer = SstWriter::create(&path, 7, options).unwrap();
+    for i in 0..200u32 {
+        writer.put(&i.to_be_bytes(), b"value").unwrap();
+    }
+    writer.delete(&200u32.to_be_bytes()).unwrap();
+    writer.finish().unwrap();
+    path
+}
+
+fn corruption_offset(result: Result<Table, FormatError>) -> u64 {
+    match result {
+        Err(FormatError::Corruption { offset, .. }) => offset,
+        Err(e) => panic!("expected a corruption, got {}", e),
+        Ok(_) => panic!("expected a corruption, the table opened"),
+    }
+}
+
+fn put_varint64(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[test]
+fn test_read_table() {
+    let tmp = TempDir::new("table_read").unwrap();
+    let table = Table::open(&write_table(tmp.path())).unwrap();
+    assert!(table.has_filter());
+    assert!(table.may_contain(&5u32.to_be_bytes()).unwrap());
+
+    let entries: Vec<(InternalKey, Vec<u8>)> = table.iter().map(Result::unwrap).collect();
+    assert_eq!(entries.len(), 201);
+    assert_eq!(
+        entries[0],
+        (
+            InternalKey::new(&0u32.to_be_bytes(), 7, ValueType::Value),
+            b"value".to_vec()
+        )
+    );
+    assert_eq!(entries[200].0.value_type, ValueType::Deletion);
+
+    let mut iter = table.iter();
+    iter.seek(&InternalKey::new(
+        &150u32.to_be_bytes(),
+        MAX_SEQUENCE,
+        ValueType::Value,
+    ))
+    .unwrap();
+    let (key, _) = iter.next().unwrap().unwrap();
+    assert_eq!(key.user_key, 150u32.to_be_bytes());
+}
+
+#[test]
+fn test_truncated_table() {
+    let tmp = TempDir::new("table_truncated").unwrap();
+    let path = write_table(tmp.path());
+    let contents = fs::read(&path).unwrap();
+
+    fs::write(&path, &contents[..contents.len() - 10]).unwrap();
+    corruption_offset(Table::open(&path));
+    fs::write(&path, &contents[..FOOTER_SIZE - 1]).unwrap();
+    assert_eq!(corruption_offset(Table::open(&path)), 0);
+}
+
+#[test]
+fn test_garbled_footer() {
+    let tmp = TempDir::new("table_garbled_footer").unwrap();
+    let path = write_table(tmp.path());
+    let mut contents = fs::read(&path).unwrap();
+    let footer = contents.len() - FOOTER_SIZE;
+
+    // block handles whose end overflows, or lies past the end of the file
+    for &(offset, size) in &[(u64::MAX - 2, 10), (0, u64::MAX), (footer as u64, 100)] {
+        let mut handles = Vec::new();
+        put_varint64(&mut handles, 0);
+        put_varint64(&mut handles, 0);
+        put_varint64(&mut handles, offset);
+        put_varint64(&mut handles, size);
+        handles.resize(FOOTER_SIZE - 8, 0);
+        contents[footer..footer + FOOTER_SIZE - 8].copy_from_slice(&handles);
+        fs::write(&path, &contents).unwrap();
+        assert_eq!(corruption_offset(Table::open(&path)), offset);
+    }
+}
+
+#[test]
+fn test_garbled_data_block() {
+    let tmp = TempDir::new("table_garbled_block").unwrap();
+    let path = write_table(tmp.path());
+    let mut contents = fs::read(&path).unwrap();
+    contents[10] ^= 0xff;
+    fs::write(&path, &contents).unwrap();
+
+    // the index is intact, so the damage shows when reading the block
+    let table = Table::open(&path).unwrap();
+    let mut iter = table.iter();
+    match iter.next() {
+        Some(Err(FormatError::Corruption { offset, .. })) => assert_eq!(offset, 0),
+        other => panic!("expected a corruption, got {:?}", other.map(|r| r.is_ok())),
+    }
+    // the following blocks are still readable
+    assert!(iter.any(|entry| entry.is_ok()));
+}
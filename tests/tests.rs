@@ -0,0 +1,56 @@
+extern crate leveldb;
+extern crate tempdir;
+
+#[cfg(feature = "native")]
+mod utils;
+
+#[cfg(feature = "native")]
+mod get;
+#[cfg(feature = "native")]
+mod atomic;
+#[cfg(feature = "native")]
+mod locking;
+#[cfg(feature = "native")]
+mod transaction;
+#[cfg(feature = "native")]
+mod merge;
+#[cfg(feature = "native")]
+mod ttl;
+#[cfg(feature = "native")]
+mod index;
+#[cfg(feature = "async")]
+mod async_database;
+#[cfg(feature = "native")]
+mod group_commit;
+#[cfg(feature = "native")]
+mod watch;
+#[cfg(feature = "native")]
+mod change_log;
+#[cfg(feature = "native")]
+mod replication;
+#[cfg(feature = "native")]
+mod checkpoint;
+#[cfg(feature = "native")]
+mod backup;
+#[cfg(feature = "native")]
+mod export;
+mod log;
+mod table;
+mod manifest;
+#[cfg(feature = "native")]
+mod verify;
+#[cfg(feature = "native")]
+mod salvage;
+#[cfg(feature = "native")]
+mod read_only;
+#[cfg(feature = "pure")]
+mod pure;
+#[cfg(feature = "native")]
+mod ingest;
+#[cfg(feature = "cli")]
+#[path = "../src/bin/leveldb-cli/format.rs"]
+mod format;
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "cli")]
+mod shell;
@@ -0,0 +1,131 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::merge::U64AddOperator;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use leveldb::transaction::TransactionError;
+use std::sync::Arc;
+
+#[test]
+fn test_commit_applies_writes() {
+    let tmp = tmpdir("txn_commit");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"gone", b"value");
+
+    let mut txn = database.transaction();
+    txn.put(b"key", b"value");
+    txn.delete(b"gone");
+    txn.commit(&WriteOptions::default()).unwrap();
+
+    assert_eq!(db_get(&database, b"key"), Some(b"value".to_vec()));
+    assert_eq!(db_get(&database, b"gone"), None);
+}
+
+#[test]
+fn test_reads_see_snapshot_and_own_writes() {
+    let tmp = tmpdir("txn_reads");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"key", b"old");
+    let read_opts = ReadOptions::default();
+
+    let mut txn = database.transaction();
+    db_put_simple(&database, b"key", b"outside");
+    assert_eq!(txn.get(&read_opts, b"key").unwrap(), Some(b"old".to_vec()));
+
+    txn.put(b"other", b"mine");
+    assert_eq!(
+        txn.get(&read_opts, b"other").unwrap(),
+        Some(b"mine".to_vec())
+    );
+    txn.delete(b"key");
+    assert!(!txn.contains_key(&read_opts, b"key").unwrap());
+}
+
+#[test]
+fn test_conflicting_write_aborts_commit() {
+    let tmp = tmpdir("txn_conflict");
+    let database = open_database(tmp.path(), true);
+    db_put_simple(&database, b"key", b"old");
+
+    let mut txn = database.transaction();
+    txn.get(&ReadOptions::default(), b"key").unwrap();
+    txn.put(b"result", b"derived from old");
+    db_put_simple(&database, b"key", b"new");
+
+    match txn.commit(&WriteOptions::default()) {
+        Err(TransactionError::Conflict) => {}
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert_eq!(db_get(&database, b"result"), None);
+}
+
+#[test]
+fn test_unread_writes_do_not_conflict() {
+    let tmp = tmpdir("txn_blind_write");
+    let database = open_database(tmp.path(), true);
+
+    let mut txn = database.transaction();
+    txn.put(b"key", b"from transaction");
+    db_put_simple(&database, b"key", b"outside");
+    txn.commit(&WriteOptions::default()).unwrap();
+
+    assert_eq!(
+        db_get(&database, b"key"),
+        Some(b"from transaction".to_vec())
+    );
+}
+
+#[test]
+fn test_dropped_transaction_writes_nothing() {
+    let tmp = tmpdir("txn_drop");
+    let database = open_database(tmp.path(), true);
+
+    let mut txn = database.transaction();
+    txn.put(b"key", b"value");
+    drop(txn);
+
+    assert_eq!(db_get(&database, b"key"), None);
+}
+
+#[test]
+fn test_reads_fold_merge_operands() {
+    let tmp = tmpdir("txn_merge");
+    let opts = Options {
+        merge_operator: Some(Arc::new(U64AddOperator)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let read_opts = ReadOptions::default();
+    let write_opts = WriteOptions::default();
+    for _ in 0..3 {
+        database
+            .merge(&write_opts, b"counter", &1u64.to_le_bytes())
+            .unwrap();
+    }
+
+    // read-modify-write of a merged value
+    let mut txn = database.transaction();
+    let value = txn.get(&read_opts, b"counter").unwrap().unwrap();
+    assert_eq!(value, 3u64.to_le_bytes().to_vec());
+    assert!(txn.contains_key(&read_opts, b"counter").unwrap());
+    txn.put(b"counter", &10u64.to_le_bytes());
+    txn.commit(&write_opts).unwrap();
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(10u64.to_le_bytes().to_vec())
+    );
+
+    // a merge landing after the read is a conflict
+    let mut txn = database.transaction();
+    txn.get(&read_opts, b"counter").unwrap();
+    database
+        .merge(&write_opts, b"counter", &1u64.to_le_bytes())
+        .unwrap();
+    txn.put(b"counter", &0u64.to_le_bytes());
+    match txn.commit(&write_opts) {
+        Err(TransactionError::Conflict) => {}
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert_eq!(
+        db_get(&database, b"counter"),
+        Some(11u64.to_le_bytes().to_vec())
+    );
+}
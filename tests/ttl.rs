@@ -0,0 +1,131 @@
+use crate::utils::{db_get, db_put_simple, open_database, open_with, tmpdir};
+use leveldb::database::Database;
+use leveldb::management::checkpoint;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+fn open_ttl(path: &Path) -> Database {
+    let opts = Options {
+        ttl: true,
+        ..Options::default()
+    };
+    open_with(path, opts)
+}
+
+fn user_entries(database: &Database) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = Vec::new();
+    let mut iter = database.iter(&ReadOptions::default());
+    iter.seek_to_first();
+    while iter.valid() {
+        entries.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next();
+    }
+    entries
+}
+
+#[test]
+fn test_values_expire() {
+    let tmp = tmpdir("ttl_expire");
+    let database = open_ttl(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"forever", b"value");
+    database
+        .put_with_ttl(&write_opts, b"short", b"value", Duration::from_millis(50))
+        .unwrap();
+    database
+        .put_with_ttl(&write_opts, b"long", b"value", Duration::from_secs(3600))
+        .unwrap();
+    assert_eq!(db_get(&database, b"short"), Some(b"value".to_vec()));
+
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(db_get(&database, b"short"), None);
+    assert_eq!(db_get(&database, b"long"), Some(b"value".to_vec()));
+    assert_eq!(db_get(&database, b"forever"), Some(b"value".to_vec()));
+    assert_eq!(
+        user_entries(&database),
+        vec![
+            (b"forever".to_vec(), b"value".to_vec()),
+            (b"long".to_vec(), b"value".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn test_purge_expired() {
+    let tmp = tmpdir("ttl_purge");
+    let database = open_ttl(tmp.path());
+    let write_opts = WriteOptions::default();
+
+    for i in 0..1000u32 {
+        database
+            .put_with_ttl(
+                &write_opts,
+                &i.to_be_bytes(),
+                b"v",
+                Duration::from_millis(1),
+            )
+            .unwrap();
+    }
+    database
+        .put_with_ttl(&write_opts, b"kept", b"v", Duration::from_secs(3600))
+        .unwrap();
+    // overwritten values are not purged with their old expiry
+    database
+        .put_with_ttl(&write_opts, b"renewed", b"v", Duration::from_millis(1))
+        .unwrap();
+    db_put_simple(&database, b"renewed", b"forever");
+    thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(database.purge_expired(&write_opts).unwrap(), 1000);
+    assert_eq!(database.purge_expired(&write_opts).unwrap(), 0);
+    assert_eq!(db_get(&database, b"kept"), Some(b"v".to_vec()));
+    assert_eq!(db_get(&database, b"renewed"), Some(b"forever".to_vec()));
+}
+
+#[test]
+fn test_put_with_ttl_needs_ttl() {
+    let tmp = tmpdir("ttl_disabled");
+    let database = open_database(tmp.path(), true);
+
+    assert!(database
+        .put_with_ttl(
+            &WriteOptions::default(),
+            b"key",
+            b"v",
+            Duration::from_secs(1)
+        )
+        .is_err());
+}
+
+#[test]
+fn test_ttl_mode_must_match() {
+    let tmp = tmpdir("ttl_mode");
+    let ttl_path = tmp.path().join("ttl");
+    let plain_path = tmp.path().join("plain");
+    db_put_simple(&open_ttl(&ttl_path), b"key", b"value");
+    db_put_simple(&open_database(&plain_path, true), b"key", b"value");
+
+    assert!(Database::open(&ttl_path, Options::default()).is_err());
+    let opts = Options {
+        ttl: true,
+        ..Options::default()
+    };
+    assert!(Database::open(&plain_path, opts).is_err());
+
+    let database = open_ttl(&ttl_path);
+    assert_eq!(db_get(&database, b"key"), Some(b"value".to_vec()));
+}
+
+#[test]
+fn test_checkpoint_keeps_ttl_mode() {
+    let tmp = tmpdir("ttl_checkpoint");
+    let database = open_ttl(&tmp.path().join("db"));
+    db_put_simple(&database, b"key", b"value");
+
+    let copy = tmp.path().join("copy");
+    checkpoint(&database, &copy).unwrap();
+    assert_eq!(db_get(&open_ttl(&copy), b"key"), Some(b"value".to_vec()));
+}
@@ -0,0 +1,60 @@
+#![allow(dead_code)]
+
+use leveldb::database::Database;
+use leveldb::options::{Options, ReadOptions, WriteOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+
+pub fn open_database(path: &Path, create_if_missing: bool) -> Database {
+    let opts = Options {
+        create_if_missing,
+        ..Options::default()
+    };
+    match Database::open(path, opts) {
+        Ok(db) => db,
+        Err(e) => panic!("failed to open database: {:?}", e),
+    }
+}
+
+/// open a database with `opts`, creating it if missing.
+pub fn open_with(path: &Path, mut opts: Options) -> Database {
+    opts.create_if_missing = true;
+    match Database::open(path, opts) {
+        Ok(db) => db,
+        Err(e) => panic!("failed to open database: {:?}", e),
+    }
+}
+
+pub fn tmpdir(name: &str) -> TempDir {
+    TempDir::new(name).unwrap()
+}
+
+pub fn db_put_simple(database: &Database, key: &[u8], val: &[u8]) {
+    match database.put(&WriteOptions::default(), key, val) {
+        Ok(_) => (),
+        Err(e) => panic!("failed to write to database: {:?}", e),
+    }
+}
+
+pub fn db_get(database: &Database, key: &[u8]) -> Option<Vec<u8>> {
+    database.get(&ReadOptions::default(), key).unwrap()
+}
+
+/// the files in `path` with `extension`, sorted.
+pub fn files_with_extension(path: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some(extension.as_ref()))
+        .collect();
+    files.sort();
+    files
+}
+
+/// flip the bits of the byte at `offset` in a file.
+pub fn garble(path: &Path, offset: usize) {
+    let mut contents = fs::read(path).unwrap();
+    contents[offset] ^= 0xff;
+    fs::write(path, contents).unwrap();
+}
@@ -0,0 +1,146 @@
+use crate::utils::{db_put_simple, files_with_extension, garble, open_database, tmpdir};
+use leveldb::management::verify;
+use std::fs::{self, OpenOptions};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+/// create a closed database with 1000 entries in tables and one write
+/// still in the log.
+fn create_database(path: &Path) {
+    let database = open_database(path, true);
+    for i in 0..1000u32 {
+        db_put_simple(&database, &i.to_be_bytes(), &[7; 100]);
+    }
+    database.compact_range(None, None);
+    db_put_simple(&database, b"unflushed", b"value");
+}
+
+#[test]
+fn test_verify_intact_database() {
+    let tmp = tmpdir("verify_intact");
+    create_database(tmp.path());
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+    assert!(report.tables >= 1);
+    assert_eq!(report.entries, 1000);
+    assert_eq!(report.log_records, 1);
+    assert!(report.orphaned_tables.is_empty());
+}
+
+#[test]
+fn test_verify_damaged_table() {
+    let tmp = tmpdir("verify_table");
+    create_database(tmp.path());
+    let table = files_with_extension(tmp.path(), "ldb").remove(0);
+    garble(&table, 100);
+    let damaged = fs::read(&table).unwrap();
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(!report.is_ok());
+    assert!(report.entries < 1000);
+    assert_eq!(report.corruptions.len(), 1);
+    let corruption = &report.corruptions[0];
+    assert_eq!(
+        corruption.file,
+        table.file_name().unwrap().to_str().unwrap()
+    );
+    // the damage is in the first block, so it starts at the table's
+    // smallest key
+    let (start, _) = corruption.keys.clone().unwrap();
+    assert_eq!(start, Bound::Included(0u32.to_be_bytes().to_vec()));
+    // verifying doesn't touch the files
+    assert_eq!(fs::read(&table).unwrap(), damaged);
+}
+
+#[test]
+fn test_verify_missing_and_orphaned_tables() {
+    let tmp = tmpdir("verify_missing");
+    create_database(tmp.path());
+    let table = files_with_extension(tmp.path(), "ldb").remove(0);
+    fs::rename(&table, tmp.path().join("999999.ldb")).unwrap();
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.missing_tables.len(), 1);
+    assert_eq!(report.orphaned_tables, vec![999999]);
+    assert!(report.corruptions.is_empty());
+}
+
+/// the logs of `path` that hold writes.
+fn written_logs(path: &Path) -> Vec<PathBuf> {
+    let mut logs = files_with_extension(path, "log");
+    logs.retain(|log| fs::metadata(log).unwrap().len() > 0);
+    logs
+}
+
+#[test]
+fn test_verify_damaged_log() {
+    let tmp = tmpdir("verify_log");
+    {
+        let database = open_database(tmp.path(), true);
+        // spans the first block of the log, so that the write after it
+        // starts in the second
+        db_put_simple(&database, b"large", &[7; 40000]);
+        db_put_simple(&database, b"unflushed", b"value");
+    }
+    for log in written_logs(tmp.path()) {
+        garble(&log, 10);
+    }
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.log_records, 1);
+    assert!(!report.corruptions.is_empty());
+    assert!(report.corruptions.iter().all(|c| c.keys.is_none()));
+    assert!(report.warnings.is_empty());
+}
+
+#[test]
+fn test_verify_damaged_end_of_log() {
+    let tmp = tmpdir("verify_log_end");
+    create_database(tmp.path());
+    let log = written_logs(tmp.path()).remove(0);
+    garble(&log, 10);
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(report.log_records, 0);
+    assert_eq!(report.entries, 1000);
+    assert_eq!(report.warnings.len(), 1);
+    assert_eq!(
+        report.warnings[0].file,
+        log.file_name().unwrap().to_str().unwrap()
+    );
+}
+
+#[test]
+fn test_verify_truncated_log() {
+    let tmp = tmpdir("verify_log_truncated");
+    create_database(tmp.path());
+    {
+        let database = open_database(tmp.path(), false);
+        db_put_simple(&database, b"last", b"value");
+    }
+    let log = written_logs(tmp.path()).remove(0);
+    let length = fs::metadata(&log).unwrap().len();
+    OpenOptions::new()
+        .write(true)
+        .open(&log)
+        .unwrap()
+        .set_len(length - 3)
+        .unwrap();
+
+    let report = verify(tmp.path()).unwrap();
+    assert!(report.is_ok(), "{:?}", report);
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.warnings[0].reason.contains("truncated"));
+}
+
+#[test]
+fn test_verify_without_current() {
+    let tmp = tmpdir("verify_current");
+    create_database(tmp.path());
+    fs::remove_file(tmp.path().join("CURRENT")).unwrap();
+    assert!(verify(tmp.path()).is_err());
+}
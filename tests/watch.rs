@@ -0,0 +1,189 @@
+use crate::utils::{db_put_simple, open_database, open_with, tmpdir};
+use leveldb::batch::Writebatch;
+use leveldb::merge::AppendOperator;
+use leveldb::options::{Options, WriteOptions};
+use leveldb::watch::{ChangeEvent, WatchRange};
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn put(key: &[u8], value: &[u8]) -> ChangeEvent {
+    ChangeEvent::Put {
+        key: key.to_vec(),
+        value: value.to_vec(),
+    }
+}
+
+fn delete(key: &[u8]) -> ChangeEvent {
+    ChangeEvent::Delete { key: key.to_vec() }
+}
+
+#[test]
+fn test_prefix_receives_writes() {
+    let tmp = tmpdir("watch_prefix");
+    let database = open_database(tmp.path(), true);
+    let events = database.watch(b"user:");
+    let write_opts = WriteOptions::default();
+
+    db_put_simple(&database, b"user:1", b"alice");
+    db_put_simple(&database, b"other", b"ignored");
+    database.delete(&write_opts, b"user:1").unwrap();
+    let mut batch = Writebatch::new();
+    batch.put(b"user:2", b"bob");
+    batch.put(b"other", b"ignored");
+    batch.delete(b"user:3");
+    database.write(&write_opts, &batch).unwrap();
+
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            put(b"user:1", b"alice"),
+            delete(b"user:1"),
+            put(b"user:2", b"bob"),
+            delete(b"user:3"),
+        ]
+    );
+}
+
+#[test]
+fn test_range_and_transactions() {
+    let tmp = tmpdir("watch_range");
+    let database = open_database(tmp.path(), true);
+    let events = database.watch(WatchRange::range(b"b", Some(b"d")));
+
+    let mut txn = database.transaction();
+    txn.put(b"a", b"1");
+    txn.put(b"c", b"2");
+    txn.put(b"d", b"3");
+    txn.commit(&WriteOptions::default()).unwrap();
+
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(received, vec![put(b"c", b"2")]);
+}
+
+#[test]
+fn test_merges_and_folds() {
+    let tmp = tmpdir("watch_merge");
+    let opts = Options {
+        merge_operator: Some(Arc::new(AppendOperator)),
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let events = database.watch(WatchRange::all());
+    let write_opts = WriteOptions::default();
+
+    database.merge(&write_opts, b"key", b"a").unwrap();
+    database.merge(&write_opts, b"key", b"b").unwrap();
+    database.fold_merges(&write_opts).unwrap();
+
+    // no events for the operand records
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            ChangeEvent::Merge {
+                key: b"key".to_vec(),
+                operand: b"a".to_vec(),
+            },
+            ChangeEvent::Merge {
+                key: b"key".to_vec(),
+                operand: b"b".to_vec(),
+            },
+            put(b"key", b"ab"),
+        ]
+    );
+}
+
+#[test]
+fn test_ttl_writes_and_purges() {
+    let tmp = tmpdir("watch_ttl");
+    let opts = Options {
+        ttl: true,
+        ..Options::default()
+    };
+    let database = open_with(tmp.path(), opts);
+    let events = database.watch(WatchRange::all());
+    let write_opts = WriteOptions::default();
+
+    database
+        .put_with_ttl(&write_opts, b"key", b"value", Duration::from_millis(1))
+        .unwrap();
+    db_put_simple(&database, b"plain", b"value");
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(database.purge_expired(&write_opts).unwrap(), 1);
+
+    // values come without their expiry header
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            put(b"key", b"value"),
+            put(b"plain", b"value"),
+            delete(b"key")
+        ]
+    );
+}
+
+#[test]
+fn test_full_subscriber_is_dropped() {
+    let tmp = tmpdir("watch_full");
+    let database = open_database(tmp.path(), true);
+    let events = database.watch_with_capacity(WatchRange::all(), 2);
+
+    for i in 0..3u8 {
+        db_put_simple(&database, &[i], b"value");
+    }
+    assert_eq!(events.recv().unwrap(), put(&[0], b"value"));
+    assert_eq!(events.recv().unwrap(), put(&[1], b"value"));
+    assert_eq!(events.try_recv(), Err(TryRecvError::Disconnected));
+
+    // the database keeps working without subscribers
+    db_put_simple(&database, b"after", b"value");
+}
+
+#[test]
+fn test_concurrent_writes_keep_key_order() {
+    let tmp = tmpdir("watch_concurrent");
+    let database = Arc::new(open_database(tmp.path(), true));
+    let events = database.watch_with_capacity(WatchRange::all(), 1 << 16);
+
+    let threads: Vec<_> = (0..4u32)
+        .map(|t| {
+            let database = database.clone();
+            thread::spawn(move || {
+                for i in 0..200u32 {
+                    let value = [t.to_be_bytes(), i.to_be_bytes()].concat();
+                    db_put_simple(&database, b"shared", &value);
+                    db_put_simple(&database, &t.to_be_bytes(), &i.to_be_bytes());
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(received.len(), 1600);
+    // every thread's writes arrive in the order it made them, and the
+    // last event for a key matches the stored value
+    let mut last = vec![None; 4];
+    let mut shared = None;
+    for event in received {
+        if let ChangeEvent::Put { key, value } = event {
+            if key == b"shared" {
+                shared = Some(value);
+            } else {
+                let t = key[3] as usize;
+                assert!(last[t] < Some(value.clone()));
+                last[t] = Some(value);
+            }
+        }
+    }
+    assert_eq!(
+        shared,
+        database.get(&Default::default(), b"shared").unwrap()
+    );
+}